@@ -0,0 +1,104 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use muna::protocol::RespValueRef;
+
+/// The values the old encoder supported, from before RESP3. The benchmark
+/// only builds arrays of bulk strings, but the encoder covers them all.
+#[allow(dead_code)]
+enum Resp2Value {
+    Failure(String),
+    String(String),
+    BulkString(Vec<u8>),
+    Array(Vec<Resp2Value>),
+    Int(i64),
+    NullArray,
+    NullBulkString,
+}
+
+impl From<&Resp2Value> for RespValueRef {
+    fn from(value: &Resp2Value) -> Self {
+        match value {
+            Resp2Value::Failure(e) => RespValueRef::Failure(e.clone()),
+            Resp2Value::String(s) => RespValueRef::String(s.clone()),
+            Resp2Value::BulkString(s) => RespValueRef::BulkString(s.clone()),
+            Resp2Value::Array(array) => RespValueRef::Array(array.iter().map(Into::into).collect()),
+            Resp2Value::Int(i) => RespValueRef::Int(*i),
+            Resp2Value::NullArray => RespValueRef::NullArray,
+            Resp2Value::NullBulkString => RespValueRef::NullBulkString,
+        }
+    }
+}
+
+/// The encoder muna used before replies were written into a reusable output
+/// buffer: every value allocated its own `String`, and arrays concatenated
+/// the strings of their children.
+fn encode_with_strings(value: &Resp2Value) -> String {
+    let mut return_value = String::new();
+    match value {
+        Resp2Value::Failure(e) => {
+            return_value.push('-');
+            return_value.push_str(e);
+            return_value.push_str("\r\n");
+        }
+        Resp2Value::String(s) => {
+            return_value.push('+');
+            return_value.push_str(s);
+            return_value.push_str("\r\n");
+        }
+        Resp2Value::BulkString(s) => {
+            return_value.push('$');
+            return_value.push_str(s.len().to_string().as_str());
+            return_value.push_str("\r\n");
+            return_value.push_str(&String::from_utf8_lossy(s));
+            return_value.push_str("\r\n");
+        }
+        Resp2Value::Array(array) => {
+            return_value.push('*');
+            return_value.push_str(array.len().to_string().as_str());
+            return_value.push_str("\r\n");
+            for redis_value in array {
+                return_value.push_str(&encode_with_strings(redis_value));
+            }
+        }
+        Resp2Value::Int(i) => {
+            return_value.push(':');
+            return_value.push_str(i.to_string().as_str());
+            return_value.push_str("\r\n");
+        }
+        Resp2Value::NullArray => return_value.push_str("*-1\r\n"),
+        Resp2Value::NullBulkString => return_value.push_str("$-1\r\n"),
+    }
+    return_value
+}
+
+fn large_reply(elements: usize) -> Resp2Value {
+    Resp2Value::Array(
+        (0..elements)
+            .map(|i| Resp2Value::BulkString(format!("value:{:0>100}", i).into_bytes()))
+            .collect(),
+    )
+}
+
+fn bench_encoders(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode_large_array");
+    for elements in [100, 1_000, 10_000] {
+        let old_reply = large_reply(elements);
+        let reply = RespValueRef::from(&old_reply);
+        let mut out = Vec::new();
+        reply.write_resp_value(&mut out).unwrap();
+        group.throughput(Throughput::Bytes(out.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("string", elements), &old_reply, |b, reply| {
+            b.iter(|| encode_with_strings(black_box(reply)))
+        });
+        group.bench_with_input(BenchmarkId::new("buffer", elements), &reply, |b, reply| {
+            b.iter(|| {
+                out.clear();
+                black_box(reply).write_resp_value(&mut out).unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_encoders);
+criterion_main!(benches);
@@ -0,0 +1,450 @@
+use std::{
+    fmt, fs, io,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use thiserror::Error;
+
+use crate::{
+    glob,
+    protocol::{quote_arg, split_args},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Debug,
+    Verbose,
+    Notice,
+    Warning,
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "debug" => Ok(LogLevel::Debug),
+            "verbose" => Ok(LogLevel::Verbose),
+            "notice" => Ok(LogLevel::Notice),
+            "warning" => Ok(LogLevel::Warning),
+            _ => Err(format!("Invalid log level '{}', expected debug, verbose, notice or warning", s)),
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LogLevel::Debug => "debug",
+            LogLevel::Verbose => "verbose",
+            LogLevel::Notice => "notice",
+            LogLevel::Warning => "warning",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Can't open config file '{}': {1}", .0.display())]
+    Io(PathBuf, #[source] io::Error),
+    #[error("{location} ('{directive}'): {reason}")]
+    Invalid {
+        location: String,
+        directive: String,
+        reason: String,
+    },
+}
+
+/// Server settings, read from a redis.conf style file and overridden by
+/// `--name value` command line options.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub bind: Vec<IpAddr>,
+    pub port: u16,
+    pub maxclients: usize,
+    /// Seconds a client may stay idle before it is disconnected, or 0 to
+    /// never disconnect idle clients.
+    pub timeout: u64,
+    pub loglevel: LogLevel,
+    /// Directory persistence files are written to.
+    pub dir: PathBuf,
+    pub dbfilename: String,
+    /// The file the configuration was loaded from, if any.
+    pub file: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: vec![IpAddr::from([127, 0, 0, 1])],
+            port: 7878,
+            maxclients: 10000,
+            timeout: 0,
+            loglevel: LogLevel::Notice,
+            dir: PathBuf::from("."),
+            dbfilename: "dump.rdb".to_string(),
+            file: None,
+        }
+    }
+}
+
+type Setter = fn(&mut Config, &[String]) -> Result<(), String>;
+type Getter = fn(&Config) -> String;
+
+struct Parameter {
+    name: &'static str,
+    get: Getter,
+    set: Setter,
+    /// Whether `CONFIG SET` may change the parameter while the server runs.
+    mutable: bool,
+}
+
+static PARAMETERS: &[Parameter] = &[
+    Parameter {
+        name: "bind",
+        get: |c| c.bind.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(" "),
+        set: |c, args| {
+            let addrs = args
+                .iter()
+                .flat_map(|arg| arg.split_whitespace())
+                .map(|a| a.parse().map_err(|_| format!("Invalid bind address '{}'", a)))
+                .collect::<Result<Vec<IpAddr>, _>>()?;
+            if addrs.is_empty() {
+                return Err("At least one bind address is required".to_string());
+            }
+            c.bind = addrs;
+            Ok(())
+        },
+        mutable: false,
+    },
+    Parameter {
+        name: "port",
+        get: |c| c.port.to_string(),
+        set: |c, args| {
+            c.port = single(args)?.parse().map_err(|_| "Invalid port".to_string())?;
+            Ok(())
+        },
+        mutable: false,
+    },
+    Parameter {
+        name: "maxclients",
+        get: |c| c.maxclients.to_string(),
+        set: |c, args| {
+            match single(args)?.parse() {
+                Ok(n) if n >= 1 => c.maxclients = n,
+                _ => return Err("Invalid max clients limit".to_string()),
+            }
+            Ok(())
+        },
+        mutable: true,
+    },
+    Parameter {
+        name: "timeout",
+        get: |c| c.timeout.to_string(),
+        set: |c, args| {
+            c.timeout = single(args)?.parse().map_err(|_| "Invalid timeout value".to_string())?;
+            Ok(())
+        },
+        mutable: true,
+    },
+    Parameter {
+        name: "loglevel",
+        get: |c| c.loglevel.to_string(),
+        set: |c, args| {
+            c.loglevel = single(args)?.parse()?;
+            Ok(())
+        },
+        mutable: true,
+    },
+    Parameter {
+        name: "dir",
+        get: |c| c.dir.display().to_string(),
+        set: |c, args| {
+            let dir = single(args)?;
+            if !Path::new(dir).is_dir() {
+                return Err(format!("No such directory '{}'", dir));
+            }
+            c.dir = PathBuf::from(dir);
+            Ok(())
+        },
+        mutable: true,
+    },
+    Parameter {
+        name: "dbfilename",
+        get: |c| c.dbfilename.clone(),
+        set: |c, args| {
+            let name = single(args)?;
+            if name.is_empty() || name.contains('/') {
+                return Err("dbfilename can't be a path, just a filename".to_string());
+            }
+            c.dbfilename = name.to_string();
+            Ok(())
+        },
+        mutable: true,
+    },
+];
+
+fn single(args: &[String]) -> Result<&str, String> {
+    match args {
+        [value] => Ok(value),
+        _ => Err("wrong number of arguments".to_string()),
+    }
+}
+
+fn lookup(name: &str) -> Option<&'static Parameter> {
+    PARAMETERS.iter().find(|p| name.eq_ignore_ascii_case(p.name))
+}
+
+impl Config {
+    /// Builds the configuration from the process arguments, not counting the
+    /// program name: an optional config file followed by `--name value`
+    /// overrides, e.g. `muna muna.conf --port 7000 --bind 127.0.0.1 ::1`.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self, ConfigError> {
+        let mut args = args.into_iter().peekable();
+        let mut config = Config::default();
+
+        if let Some(path) = args.next_if(|arg| !arg.starts_with("--")) {
+            let path = PathBuf::from(path);
+            let contents = fs::read_to_string(&path).map_err(|e| ConfigError::Io(path.clone(), e))?;
+            config.load(&contents)?;
+            config.file = Some(path);
+        }
+
+        while let Some(arg) = args.next() {
+            let mut values = Vec::new();
+            while let Some(value) = args.next_if(|arg| !arg.starts_with("--")) {
+                values.push(value);
+            }
+            let invalid = |reason: String| ConfigError::Invalid {
+                location: "command line".to_string(),
+                directive: std::iter::once(arg.clone()).chain(values.iter().cloned()).collect::<Vec<_>>().join(" "),
+                reason,
+            };
+            let Some(name) = arg.strip_prefix("--") else {
+                return Err(invalid("Expected an option starting with '--'".to_string()));
+            };
+            config.set(name, &values).map_err(invalid)?;
+        }
+
+        Ok(config)
+    }
+
+    /// Applies every directive in a config file, in order. Blank lines and
+    /// lines starting with `#` are ignored.
+    pub fn load(&mut self, contents: &str) -> Result<(), ConfigError> {
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = |reason: String| ConfigError::Invalid {
+                location: format!("line {}", number + 1),
+                directive: line.to_string(),
+                reason,
+            };
+            let args = split_args(line.as_bytes()).map_err(|e| invalid(e.to_string()))?;
+            let mut args = args.into_iter().map(|arg| String::from_utf8_lossy(&arg).to_string());
+            let name = args.next().unwrap_or_default();
+            self.set(&name, &args.collect::<Vec<_>>()).map_err(invalid)?;
+        }
+        Ok(())
+    }
+
+    /// Changes a single parameter, validating its value.
+    pub fn set(&mut self, name: &str, args: &[String]) -> Result<(), String> {
+        let Some(parameter) = lookup(name) else {
+            return Err("Bad directive or wrong number of arguments".to_string());
+        };
+        (parameter.set)(self, args)
+    }
+
+    /// Returns the current value of a parameter as it would be written in a
+    /// config file.
+    pub fn get(&self, name: &str) -> Option<String> {
+        lookup(name).map(|parameter| (parameter.get)(self))
+    }
+
+    /// Returns every parameter whose name matches the glob `pattern`, along
+    /// with its current value.
+    pub fn get_matching(&self, pattern: &[u8]) -> Vec<(&'static str, String)> {
+        let pattern = pattern.to_ascii_lowercase();
+        PARAMETERS
+            .iter()
+            .filter(|p| glob::matches(&pattern, p.name.as_bytes()))
+            .map(|p| (p.name, (p.get)(self)))
+            .collect()
+    }
+
+    /// Writes the running configuration back to the file it was loaded from.
+    pub fn rewrite(&self) -> io::Result<()> {
+        let Some(path) = &self.file else {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no config file"));
+        };
+        let original = fs::read_to_string(path)?;
+
+        // Write to a temporary file first so a failed write can't leave a
+        // truncated config behind.
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, self.rewrite_contents(&original))?;
+        fs::rename(&tmp, path)
+    }
+
+    /// Produces the new contents of a config file. Comments, blank lines and
+    /// unknown lines are kept as they are, the first line setting each
+    /// parameter is updated in place and any repeated lines are dropped.
+    /// Parameters the file didn't mention are appended if they differ from
+    /// their default.
+    fn rewrite_contents(&self, original: &str) -> String {
+        let defaults = Config::default();
+        let mut written = Vec::new();
+        let mut lines = Vec::new();
+
+        for line in original.lines() {
+            let directive = line.split_whitespace().next().unwrap_or_default();
+            let Some(parameter) = (!directive.starts_with('#')).then(|| lookup(directive)).flatten() else {
+                lines.push(line.to_string());
+                continue;
+            };
+            if !written.contains(&parameter.name) {
+                written.push(parameter.name);
+                lines.push(format_directive(parameter.name, &(parameter.get)(self)));
+            }
+        }
+
+        let missing: Vec<&Parameter> = PARAMETERS
+            .iter()
+            .filter(|p| !written.contains(&p.name) && (p.get)(self) != (p.get)(&defaults))
+            .collect();
+        if !missing.is_empty() {
+            lines.push("# Generated by CONFIG REWRITE".to_string());
+            lines.extend(missing.iter().map(|p| format_directive(p.name, &(p.get)(self))));
+        }
+
+        let mut contents = lines.join("\n");
+        contents.push('\n');
+        contents
+    }
+}
+
+/// Whether `CONFIG SET` may change a parameter at runtime, or `None` if the
+/// parameter doesn't exist.
+pub fn is_mutable(name: &str) -> Option<bool> {
+    lookup(name).map(|parameter| parameter.mutable)
+}
+
+/// Formats a config file line, quoting the value if it wouldn't survive
+/// being split back into arguments.
+fn format_directive(name: &str, value: &str) -> String {
+    let plain = !value.is_empty()
+        && value.bytes().all(|b| b.is_ascii_graphic() && !matches!(b, b'"' | b'\'' | b'\\'));
+    if plain {
+        format!("{} {}", name, value)
+    } else {
+        format!("{} {}", name, quote_arg(value.as_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_load_file_contents() {
+        let mut config = Config::default();
+        config
+            .load("# a comment\n\nport 6380\nbind 127.0.0.1 ::1\nMAXCLIENTS 20\n  loglevel \"debug\"\n")
+            .unwrap();
+
+        assert_eq!(config.port, 6380);
+        assert_eq!(config.bind, vec![IpAddr::from([127, 0, 0, 1]), "::1".parse().unwrap()]);
+        assert_eq!(config.maxclients, 20);
+        assert_eq!(config.loglevel, LogLevel::Debug);
+        assert_eq!(config.get("bind").unwrap(), "127.0.0.1 ::1");
+    }
+
+    #[test]
+    fn test_command_line_overrides_file() {
+        let path = std::env::temp_dir().join(format!("muna-config-test-{}.conf", std::process::id()));
+        fs::write(&path, "port 6380\ntimeout 10\n").unwrap();
+
+        let mut cli = vec![path.display().to_string()];
+        cli.extend(args("--port 7000 --bind 0.0.0.0 ::"));
+        let config = Config::from_args(cli).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.port, 7000);
+        assert_eq!(config.timeout, 10);
+        assert_eq!(config.bind, vec![IpAddr::from([0, 0, 0, 0]), "::".parse().unwrap()]);
+        assert_eq!(config.file, Some(path));
+    }
+
+    #[test]
+    fn test_get_matching() {
+        let config = Config::default();
+        assert_eq!(config.get_matching(b"port"), vec![("port", "7878".to_string())]);
+        assert_eq!(
+            config.get_matching(b"*MAX*"),
+            vec![("maxclients", "10000".to_string())]
+        );
+        assert!(config.get_matching(b"nothing*").is_empty());
+    }
+
+    #[test]
+    fn test_rewrite_keeps_comments() {
+        let original = "# Muna\n\nport 6380\n# limits\nmaxclients 5\nmaxclients 6\nunknown thing\n";
+        let config = Config {
+            port: 6380,
+            maxclients: 100,
+            loglevel: LogLevel::Verbose,
+            dir: PathBuf::from("/tmp/with space"),
+            ..Config::default()
+        };
+
+        assert_eq!(
+            config.rewrite_contents(original),
+            "# Muna\n\nport 6380\n# limits\nmaxclients 100\nunknown thing\n\
+             # Generated by CONFIG REWRITE\nloglevel verbose\ndir \"/tmp/with space\"\n"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_round_trip() {
+        let config = Config {
+            dbfilename: "a\x01b 'q' \"q\" \\ é\n".to_string(),
+            ..Config::default()
+        };
+
+        let mut reloaded = Config::default();
+        reloaded.load(&config.rewrite_contents("")).unwrap();
+        assert_eq!(reloaded.dbfilename, config.dbfilename);
+    }
+
+    #[test]
+    fn test_invalid_settings() {
+        let err = Config::default().load("port 6380\nport http\n").unwrap_err();
+        assert_eq!(err.to_string(), "line 2 ('port http'): Invalid port");
+
+        let err = Config::from_args(args("--maxclients 0")).unwrap_err();
+        assert_eq!(err.to_string(), "command line ('--maxclients 0'): Invalid max clients limit");
+
+        let err = Config::from_args(args("--frobnicate yes")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "command line ('--frobnicate yes'): Bad directive or wrong number of arguments"
+        );
+
+        assert!(matches!(
+            Config::from_args(args("/does/not/exist.conf")),
+            Err(ConfigError::Io(..))
+        ));
+    }
+}
@@ -0,0 +1,115 @@
+/// Matches `string` against a Redis style glob `pattern`, supporting `*`,
+/// `?`, character classes such as `[a-z]` or `[^abc]`, and `\` escapes.
+pub fn matches(pattern: &[u8], string: &[u8]) -> bool {
+    let (mut p, mut s) = (0, 0);
+    // Where to resume after the last `*`: the pattern just past it and the
+    // position in `string` it has swallowed up to. Only the last star needs
+    // remembering, which keeps matching O(pattern * string).
+    let mut star: Option<(usize, usize)> = None;
+
+    while s < string.len() {
+        if let Some(&c) = pattern.get(p) {
+            // How much of the pattern the next byte of `string` consumes.
+            let consumed = match c {
+                b'*' => {
+                    p += 1;
+                    star = Some((p, s));
+                    continue;
+                }
+                b'?' => Some(1),
+                b'[' => {
+                    let (matched, rest) = match_class(&pattern[p + 1..], string[s]);
+                    matched.then(|| pattern.len() - rest.len() - p)
+                }
+                b'\\' if p + 1 < pattern.len() => (pattern[p + 1] == string[s]).then_some(2),
+                _ => (c == string[s]).then_some(1),
+            };
+            if let Some(consumed) = consumed {
+                p += consumed;
+                s += 1;
+                continue;
+            }
+        }
+
+        let Some((star_p, star_s)) = star else {
+            return false;
+        };
+        p = star_p;
+        s = star_s + 1;
+        star = Some((star_p, s));
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// Checks `c` against the class starting right after a `[`, returning whether
+/// it matched and the pattern left after the closing `]`.
+fn match_class(mut pattern: &[u8], c: u8) -> (bool, &[u8]) {
+    let negate = pattern.first() == Some(&b'^');
+    if negate {
+        pattern = &pattern[1..];
+    }
+
+    let mut matched = false;
+    loop {
+        match pattern {
+            [] => break,
+            [b']', rest @ ..] => {
+                pattern = rest;
+                break;
+            }
+            [b'\\', escaped, rest @ ..] => {
+                matched |= *escaped == c;
+                pattern = rest;
+            }
+            [start, b'-', end, rest @ ..] if *end != b']' => {
+                let (low, high) = if start <= end { (*start, *end) } else { (*end, *start) };
+                matched |= low <= c && c <= high;
+                pattern = rest;
+            }
+            [other, rest @ ..] => {
+                matched |= *other == c;
+                pattern = rest;
+            }
+        }
+    }
+
+    (matched != negate, pattern)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_literal_and_wildcards() {
+        assert!(matches(b"key", b"key"));
+        assert!(!matches(b"key", b"keys"));
+        assert!(matches(b"*", b""));
+        assert!(matches(b"user:*", b"user:42"));
+        assert!(matches(b"h?llo", b"hello"));
+        assert!(!matches(b"h?llo", b"hllo"));
+        assert!(matches(b"*max*", b"maxclients"));
+    }
+
+    #[test]
+    fn test_classes_and_escapes() {
+        assert!(matches(b"h[ae]llo", b"hallo"));
+        assert!(!matches(b"h[ae]llo", b"hillo"));
+        assert!(matches(b"h[^e]llo", b"hallo"));
+        assert!(!matches(b"h[^e]llo", b"hello"));
+        assert!(matches(b"h[a-b]llo", b"hbllo"));
+        assert!(matches(b"\\*", b"*"));
+        assert!(!matches(b"\\*", b"a"));
+    }
+
+    #[test]
+    fn test_many_stars_match_in_linear_passes() {
+        let pattern = [b"*a".repeat(12), b"b".to_vec()].concat();
+        let string = b"a".repeat(30);
+        let started = std::time::Instant::now();
+        assert!(!matches(&pattern, &string));
+        assert!(matches(&pattern, &[string.as_slice(), b"b"].concat()));
+        assert!(started.elapsed() < std::time::Duration::from_millis(100));
+    }
+}
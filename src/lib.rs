@@ -0,0 +1,12 @@
+pub mod config;
+pub mod server;
+pub mod glob;
+pub mod operations;
+pub mod protocol;
+pub mod record;
+pub mod repository;
+pub mod request;
+pub mod session;
+pub mod sorted_set;
+pub mod stats;
+pub mod stream;
@@ -1,28 +1,33 @@
-#![feature(let_else)]
+use std::{env, process};
 
-mod server;
-mod operations;
-mod protocol;
-mod record;
-mod repository;
-mod request;
+use muna::{config::Config, server::Server};
 
-use std::net::TcpListener;
+const USAGE: &str = "Usage: muna [/path/to/muna.conf] [--option value ...]
 
-use crate::server::handle_connection;
+Examples:
+       muna
+       muna /etc/muna/muna.conf
+       muna --port 7777 --bind 127.0.0.1 ::1
+       muna /etc/muna/muna.conf --loglevel verbose";
 
 fn main() {
-    let listener = TcpListener::bind("127.0.0.1:7878").unwrap();
-    for stream in listener.incoming() {
-        match stream {
-            Ok(stream) => {
-                println!("New connection: {}", stream.peer_addr().unwrap());
-                handle_connection(stream);
-            }
-            Err(e) => {
-                println!("Error: {}", e)
-            }
-        }
+    let args: Vec<String> = env::args().skip(1).collect();
+    if matches!(args.first().map(String::as_str), Some("-h" | "--help")) {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let config = Config::from_args(args).unwrap_or_else(|e| {
+        eprintln!("Fatal config error: {}", e);
+        process::exit(1);
+    });
+
+    let mut server = Server::new(config).unwrap_or_else(|e| {
+        eprintln!("Failed to start server: {}", e);
+        process::exit(1);
+    });
+    if let Err(e) = server.run() {
+        eprintln!("Server stopped: {}", e);
+        process::exit(1);
     }
-    drop(listener);
 }
@@ -1,26 +1,245 @@
-use crate::{repository::Repository, request::Request};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use thiserror::Error;
+
+use crate::{
+    config::Config,
+    repository::Repository,
+    request::Request,
+    session::{Block, Session},
+    stats::Stats,
+};
+
+mod config;
+mod connection;
 mod hash;
 mod string;
 mod key;
+mod list;
 mod server;
+mod set;
+mod sorted_set;
+mod stream;
 
 use self::{
-    hash::{hget, hset},
-    string::{get, set},
-    key::expire,
-    server::flush_all
+    config::config,
+    connection::hello,
+    hash::{
+        hdel, hexists, hexpire, hexpireat, hget, hgetall, hincrby, hincrbyfloat, hkeys, hlen, hmget,
+        hpersist, hpexpire, hpexpireat, hpttl, hrandfield, hset, hsetnx, hstrlen, httl, hvals
+    },
+    string::{
+        append, decr, decrby, get, getdel, getex, getrange, incr, incrby, incrbyfloat, mget, mset,
+        msetnx, set, setnx, setrange, strlen
+    },
+    key::{expire, keys, persist, pttl, ttl},
+    list::{
+        blmove, blmpop, blpop, brpop, lindex, linsert, llen, lmove, lmpop, lpop, lpos, lpush,
+        lpushx, lrange, lrem, lset, ltrim, rpop, rpush, rpushx
+    },
+    server::{flush_all, info, ping},
+    set::{
+        sadd, scard, sdiff, sdiffstore, sinter, sintercard, sinterstore, sismember, smembers,
+        smismember, smove, spop, srandmember, srem, sunion, sunionstore
+    },
+    sorted_set::{
+        bzmpop, bzpopmax, bzpopmin, zadd, zcard, zcount, zincrby, zinterstore, zmpop, zpopmax,
+        zpopmin, zrange, zrank, zrem, zrevrank, zscore, zunionstore
+    },
+    stream::{
+        xack, xadd, xautoclaim, xclaim, xdel, xgroup, xinfo, xlen, xpending, xrange, xread,
+        xreadgroup, xrevrange, xtrim
+    }
 };
 
-type OperationHandler = fn(repo: &mut Repository, request: &Request) -> OperationResult;
+/// Everything a command can reach while it runs: the shared keyspace, the
+/// state of the connection that sent it, and the server's configuration and
+/// statistics.
+pub struct Context<'a> {
+    pub repo: &'a mut Repository,
+    pub session: &'a mut Session,
+    pub config: &'a mut Config,
+    pub stats: &'a mut Stats,
+}
+
+impl Context<'_> {
+    /// Parks the client until one of `keys` receives data or `deadline`
+    /// passes, at which point the server runs the command again or replies
+    /// with a null array. The returned reply is never sent.
+    pub fn block(&mut self, keys: &[Vec<u8>], deadline: Option<Instant>) -> OperationResult {
+        self.session.block = Some(Block {
+            keys: keys.to_vec(),
+            deadline,
+            retry: None,
+        });
+        OperationResult::NullArray
+    }
+
+    /// Like `block`, but runs `retry` on wakeup instead of the command that
+    /// blocked.
+    pub fn block_with_retry(
+        &mut self,
+        keys: &[Vec<u8>],
+        deadline: Option<Instant>,
+        retry: Request,
+    ) -> OperationResult {
+        self.block(keys, deadline);
+        if let Some(block) = &mut self.session.block {
+            block.retry = Some(retry);
+        }
+        OperationResult::NullArray
+    }
+}
+
+type OperationHandler = fn(ctx: &mut Context, request: &Request) -> OperationResult;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum OperationResult {
     Ok,
-    StringRes(String),
+    /// A status reply other than `OK`, such as `PONG`.
+    Status(String),
+    StringRes(Vec<u8>),
+    /// Human readable text, sent as a verbatim string to clients that
+    /// understand them and as a bulk string to everyone else.
+    Verbatim(String),
     Error(String),
     Int(i64),
     Nil,
+    Array(Vec<OperationResult>),
+    NullArray,
+    /// Key/value pairs, sent as a map to RESP3 clients and as a flat array to
+    /// RESP2 clients.
+    Map(Vec<(OperationResult, OperationResult)>),
+    /// Unordered unique values, sent as an array to RESP2 clients.
+    Set(Vec<OperationResult>),
+    /// A floating point number, sent as a bulk string to RESP2 clients.
+    Double(f64),
+}
+
+/// Errors a command can fail with. Each message starts with the error code
+/// Redis uses for it, which is what client libraries match on.
+#[derive(Error, Debug, PartialEq, Clone)]
+pub enum OperationError {
+    #[error("ERR unknown command '{0}'")]
+    UnknownCommand(String),
+    #[error("ERR unknown subcommand '{0}'")]
+    UnknownSubcommand(String),
+    #[error("ERR wrong number of arguments for '{0}' command")]
+    WrongArity(&'static str),
+    #[error("WRONGTYPE Operation against a key holding the wrong kind of value")]
+    WrongType,
+    #[error("ERR value is not an integer or out of range")]
+    NotAnInteger,
+    #[error("ERR value is not a valid float")]
+    NotAFloat,
+    #[error("ERR hash value is not an integer")]
+    HashValueNotAnInteger,
+    #[error("ERR hash value is not a float")]
+    HashValueNotAFloat,
+    #[error("ERR increment or decrement would overflow")]
+    Overflow,
+    #[error("ERR increment would produce NaN or Infinity")]
+    NanOrInfinity,
+    #[error("ERR value is out of range, must be positive")]
+    MustBePositive,
+    #[error("ERR value is out of range")]
+    OutOfRange,
+    #[error("ERR syntax error")]
+    Syntax,
+    #[error("ERR timeout is not a float or out of range")]
+    InvalidTimeout,
+    #[error("ERR timeout is negative")]
+    NegativeTimeout,
+    #[error("ERR timeout is not an integer or out of range")]
+    TimeoutNotInteger,
+    #[error("ERR numkeys should be greater than 0")]
+    NumKeys,
+    #[error("ERR count should be greater than 0")]
+    CountNotPositive,
+    #[error("ERR LIMIT can't be negative")]
+    NegativeLimit,
+    #[error("ERR no such key")]
+    NoSuchKey,
+    #[error("ERR XX and NX options at the same time are not compatible")]
+    XxAndNx,
+    #[error("ERR GT, LT, and/or NX options at the same time are not compatible")]
+    GtLtNx,
+    #[error("ERR INCR option supports a single increment-element pair")]
+    IncrPair,
+    #[error("ERR resulting score is not a number (NaN)")]
+    NanScore,
+    #[error("ERR min or max is not a float")]
+    MinMaxNotFloat,
+    #[error("ERR min or max not valid string range item")]
+    MinMaxNotString,
+    #[error("ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX")]
+    LimitWithoutBy,
+    #[error("ERR syntax error, WITHSCORES not supported in combination with BYLEX")]
+    WithScoresByLex,
+    #[error("ERR weight value is not a float")]
+    WeightNotAFloat,
+    #[error("ERR at least 1 input key is needed for '{0}' command")]
+    NoInputKeys(&'static str),
+    #[error("ERR Invalid stream ID specified as stream command argument")]
+    InvalidStreamId,
+    #[error("ERR The ID specified in XADD must be greater than 0-0")]
+    StreamIdZero,
+    #[error("ERR The ID specified in XADD is equal or smaller than the target stream top item")]
+    StreamIdTooSmall,
+    #[error("ERR The stream has exhausted the last possible ID, unable to add more items")]
+    StreamExhausted,
+    #[error("ERR The MAXLEN argument must be >= 0.")]
+    StreamMaxLen,
+    #[error("ERR The LIMIT argument must be >= 0.")]
+    StreamLimit,
+    #[error("ERR syntax error, LIMIT cannot be used without the special ~ option")]
+    LimitWithoutApprox,
+    #[error("ERR Unbalanced '{0}' list of streams: for each stream key an ID must be specified.")]
+    UnbalancedStreams(&'static str),
+    #[error("NOGROUP No such key '{0}' or consumer group '{1}'")]
+    NoGroup(String, String),
+    #[error("BUSYGROUP Consumer Group name already exists")]
+    BusyGroup,
+    #[error("ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.")]
+    XgroupNoKey,
+    #[error("ERR index out of range")]
+    IndexOutOfRange,
+    #[error("ERR RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the last match")]
+    ZeroRank,
+    #[error("ERR COUNT can't be negative")]
+    NegativeCount,
+    #[error("ERR MAXLEN can't be negative")]
+    NegativeMaxLen,
+    #[error("ERR Mandatory argument FIELDS is missing or not at the right position")]
+    MissingFields,
+    #[error("ERR Parameter `numFields` should be greater than 0 and match the number of arguments")]
+    NumFields,
+    #[error("ERR offset is out of range")]
+    OffsetOutOfRange,
+    #[error("ERR string exceeds maximum allowed size (proto-max-bulk-len)")]
+    StringTooLong,
+    #[error("ERR invalid expire time in '{0}' command")]
+    InvalidExpireTime(&'static str),
+    #[error("ERR Protocol version is not an integer or out of range")]
+    InvalidProtocolVersion,
+    #[error("NOPROTO unsupported protocol version")]
+    NoProto,
+    #[error("WRONGPASS invalid username-password pair or user is disabled.")]
+    WrongPass,
+    #[error("ERR Unknown option or number of arguments for CONFIG SET - '{0}'")]
+    UnknownConfig(String),
+    #[error("ERR CONFIG SET failed (possibly related to argument '{0}') - {1}")]
+    ConfigSet(String, String),
+    #[error("ERR The server is running without a config file")]
+    NoConfigFile,
+    #[error("ERR Rewriting config file: {0}")]
+    ConfigRewrite(String),
+}
+
+impl From<OperationError> for OperationResult {
+    fn from(e: OperationError) -> Self {
+        OperationResult::Error(e.to_string())
+    }
 }
 
 pub struct Operation {
@@ -30,15 +249,15 @@ pub struct Operation {
 }
 
 impl Operation {
-    pub fn execute(&self, repo: &mut Repository, request: &Request) -> OperationResult {
+    pub fn execute(&self, ctx: &mut Context, request: &Request) -> OperationResult {
         if !is_valid_arity(self.arity.into(), request.arity()) {
-            return OperationResult::Error("Wrong number of arguments".to_string());
+            return OperationError::WrongArity(self.name).into();
         }
-        (self.handler)(repo, request)
+        (self.handler)(ctx, request)
     }
 }
 
-pub fn commands_handler(_: &mut Repository, _: &Request) -> OperationResult {
+pub fn commands_handler(_: &mut Context, _: &Request) -> OperationResult {
     OperationResult::Ok
 }
 
@@ -57,6 +276,81 @@ static OPERATIONS: &[Operation] = &[
         handler: set,
         arity: -3,
     },
+    Operation {
+        name: "mget",
+        handler: mget,
+        arity: -2
+    },
+    Operation {
+        name: "mset",
+        handler: mset,
+        arity: -3
+    },
+    Operation {
+        name: "getdel",
+        handler: getdel,
+        arity: 2
+    },
+    Operation {
+        name: "getex",
+        handler: getex,
+        arity: -2
+    },
+    Operation {
+        name: "setnx",
+        handler: setnx,
+        arity: 3
+    },
+    Operation {
+        name: "msetnx",
+        handler: msetnx,
+        arity: -3
+    },
+    Operation {
+        name: "append",
+        handler: append,
+        arity: 3
+    },
+    Operation {
+        name: "strlen",
+        handler: strlen,
+        arity: 2
+    },
+    Operation {
+        name: "getrange",
+        handler: getrange,
+        arity: 4
+    },
+    Operation {
+        name: "setrange",
+        handler: setrange,
+        arity: 4
+    },
+    Operation {
+        name: "incr",
+        handler: incr,
+        arity: 2
+    },
+    Operation {
+        name: "decr",
+        handler: decr,
+        arity: 2
+    },
+    Operation {
+        name: "incrby",
+        handler: incrby,
+        arity: 3
+    },
+    Operation {
+        name: "decrby",
+        handler: decrby,
+        arity: 3
+    },
+    Operation {
+        name: "incrbyfloat",
+        handler: incrbyfloat,
+        arity: 3
+    },
     Operation {
         name: "hget",
         handler: hget,
@@ -67,6 +361,436 @@ static OPERATIONS: &[Operation] = &[
         handler: hset,
         arity: -4,
     },
+    Operation {
+        name: "hsetnx",
+        handler: hsetnx,
+        arity: 4
+    },
+    Operation {
+        name: "hdel",
+        handler: hdel,
+        arity: -3
+    },
+    Operation {
+        name: "hgetall",
+        handler: hgetall,
+        arity: 2
+    },
+    Operation {
+        name: "hkeys",
+        handler: hkeys,
+        arity: 2
+    },
+    Operation {
+        name: "hvals",
+        handler: hvals,
+        arity: 2
+    },
+    Operation {
+        name: "hlen",
+        handler: hlen,
+        arity: 2
+    },
+    Operation {
+        name: "hexists",
+        handler: hexists,
+        arity: 3
+    },
+    Operation {
+        name: "hmget",
+        handler: hmget,
+        arity: -3
+    },
+    Operation {
+        name: "hincrby",
+        handler: hincrby,
+        arity: 4
+    },
+    Operation {
+        name: "hincrbyfloat",
+        handler: hincrbyfloat,
+        arity: 4
+    },
+    Operation {
+        name: "hstrlen",
+        handler: hstrlen,
+        arity: 3
+    },
+    Operation {
+        name: "hrandfield",
+        handler: hrandfield,
+        arity: -2
+    },
+    Operation {
+        name: "hexpire",
+        handler: hexpire,
+        arity: -6
+    },
+    Operation {
+        name: "hpexpire",
+        handler: hpexpire,
+        arity: -6
+    },
+    Operation {
+        name: "hexpireat",
+        handler: hexpireat,
+        arity: -6
+    },
+    Operation {
+        name: "hpexpireat",
+        handler: hpexpireat,
+        arity: -6
+    },
+    Operation {
+        name: "httl",
+        handler: httl,
+        arity: -5
+    },
+    Operation {
+        name: "hpttl",
+        handler: hpttl,
+        arity: -5
+    },
+    Operation {
+        name: "hpersist",
+        handler: hpersist,
+        arity: -5
+    },
+    Operation {
+        name: "lpush",
+        handler: lpush,
+        arity: -3
+    },
+    Operation {
+        name: "rpush",
+        handler: rpush,
+        arity: -3
+    },
+    Operation {
+        name: "lpushx",
+        handler: lpushx,
+        arity: -3
+    },
+    Operation {
+        name: "rpushx",
+        handler: rpushx,
+        arity: -3
+    },
+    Operation {
+        name: "lpop",
+        handler: lpop,
+        arity: -2
+    },
+    Operation {
+        name: "rpop",
+        handler: rpop,
+        arity: -2
+    },
+    Operation {
+        name: "llen",
+        handler: llen,
+        arity: 2
+    },
+    Operation {
+        name: "lrange",
+        handler: lrange,
+        arity: 4
+    },
+    Operation {
+        name: "lindex",
+        handler: lindex,
+        arity: 3
+    },
+    Operation {
+        name: "lset",
+        handler: lset,
+        arity: 4
+    },
+    Operation {
+        name: "linsert",
+        handler: linsert,
+        arity: 5
+    },
+    Operation {
+        name: "lrem",
+        handler: lrem,
+        arity: 4
+    },
+    Operation {
+        name: "ltrim",
+        handler: ltrim,
+        arity: 4
+    },
+    Operation {
+        name: "lpos",
+        handler: lpos,
+        arity: -3
+    },
+    Operation {
+        name: "lmove",
+        handler: lmove,
+        arity: 5
+    },
+    Operation {
+        name: "lmpop",
+        handler: lmpop,
+        arity: -4
+    },
+    Operation {
+        name: "blpop",
+        handler: blpop,
+        arity: -3
+    },
+    Operation {
+        name: "brpop",
+        handler: brpop,
+        arity: -3
+    },
+    Operation {
+        name: "blmove",
+        handler: blmove,
+        arity: 6
+    },
+    Operation {
+        name: "blmpop",
+        handler: blmpop,
+        arity: -5
+    },
+    Operation {
+        name: "sadd",
+        handler: sadd,
+        arity: -3
+    },
+    Operation {
+        name: "srem",
+        handler: srem,
+        arity: -3
+    },
+    Operation {
+        name: "smembers",
+        handler: smembers,
+        arity: 2
+    },
+    Operation {
+        name: "sismember",
+        handler: sismember,
+        arity: 3
+    },
+    Operation {
+        name: "smismember",
+        handler: smismember,
+        arity: -3
+    },
+    Operation {
+        name: "scard",
+        handler: scard,
+        arity: 2
+    },
+    Operation {
+        name: "spop",
+        handler: spop,
+        arity: -2
+    },
+    Operation {
+        name: "srandmember",
+        handler: srandmember,
+        arity: -2
+    },
+    Operation {
+        name: "smove",
+        handler: smove,
+        arity: 4
+    },
+    Operation {
+        name: "sinter",
+        handler: sinter,
+        arity: -2
+    },
+    Operation {
+        name: "sunion",
+        handler: sunion,
+        arity: -2
+    },
+    Operation {
+        name: "sdiff",
+        handler: sdiff,
+        arity: -2
+    },
+    Operation {
+        name: "sinterstore",
+        handler: sinterstore,
+        arity: -3
+    },
+    Operation {
+        name: "sunionstore",
+        handler: sunionstore,
+        arity: -3
+    },
+    Operation {
+        name: "sdiffstore",
+        handler: sdiffstore,
+        arity: -3
+    },
+    Operation {
+        name: "sintercard",
+        handler: sintercard,
+        arity: -3
+    },
+    Operation {
+        name: "zadd",
+        handler: zadd,
+        arity: -4
+    },
+    Operation {
+        name: "zincrby",
+        handler: zincrby,
+        arity: 4
+    },
+    Operation {
+        name: "zrem",
+        handler: zrem,
+        arity: -3
+    },
+    Operation {
+        name: "zcard",
+        handler: zcard,
+        arity: 2
+    },
+    Operation {
+        name: "zscore",
+        handler: zscore,
+        arity: 3
+    },
+    Operation {
+        name: "zrank",
+        handler: zrank,
+        arity: -3
+    },
+    Operation {
+        name: "zrevrank",
+        handler: zrevrank,
+        arity: -3
+    },
+    Operation {
+        name: "zcount",
+        handler: zcount,
+        arity: 4
+    },
+    Operation {
+        name: "zrange",
+        handler: zrange,
+        arity: -4
+    },
+    Operation {
+        name: "zpopmin",
+        handler: zpopmin,
+        arity: -2
+    },
+    Operation {
+        name: "zpopmax",
+        handler: zpopmax,
+        arity: -2
+    },
+    Operation {
+        name: "zunionstore",
+        handler: zunionstore,
+        arity: -4
+    },
+    Operation {
+        name: "zinterstore",
+        handler: zinterstore,
+        arity: -4
+    },
+    Operation {
+        name: "zmpop",
+        handler: zmpop,
+        arity: -4
+    },
+    Operation {
+        name: "bzpopmin",
+        handler: bzpopmin,
+        arity: -3
+    },
+    Operation {
+        name: "bzpopmax",
+        handler: bzpopmax,
+        arity: -3
+    },
+    Operation {
+        name: "bzmpop",
+        handler: bzmpop,
+        arity: -5
+    },
+    Operation {
+        name: "xadd",
+        handler: xadd,
+        arity: -5
+    },
+    Operation {
+        name: "xlen",
+        handler: xlen,
+        arity: 2
+    },
+    Operation {
+        name: "xrange",
+        handler: xrange,
+        arity: -4
+    },
+    Operation {
+        name: "xrevrange",
+        handler: xrevrange,
+        arity: -4
+    },
+    Operation {
+        name: "xdel",
+        handler: xdel,
+        arity: -3
+    },
+    Operation {
+        name: "xtrim",
+        handler: xtrim,
+        arity: -4
+    },
+    Operation {
+        name: "xread",
+        handler: xread,
+        arity: -4
+    },
+    Operation {
+        name: "xgroup",
+        handler: xgroup,
+        arity: -2
+    },
+    Operation {
+        name: "xreadgroup",
+        handler: xreadgroup,
+        arity: -7
+    },
+    Operation {
+        name: "xack",
+        handler: xack,
+        arity: -4
+    },
+    Operation {
+        name: "xpending",
+        handler: xpending,
+        arity: -3
+    },
+    Operation {
+        name: "xclaim",
+        handler: xclaim,
+        arity: -6
+    },
+    Operation {
+        name: "xautoclaim",
+        handler: xautoclaim,
+        arity: -6
+    },
+    Operation {
+        name: "xinfo",
+        handler: xinfo,
+        arity: -2
+    },
     Operation {
         name: "command",
         handler: commands_handler,
@@ -77,17 +801,129 @@ static OPERATIONS: &[Operation] = &[
         handler: expire,
         arity: 3
     },
+    Operation {
+        name: "ttl",
+        handler: ttl,
+        arity: 2
+    },
+    Operation {
+        name: "pttl",
+        handler: pttl,
+        arity: 2
+    },
+    Operation {
+        name: "persist",
+        handler: persist,
+        arity: 2
+    },
+    Operation {
+        name: "keys",
+        handler: keys,
+        arity: 2
+    },
     Operation {
         name: "flushall",
         handler: flush_all,
         arity: 1
     },
+    Operation {
+        name: "ping",
+        handler: ping,
+        arity: -1
+    },
+    Operation {
+        name: "hello",
+        handler: hello,
+        arity: -1
+    },
+    Operation {
+        name: "config",
+        handler: config,
+        arity: -2
+    },
+    Operation {
+        name: "info",
+        handler: info,
+        arity: -1
+    },
 ];
 
-pub fn lookup(name: &str) -> Option<&Operation> {
+pub fn lookup(name: &[u8]) -> Option<&Operation> {
     OPERATIONS
         .iter()
-        .find(|o| name.eq_ignore_ascii_case(o.name))
+        .find(|o| name.eq_ignore_ascii_case(o.name.as_bytes()))
+}
+
+/// Parses a decimal integer argument, rejecting anything that isn't plain
+/// ASCII digits with an optional sign.
+fn parse_int(arg: &[u8]) -> Option<i64> {
+    std::str::from_utf8(arg).ok()?.parse().ok()
+}
+
+/// How many members `HRANDFIELD` and `SRANDMEMBER` may pick when a negative
+/// count allows repeats. Those replies aren't bounded by the size of the
+/// collection and are built in memory before being sent.
+const MAX_RANDOM_REPEATS: u64 = 1024 * 1024;
+
+/// Checks the count of `HRANDFIELD` or `SRANDMEMBER`, rejecting negative
+/// counts that would pick more than `MAX_RANDOM_REPEATS` members.
+fn check_random_count(count: i64) -> Result<i64, OperationError> {
+    if count < 0 && count.unsigned_abs() > MAX_RANDOM_REPEATS {
+        return Err(OperationError::OutOfRange);
+    }
+    Ok(count)
+}
+
+/// Parses a finite floating point argument. Infinities and NaN are rejected
+/// since no command can store them.
+fn parse_float(arg: &[u8]) -> Option<f64> {
+    let f: f64 = std::str::from_utf8(arg).ok()?.parse().ok()?;
+    f.is_finite().then_some(f)
+}
+
+/// Parses the timeout of a blocking command, given in seconds, into the
+/// deadline to wait until. Zero waits forever.
+fn parse_timeout(arg: &[u8]) -> Result<Option<Instant>, OperationError> {
+    let secs = parse_float(arg).ok_or(OperationError::InvalidTimeout)?;
+    if secs < 0.0 {
+        return Err(OperationError::NegativeTimeout);
+    }
+    if secs == 0.0 {
+        return Ok(None);
+    }
+    Duration::try_from_secs_f64(secs)
+        .ok()
+        .and_then(|timeout| Instant::now().checked_add(timeout))
+        .map(Some)
+        .ok_or(OperationError::InvalidTimeout)
+}
+
+/// Converts a Unix time in milliseconds into an `Instant`. Times in the past
+/// map to now, so keys given them are already expired on their next access.
+fn instant_from_unix_millis(millis: u64) -> Instant {
+    let now = Instant::now();
+    let at = UNIX_EPOCH + Duration::from_millis(millis);
+    match at.duration_since(SystemTime::now()) {
+        Ok(remaining) => now.checked_add(remaining).unwrap_or(now),
+        Err(_) => now,
+    }
+}
+
+/// Runs commands against a repository the way the server would, for tests
+/// of individual commands.
+#[cfg(test)]
+pub(crate) fn exec(repo: &mut Repository, command: &[&str]) -> OperationResult {
+    let request = Request::new(command.iter().map(|arg| arg.as_bytes().to_vec()).collect());
+    let mut ctx = Context {
+        repo,
+        session: &mut Session::default(),
+        config: &mut Config::default(),
+        stats: &mut Stats::default(),
+    };
+    match lookup(request.command()) {
+        Some(operation) => operation.execute(&mut ctx, &request),
+        None => OperationError::UnknownCommand(command[0].to_string()).into(),
+    }
 }
 
 #[cfg(test)]
@@ -96,18 +932,45 @@ mod tests {
 
     #[test]
     fn test_is_valid_arity() {
-        assert_eq!(is_valid_arity(2, 2), true);
-        assert_eq!(is_valid_arity(-2, 2), true);
-        assert_eq!(is_valid_arity(-2, 3), true);
+        assert!(is_valid_arity(2, 2));
+        assert!(is_valid_arity(-2, 2));
+        assert!(is_valid_arity(-2, 3));
 
-        assert_eq!(is_valid_arity(2, 3), false);
-        assert_eq!(is_valid_arity(2, 1), false);
-        assert_eq!(is_valid_arity(-2, 1), false);
+        assert!(!is_valid_arity(2, 3));
+        assert!(!is_valid_arity(2, 1));
+        assert!(!is_valid_arity(-2, 1));
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let reply: OperationResult = OperationError::UnknownCommand("foo".to_string()).into();
+        assert_eq!(reply, OperationResult::Error("ERR unknown command 'foo'".to_string()));
+
+        let reply: OperationResult = OperationError::WrongArity("get").into();
+        assert_eq!(
+            reply,
+            OperationResult::Error("ERR wrong number of arguments for 'get' command".to_string())
+        );
+
+        let reply: OperationResult = OperationError::WrongType.into();
+        assert_eq!(
+            reply,
+            OperationResult::Error(
+                "WRONGTYPE Operation against a key holding the wrong kind of value".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_execute_checks_arity() {
+        let mut repo = Repository::new();
+        assert_eq!(exec(&mut repo, &["GET"]), OperationError::WrongArity("get").into());
     }
 
     #[test]
     fn test_lookup() {
-        assert!(lookup("not implemented operation").is_none());
-        assert!(lookup("get").unwrap().name == "get");
+        assert!(lookup(b"not implemented operation").is_none());
+        assert!(lookup(b"get").unwrap().name == "get");
+        assert!(lookup(b"GET").unwrap().name == "get");
     }
 }
\ No newline at end of file
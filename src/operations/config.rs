@@ -0,0 +1,103 @@
+use crate::{config, request::Request};
+
+use super::{Context, OperationError, OperationResult};
+
+/// `CONFIG GET|SET|RESETSTAT|REWRITE ...`
+pub fn config(ctx: &mut Context, req: &Request) -> OperationResult {
+    let subcommand = &req.arguments()[0];
+    let args = &req.arguments()[1..];
+    if subcommand.eq_ignore_ascii_case(b"get") {
+        config_get(ctx, args)
+    } else if subcommand.eq_ignore_ascii_case(b"set") {
+        config_set(ctx, args)
+    } else if subcommand.eq_ignore_ascii_case(b"resetstat") {
+        config_resetstat(ctx, args)
+    } else if subcommand.eq_ignore_ascii_case(b"rewrite") {
+        config_rewrite(ctx, args)
+    } else {
+        OperationError::UnknownSubcommand(String::from_utf8_lossy(subcommand).to_string()).into()
+    }
+}
+
+/// `CONFIG GET pattern [pattern ...]`
+fn config_get(ctx: &mut Context, args: &[Vec<u8>]) -> OperationResult {
+    if args.is_empty() {
+        return OperationError::WrongArity("config|get").into();
+    }
+
+    let mut pairs: Vec<(&str, String)> = Vec::new();
+    for pattern in args {
+        for (name, value) in ctx.config.get_matching(pattern) {
+            if !pairs.iter().any(|(seen, _)| *seen == name) {
+                pairs.push((name, value));
+            }
+        }
+    }
+
+    OperationResult::Map(
+        pairs
+            .into_iter()
+            .map(|(name, value)| {
+                (
+                    OperationResult::StringRes(name.as_bytes().to_vec()),
+                    OperationResult::StringRes(value.into_bytes()),
+                )
+            })
+            .collect(),
+    )
+}
+
+/// `CONFIG SET parameter value [parameter value ...]`
+///
+/// Either every parameter is changed or, if any of them is rejected, none
+/// of them are.
+fn config_set(ctx: &mut Context, args: &[Vec<u8>]) -> OperationResult {
+    if args.is_empty() || !args.len().is_multiple_of(2) {
+        return OperationError::WrongArity("config|set").into();
+    }
+
+    let mut updated = ctx.config.clone();
+    let mut seen: Vec<String> = Vec::new();
+    for pair in args.chunks(2) {
+        let name = String::from_utf8_lossy(&pair[0]).to_ascii_lowercase();
+        let value = String::from_utf8_lossy(&pair[1]).to_string();
+        let failed = |reason: &str| OperationError::ConfigSet(name.clone(), reason.to_string());
+
+        match config::is_mutable(&name) {
+            None => return OperationError::UnknownConfig(name).into(),
+            Some(false) => return failed("can't set immutable config").into(),
+            Some(true) if seen.contains(&name) => return failed("duplicate parameter").into(),
+            Some(true) => {}
+        }
+        if let Err(reason) = updated.set(&name, &[value]) {
+            return failed(&reason).into();
+        }
+        seen.push(name);
+    }
+
+    *ctx.config = updated;
+    OperationResult::Ok
+}
+
+/// `CONFIG RESETSTAT`
+fn config_resetstat(ctx: &mut Context, args: &[Vec<u8>]) -> OperationResult {
+    if !args.is_empty() {
+        return OperationError::WrongArity("config|resetstat").into();
+    }
+    ctx.stats.reset();
+    OperationResult::Ok
+}
+
+/// `CONFIG REWRITE`
+fn config_rewrite(ctx: &mut Context, args: &[Vec<u8>]) -> OperationResult {
+    if !args.is_empty() {
+        return OperationError::WrongArity("config|rewrite").into();
+    }
+    if ctx.config.file.is_none() {
+        return OperationError::NoConfigFile.into();
+    }
+    match ctx.config.rewrite() {
+        Ok(()) => OperationResult::Ok,
+        Err(e) => OperationError::ConfigRewrite(e.to_string()).into(),
+    }
+}
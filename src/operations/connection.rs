@@ -0,0 +1,57 @@
+use crate::{protocol::ProtocolVersion, request::Request};
+
+use super::{parse_int, Context, OperationError, OperationResult};
+
+/// `HELLO [protover [AUTH username password] [SETNAME clientname]]`
+///
+/// Switches the connection to the requested protocol version and replies with
+/// a map describing the server.
+pub fn hello(ctx: &mut Context, req: &Request) -> OperationResult {
+    let mut args = req.arguments().iter();
+    let mut protocol = ctx.session.protocol;
+    let mut name = None;
+
+    if let Some(version) = args.next() {
+        protocol = match parse_int(version) {
+            Some(2) => ProtocolVersion::Resp2,
+            Some(3) => ProtocolVersion::Resp3,
+            Some(_) => return OperationError::NoProto.into(),
+            None => return OperationError::InvalidProtocolVersion.into(),
+        };
+    }
+
+    while let Some(option) = args.next() {
+        if option.eq_ignore_ascii_case(b"auth") {
+            let (Some(username), Some(_password)) = (args.next(), args.next()) else {
+                return OperationError::Syntax.into();
+            };
+            // There are no users besides the passwordless default one.
+            if username.as_slice() != b"default" {
+                return OperationError::WrongPass.into();
+            }
+        } else if option.eq_ignore_ascii_case(b"setname") {
+            let Some(client_name) = args.next() else {
+                return OperationError::Syntax.into();
+            };
+            name = Some(client_name.to_vec());
+        } else {
+            return OperationError::Syntax.into();
+        }
+    }
+
+    ctx.session.protocol = protocol;
+    if name.is_some() {
+        ctx.session.name = name;
+    }
+
+    let field = |name: &str| OperationResult::StringRes(name.as_bytes().to_vec());
+    OperationResult::Map(vec![
+        (field("server"), field("muna")),
+        (field("version"), field(env!("CARGO_PKG_VERSION"))),
+        (field("proto"), OperationResult::Int(protocol as i64)),
+        (field("id"), OperationResult::Int(ctx.session.id as i64)),
+        (field("mode"), field("standalone")),
+        (field("role"), field("master")),
+        (field("modules"), OperationResult::Array(vec![])),
+    ])
+}
@@ -1,58 +1,745 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
 
-use crate::{repository::Repository, record::{Record}, request::Request};
+use rand::seq::{IteratorRandom, SliceRandom};
 
-use super::OperationResult;
+use crate::{
+    protocol::ProtocolVersion,
+    record::{parse_canonical_int, Record},
+    repository::Repository,
+    request::Request,
+};
 
-pub fn hget(repo: &mut Repository, req: &Request) -> OperationResult {
-    let key = &req.arguments()[0];
-    let hash_key = &req.arguments()[1];
-    if let Some(record) = repo.get(key.to_string()) {
-        match record {
-            Record::HashMap(hash) => match hash.get(hash_key) {
-                Some(s) => OperationResult::StringRes(s.to_string()),
-                None => OperationResult::Nil,
-            },
-            _ => OperationResult::Error("wrongtype".to_string()),
+use super::{
+    check_random_count, instant_from_unix_millis, parse_float, parse_int, Context, OperationError,
+    OperationResult,
+};
+
+type Hash = HashMap<Vec<u8>, Vec<u8>>;
+
+/// Borrows the hash stored at `key`, or `None` if the key doesn't exist.
+/// Keys holding other types fail with `WRONGTYPE`.
+fn read_hash<'a>(
+    repo: &'a mut Repository,
+    key: &[u8],
+) -> Result<Option<&'a mut Hash>, OperationError> {
+    match repo.get_mut(key) {
+        Some(Record::HashMap(hash)) => Ok(Some(hash)),
+        Some(_) => Err(OperationError::WrongType),
+        None => Ok(None),
+    }
+}
+
+/// Borrows the hash stored at `key`, creating an empty one if the key
+/// doesn't exist.
+fn hash_for_update<'a>(
+    repo: &'a mut Repository,
+    key: &[u8],
+) -> Result<&'a mut Hash, OperationError> {
+    if repo.get_mut(key).is_none() {
+        repo.set(key.to_vec(), Record::HashMap(HashMap::new()));
+    }
+    read_hash(repo, key).map(|hash| hash.expect("hash was just created"))
+}
+
+fn field_exists(repo: &mut Repository, key: &[u8], field: &[u8]) -> bool {
+    matches!(read_hash(repo, key), Ok(Some(hash)) if hash.contains_key(field))
+}
+
+/// Drops a key whose hash has no fields left, as Redis never keeps empty
+/// collections around.
+fn remove_if_empty(repo: &mut Repository, key: &[u8]) {
+    if let Some(Record::HashMap(hash)) = repo.get_mut(key) {
+        if hash.is_empty() {
+            repo.delete(key);
         }
-    } else {
-        OperationResult::Nil
     }
 }
 
-pub fn hset(repo: &mut Repository, req: &Request) -> OperationResult {
+pub fn hget(ctx: &mut Context, req: &Request) -> OperationResult {
+    let key = &req.arguments()[0];
+    let field = &req.arguments()[1];
+    match read_hash(ctx.repo, key) {
+        Ok(Some(hash)) => match hash.get(field) {
+            Some(s) => OperationResult::StringRes(s.to_vec()),
+            None => OperationResult::Nil,
+        },
+        Ok(None) => OperationResult::Nil,
+        Err(e) => e.into(),
+    }
+}
+
+/// Sets each field to its value and replies with the number of fields that
+/// didn't exist before.
+pub fn hset(ctx: &mut Context, req: &Request) -> OperationResult {
     if req.arity() % 2 != 0 {
-        return OperationResult::Error("wrong number of arguments".to_string());
+        return OperationError::WrongArity("hset").into();
     };
     let key = &req.arguments()[0];
     let pairs = &req.arguments()[1..];
-    if let Some(mut record) = repo.get(key.to_string()) {
-        match record {
-            Record::HashMap(ref mut hash) => {
-                for pair in pairs.chunks(2) {
-                    hash.insert(pair[0].to_string(), pair[0].to_string());
-                }
-                repo.set(key.to_string(), record.clone());
-                return OperationResult::Nil;
-            }
-            _ => {
-                let record = new_hash_from_pairs(pairs);
-                repo.set(key.to_string(), record);
-                OperationResult::Nil
-            }
+    let hash = match hash_for_update(ctx.repo, key) {
+        Ok(hash) => hash,
+        Err(e) => return e.into(),
+    };
+    let added = pairs
+        .chunks(2)
+        .filter(|pair| hash.insert(pair[0].to_vec(), pair[1].to_vec()).is_none())
+        .count();
+    for pair in pairs.chunks(2) {
+        ctx.repo.persist_field(key, &pair[0]);
+    }
+    OperationResult::Int(added as i64)
+}
+
+pub fn hsetnx(ctx: &mut Context, req: &Request) -> OperationResult {
+    let key = &req.arguments()[0];
+    let hash = match hash_for_update(ctx.repo, key) {
+        Ok(hash) => hash,
+        Err(e) => return e.into(),
+    };
+    let field = &req.arguments()[1];
+    if hash.contains_key(field) {
+        return OperationResult::Int(0);
+    }
+    hash.insert(field.to_vec(), req.arguments()[2].to_vec());
+    OperationResult::Int(1)
+}
+
+pub fn hdel(ctx: &mut Context, req: &Request) -> OperationResult {
+    let key = &req.arguments()[0];
+    let removed = match read_hash(ctx.repo, key) {
+        Ok(Some(hash)) => req.arguments()[1..]
+            .iter()
+            .filter(|field| hash.remove(*field).is_some())
+            .count(),
+        Ok(None) => 0,
+        Err(e) => return e.into(),
+    };
+    for field in &req.arguments()[1..] {
+        ctx.repo.persist_field(key, field);
+    }
+    remove_if_empty(ctx.repo, key);
+    OperationResult::Int(removed as i64)
+}
+
+pub fn hgetall(ctx: &mut Context, req: &Request) -> OperationResult {
+    match read_hash(ctx.repo, &req.arguments()[0]) {
+        Ok(hash) => OperationResult::Map(
+            hash.into_iter()
+                .flatten()
+                .map(|(field, value)| {
+                    (
+                        OperationResult::StringRes(field.to_vec()),
+                        OperationResult::StringRes(value.to_vec()),
+                    )
+                })
+                .collect(),
+        ),
+        Err(e) => e.into(),
+    }
+}
+
+pub fn hkeys(ctx: &mut Context, req: &Request) -> OperationResult {
+    match read_hash(ctx.repo, &req.arguments()[0]) {
+        Ok(hash) => OperationResult::Array(
+            hash.into_iter()
+                .flat_map(|hash| hash.keys())
+                .map(|field| OperationResult::StringRes(field.to_vec()))
+                .collect(),
+        ),
+        Err(e) => e.into(),
+    }
+}
+
+pub fn hvals(ctx: &mut Context, req: &Request) -> OperationResult {
+    match read_hash(ctx.repo, &req.arguments()[0]) {
+        Ok(hash) => OperationResult::Array(
+            hash.into_iter()
+                .flat_map(|hash| hash.values())
+                .map(|value| OperationResult::StringRes(value.to_vec()))
+                .collect(),
+        ),
+        Err(e) => e.into(),
+    }
+}
+
+pub fn hlen(ctx: &mut Context, req: &Request) -> OperationResult {
+    match read_hash(ctx.repo, &req.arguments()[0]) {
+        Ok(hash) => OperationResult::Int(hash.map_or(0, |hash| hash.len()) as i64),
+        Err(e) => e.into(),
+    }
+}
+
+pub fn hexists(ctx: &mut Context, req: &Request) -> OperationResult {
+    match read_hash(ctx.repo, &req.arguments()[0]) {
+        Ok(hash) => {
+            let exists = hash.is_some_and(|hash| hash.contains_key(&req.arguments()[1]));
+            OperationResult::Int(exists as i64)
         }
+        Err(e) => e.into(),
+    }
+}
+
+pub fn hstrlen(ctx: &mut Context, req: &Request) -> OperationResult {
+    match read_hash(ctx.repo, &req.arguments()[0]) {
+        Ok(hash) => OperationResult::Int(
+            hash.and_then(|hash| hash.get(&req.arguments()[1]))
+                .map_or(0, |value| value.len()) as i64,
+        ),
+        Err(e) => e.into(),
+    }
+}
+
+pub fn hmget(ctx: &mut Context, req: &Request) -> OperationResult {
+    let hash = match read_hash(ctx.repo, &req.arguments()[0]) {
+        Ok(hash) => hash,
+        Err(e) => return e.into(),
+    };
+    let values = req.arguments()[1..]
+        .iter()
+        .map(|field| match hash.as_ref().and_then(|hash| hash.get(field)) {
+            Some(value) => OperationResult::StringRes(value.to_vec()),
+            None => OperationResult::Nil,
+        })
+        .collect();
+    OperationResult::Array(values)
+}
+
+pub fn hincrby(ctx: &mut Context, req: &Request) -> OperationResult {
+    let Some(increment) = parse_int(&req.arguments()[2]) else {
+        return OperationError::NotAnInteger.into();
+    };
+    let hash = match hash_for_update(ctx.repo, &req.arguments()[0]) {
+        Ok(hash) => hash,
+        Err(e) => return e.into(),
+    };
+    let field = &req.arguments()[1];
+    let current = match hash.get(field) {
+        Some(value) => match parse_canonical_int(value) {
+            Some(n) => n,
+            None => return OperationError::HashValueNotAnInteger.into(),
+        },
+        None => 0,
+    };
+    let Some(value) = current.checked_add(increment) else {
+        return OperationError::Overflow.into();
+    };
+    hash.insert(field.to_vec(), value.to_string().into_bytes());
+    OperationResult::Int(value)
+}
+
+pub fn hincrbyfloat(ctx: &mut Context, req: &Request) -> OperationResult {
+    let Some(increment) = parse_float(&req.arguments()[2]) else {
+        return OperationError::NotAFloat.into();
+    };
+    let hash = match hash_for_update(ctx.repo, &req.arguments()[0]) {
+        Ok(hash) => hash,
+        Err(e) => return e.into(),
+    };
+    let field = &req.arguments()[1];
+    let current = match hash.get(field) {
+        Some(value) => match parse_float(value) {
+            Some(f) => f,
+            None => return OperationError::HashValueNotAFloat.into(),
+        },
+        None => 0.0,
+    };
+    let value = current + increment;
+    if !value.is_finite() {
+        return OperationError::NanOrInfinity.into();
+    }
+    let value = value.to_string().into_bytes();
+    hash.insert(field.to_vec(), value.clone());
+    OperationResult::StringRes(value)
+}
+
+/// `HRANDFIELD key [count [WITHVALUES]]`
+///
+/// A positive count picks that many distinct fields, a negative one allows
+/// the same field to be picked more than once.
+pub fn hrandfield(ctx: &mut Context, req: &Request) -> OperationResult {
+    let args = req.arguments();
+    let count = args.get(1).map(|count| {
+        parse_int(count).ok_or(OperationError::NotAnInteger).and_then(check_random_count)
+    });
+    let count = match count.transpose() {
+        Ok(count) => count,
+        Err(e) => return e.into(),
+    };
+    let with_values = match args.get(2) {
+        Some(option) if args.len() == 3 && option.eq_ignore_ascii_case(b"WITHVALUES") => true,
+        Some(_) => return OperationError::Syntax.into(),
+        None => false,
+    };
+    let hash = match read_hash(ctx.repo, &args[0]) {
+        Ok(hash) => hash,
+        Err(e) => return e.into(),
+    };
+
+    let mut rng = rand::thread_rng();
+    let Some(count) = count else {
+        return match hash.and_then(|hash| hash.keys().choose(&mut rng)) {
+            Some(field) => OperationResult::StringRes(field.to_vec()),
+            None => OperationResult::Nil,
+        };
+    };
+    let Some(hash) = hash else {
+        return OperationResult::Array(vec![]);
+    };
+
+    let entries: Vec<_> = hash.iter().collect();
+    let picked: Vec<_> = if count >= 0 {
+        entries.choose_multiple(&mut rng, count as usize).collect()
     } else {
-        let record = new_hash_from_pairs(pairs);
-        repo.set(key.to_string(), record);
-        OperationResult::Nil
+        (0..count.unsigned_abs()).filter_map(|_| entries.choose(&mut rng)).collect()
+    };
+
+    let replies = picked.into_iter().map(|(field, value)| {
+        let field = OperationResult::StringRes(field.to_vec());
+        let value = OperationResult::StringRes(value.to_vec());
+        (field, value)
+    });
+    match (with_values, ctx.session.protocol) {
+        (false, _) => OperationResult::Array(replies.map(|(field, _)| field).collect()),
+        (true, ProtocolVersion::Resp2) => {
+            OperationResult::Array(replies.flat_map(|(field, value)| [field, value]).collect())
+        }
+        (true, ProtocolVersion::Resp3) => OperationResult::Array(
+            replies
+                .map(|(field, value)| OperationResult::Array(vec![field, value]))
+                .collect(),
+        ),
     }
 }
 
-fn new_hash_from_pairs(pairs: &[String]) -> Record {
-    let mut new_set = HashMap::new();
-    for pair in pairs.chunks(2) {
-        new_set.insert(pair[0].to_string(), pair[1].to_string());
+/// Which existing expiration a field must have for `HEXPIRE` and friends to
+/// replace it.
+enum ExpireCondition {
+    Always,
+    NoExpiration,
+    HasExpiration,
+    Later,
+    Earlier,
+}
+
+impl ExpireCondition {
+    fn allows(&self, current: Option<Instant>, new: Instant) -> bool {
+        match self {
+            ExpireCondition::Always => true,
+            ExpireCondition::NoExpiration => current.is_none(),
+            ExpireCondition::HasExpiration => current.is_some(),
+            ExpireCondition::Later => current.is_some_and(|current| new > current),
+            ExpireCondition::Earlier => current.is_none_or(|current| new < current),
+        }
     }
-    let record = Record::HashMap(new_set);
-    record
-}
\ No newline at end of file
+}
+
+/// Parses the trailing `FIELDS numfields field [field ...]` block shared by
+/// the field expiration commands.
+fn parse_fields(args: &[Vec<u8>]) -> Result<&[Vec<u8>], OperationError> {
+    match args.first() {
+        Some(keyword) if keyword.eq_ignore_ascii_case(b"FIELDS") => {}
+        _ => return Err(OperationError::MissingFields),
+    }
+    match args.get(1).map(|count| parse_int(count)) {
+        Some(Some(count)) if count > 0 && count as usize == args.len() - 2 => Ok(&args[2..]),
+        Some(Some(_)) => Err(OperationError::NumFields),
+        Some(None) => Err(OperationError::NotAnInteger),
+        None => Err(OperationError::MissingFields),
+    }
+}
+
+/// Which form of expiration time a field expiration command takes.
+enum ExpireUnit {
+    Seconds,
+    Millis,
+    UnixSeconds,
+    UnixMillis,
+}
+
+/// `HEXPIRE key seconds [NX | XX | GT | LT] FIELDS numfields field [field ...]`
+/// and its millisecond and Unix time variants. Replies with a code for each
+/// field: -2 if it doesn't exist, 0 if the condition wasn't met, 1 if the
+/// expiration was set and 2 if the time was already in the past and the
+/// field got deleted.
+fn expire_fields(
+    ctx: &mut Context,
+    req: &Request,
+    command: &'static str,
+    unit: ExpireUnit,
+) -> OperationResult {
+    let args = req.arguments();
+    let key = &args[0];
+    let Some(amount) = parse_int(&args[1]) else {
+        return OperationError::NotAnInteger.into();
+    };
+    let (condition, rest) = match args[2].to_ascii_uppercase().as_slice() {
+        b"NX" => (ExpireCondition::NoExpiration, &args[3..]),
+        b"XX" => (ExpireCondition::HasExpiration, &args[3..]),
+        b"GT" => (ExpireCondition::Later, &args[3..]),
+        b"LT" => (ExpireCondition::Earlier, &args[3..]),
+        _ => (ExpireCondition::Always, &args[2..]),
+    };
+    let fields = match parse_fields(rest) {
+        Ok(fields) => fields,
+        Err(e) => return e.into(),
+    };
+
+    let millis = match unit {
+        ExpireUnit::Seconds | ExpireUnit::UnixSeconds => amount.checked_mul(1000),
+        ExpireUnit::Millis | ExpireUnit::UnixMillis => Some(amount),
+    };
+    let Some(millis) = millis.filter(|millis| *millis >= 0) else {
+        return OperationError::InvalidExpireTime(command).into();
+    };
+    let at = match unit {
+        ExpireUnit::Seconds | ExpireUnit::Millis => {
+            Instant::now().checked_add(Duration::from_millis(millis as u64))
+        }
+        ExpireUnit::UnixSeconds | ExpireUnit::UnixMillis => {
+            Some(instant_from_unix_millis(millis as u64))
+        }
+    };
+    let Some(at) = at else {
+        return OperationError::InvalidExpireTime(command).into();
+    };
+    let now = Instant::now();
+
+    if let Err(e) = read_hash(ctx.repo, key) {
+        return e.into();
+    }
+    let mut codes = Vec::with_capacity(fields.len());
+    for field in fields {
+        let code = if !field_exists(ctx.repo, key, field) {
+            -2
+        } else if !condition.allows(ctx.repo.field_expiration(key, field), at) {
+            0
+        } else if at <= now {
+            if let Ok(Some(hash)) = read_hash(ctx.repo, key) {
+                hash.remove(field);
+            }
+            ctx.repo.persist_field(key, field);
+            2
+        } else {
+            ctx.repo.set_field_expiration(key, field, at);
+            1
+        };
+        codes.push(OperationResult::Int(code));
+    }
+    remove_if_empty(ctx.repo, key);
+    OperationResult::Array(codes)
+}
+
+pub fn hexpire(ctx: &mut Context, req: &Request) -> OperationResult {
+    expire_fields(ctx, req, "hexpire", ExpireUnit::Seconds)
+}
+
+pub fn hpexpire(ctx: &mut Context, req: &Request) -> OperationResult {
+    expire_fields(ctx, req, "hpexpire", ExpireUnit::Millis)
+}
+
+pub fn hexpireat(ctx: &mut Context, req: &Request) -> OperationResult {
+    expire_fields(ctx, req, "hexpireat", ExpireUnit::UnixSeconds)
+}
+
+pub fn hpexpireat(ctx: &mut Context, req: &Request) -> OperationResult {
+    expire_fields(ctx, req, "hpexpireat", ExpireUnit::UnixMillis)
+}
+
+/// Replies with a code for each field listed after `FIELDS`: -2 if it
+/// doesn't exist, -1 if it never expires, or whatever `f` returns for it.
+fn for_each_field(
+    ctx: &mut Context,
+    req: &Request,
+    mut f: impl FnMut(&mut Context, &[u8], Instant) -> i64,
+) -> OperationResult {
+    let key = &req.arguments()[0];
+    let fields = match parse_fields(&req.arguments()[1..]) {
+        Ok(fields) => fields,
+        Err(e) => return e.into(),
+    };
+    if let Err(e) = read_hash(ctx.repo, key) {
+        return e.into();
+    }
+    let codes = fields
+        .iter()
+        .map(|field| {
+            let exists = field_exists(ctx.repo, key, field);
+            let code = match ctx.repo.field_expiration(key, field) {
+                _ if !exists => -2,
+                Some(at) => f(ctx, field, at),
+                None => -1,
+            };
+            OperationResult::Int(code)
+        })
+        .collect();
+    OperationResult::Array(codes)
+}
+
+pub fn httl(ctx: &mut Context, req: &Request) -> OperationResult {
+    for_each_field(ctx, req, |_, _, at| {
+        let remaining = at.saturating_duration_since(Instant::now());
+        ((remaining.as_millis() + 500) / 1000) as i64
+    })
+}
+
+pub fn hpttl(ctx: &mut Context, req: &Request) -> OperationResult {
+    for_each_field(ctx, req, |_, _, at| {
+        at.saturating_duration_since(Instant::now()).as_millis() as i64
+    })
+}
+
+pub fn hpersist(ctx: &mut Context, req: &Request) -> OperationResult {
+    let key = req.arguments()[0].clone();
+    for_each_field(ctx, req, |ctx, field, _| {
+        ctx.repo.persist_field(&key, field);
+        1
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{operations::exec, repository::Repository};
+
+    use super::*;
+
+    fn bulk(s: &str) -> OperationResult {
+        OperationResult::StringRes(s.as_bytes().to_vec())
+    }
+
+    fn sorted(reply: OperationResult) -> Vec<OperationResult> {
+        let OperationResult::Array(mut items) = reply else {
+            panic!("expected an array, got {:?}", reply);
+        };
+        items.sort_by_key(|item| format!("{:?}", item));
+        items
+    }
+
+    #[test]
+    fn test_hset_counts_new_fields() {
+        let mut repo = Repository::new();
+        assert_eq!(exec(&mut repo, &["HSET", "h", "a", "1", "b", "2"]), OperationResult::Int(2));
+        assert_eq!(exec(&mut repo, &["HSET", "h", "a", "10", "c", "3"]), OperationResult::Int(1));
+        assert_eq!(exec(&mut repo, &["HGET", "h", "a"]), bulk("10"));
+        assert_eq!(exec(&mut repo, &["HLEN", "h"]), OperationResult::Int(3));
+        assert_eq!(exec(&mut repo, &["HSET", "h", "a"]), OperationError::WrongArity("hset").into());
+
+        exec(&mut repo, &["SET", "s", "v"]);
+        assert_eq!(exec(&mut repo, &["HSET", "s", "a", "1"]), OperationError::WrongType.into());
+        assert_eq!(exec(&mut repo, &["GET", "s"]), bulk("v"));
+    }
+
+    #[test]
+    fn test_read_commands() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["HSET", "h", "a", "1", "b", "22"]);
+
+        assert_eq!(sorted(exec(&mut repo, &["HKEYS", "h"])), vec![bulk("a"), bulk("b")]);
+        assert_eq!(sorted(exec(&mut repo, &["HVALS", "h"])), vec![bulk("1"), bulk("22")]);
+        let OperationResult::Map(mut pairs) = exec(&mut repo, &["HGETALL", "h"]) else {
+            panic!("HGETALL should reply with a map");
+        };
+        pairs.sort_by_key(|pair| format!("{:?}", pair));
+        assert_eq!(pairs, vec![(bulk("a"), bulk("1")), (bulk("b"), bulk("22"))]);
+
+        assert_eq!(exec(&mut repo, &["HEXISTS", "h", "a"]), OperationResult::Int(1));
+        assert_eq!(exec(&mut repo, &["HEXISTS", "h", "z"]), OperationResult::Int(0));
+        assert_eq!(exec(&mut repo, &["HSTRLEN", "h", "b"]), OperationResult::Int(2));
+        assert_eq!(exec(&mut repo, &["HSTRLEN", "h", "z"]), OperationResult::Int(0));
+        assert_eq!(
+            exec(&mut repo, &["HMGET", "h", "a", "z", "b"]),
+            OperationResult::Array(vec![bulk("1"), OperationResult::Nil, bulk("22")])
+        );
+
+        assert_eq!(exec(&mut repo, &["HGETALL", "missing"]), OperationResult::Map(vec![]));
+        assert_eq!(exec(&mut repo, &["HKEYS", "missing"]), OperationResult::Array(vec![]));
+        assert_eq!(exec(&mut repo, &["HLEN", "missing"]), OperationResult::Int(0));
+
+        exec(&mut repo, &["SET", "s", "v"]);
+        let commands = [&["HGETALL", "s"][..], &["HKEYS", "s"], &["HVALS", "s"], &["HMGET", "s", "a"]];
+        for command in commands {
+            assert_eq!(exec(&mut repo, command), OperationError::WrongType.into());
+        }
+    }
+
+    #[test]
+    fn test_hdel_removes_empty_hash() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["HSET", "h", "a", "1", "b", "2"]);
+        assert_eq!(exec(&mut repo, &["HDEL", "h", "a", "z"]), OperationResult::Int(1));
+        assert_eq!(exec(&mut repo, &["HDEL", "h", "b"]), OperationResult::Int(1));
+        assert_eq!(repo.get(b"h"), None);
+        assert_eq!(exec(&mut repo, &["HDEL", "h", "b"]), OperationResult::Int(0));
+    }
+
+    #[test]
+    fn test_hsetnx() {
+        let mut repo = Repository::new();
+        assert_eq!(exec(&mut repo, &["HSETNX", "h", "a", "1"]), OperationResult::Int(1));
+        assert_eq!(exec(&mut repo, &["HSETNX", "h", "a", "2"]), OperationResult::Int(0));
+        assert_eq!(exec(&mut repo, &["HGET", "h", "a"]), bulk("1"));
+    }
+
+    #[test]
+    fn test_hincrby() {
+        let mut repo = Repository::new();
+        assert_eq!(exec(&mut repo, &["HINCRBY", "h", "n", "5"]), OperationResult::Int(5));
+        assert_eq!(exec(&mut repo, &["HINCRBY", "h", "n", "-7"]), OperationResult::Int(-2));
+        assert_eq!(exec(&mut repo, &["HINCRBY", "h", "n", "x"]), OperationError::NotAnInteger.into());
+
+        exec(&mut repo, &["HSET", "h", "s", "abc", "max", &i64::MAX.to_string()]);
+        assert_eq!(
+            exec(&mut repo, &["HINCRBY", "h", "s", "1"]),
+            OperationError::HashValueNotAnInteger.into()
+        );
+        assert_eq!(exec(&mut repo, &["HINCRBY", "h", "max", "1"]), OperationError::Overflow.into());
+        exec(&mut repo, &["HSET", "h", "z", "010"]);
+        assert_eq!(
+            exec(&mut repo, &["HINCRBY", "h", "z", "1"]),
+            OperationError::HashValueNotAnInteger.into()
+        );
+
+        assert_eq!(exec(&mut repo, &["HINCRBYFLOAT", "h", "f", "10.5"]), bulk("10.5"));
+        assert_eq!(exec(&mut repo, &["HINCRBYFLOAT", "h", "n", "0.5"]), bulk("-1.5"));
+        assert_eq!(
+            exec(&mut repo, &["HINCRBYFLOAT", "h", "s", "1"]),
+            OperationError::HashValueNotAFloat.into()
+        );
+    }
+
+    #[test]
+    fn test_hexpire() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["HSET", "h", "a", "1", "b", "2", "c", "3"]);
+        assert_eq!(
+            exec(&mut repo, &["HEXPIRE", "h", "100", "FIELDS", "2", "a", "z"]),
+            OperationResult::Array(vec![OperationResult::Int(1), OperationResult::Int(-2)])
+        );
+        assert_eq!(
+            exec(&mut repo, &["HTTL", "h", "FIELDS", "3", "a", "b", "z"]),
+            OperationResult::Array(vec![
+                OperationResult::Int(100),
+                OperationResult::Int(-1),
+                OperationResult::Int(-2)
+            ])
+        );
+        assert_eq!(
+            exec(&mut repo, &["HEXPIRE", "h", "50", "NX", "FIELDS", "2", "a", "b"]),
+            OperationResult::Array(vec![OperationResult::Int(0), OperationResult::Int(1)])
+        );
+        assert_eq!(
+            exec(&mut repo, &["HPEXPIRE", "h", "200000", "GT", "FIELDS", "2", "a", "c"]),
+            OperationResult::Array(vec![OperationResult::Int(1), OperationResult::Int(0)])
+        );
+        assert!(matches!(
+            exec(&mut repo, &["HPTTL", "h", "FIELDS", "1", "a"]),
+            OperationResult::Array(ttls) if matches!(ttls[0], OperationResult::Int(ms) if ms > 199_000)
+        ));
+
+        assert_eq!(
+            exec(&mut repo, &["HPERSIST", "h", "FIELDS", "2", "a", "c"]),
+            OperationResult::Array(vec![OperationResult::Int(1), OperationResult::Int(-1)])
+        );
+        assert_eq!(
+            exec(&mut repo, &["HTTL", "h", "FIELDS", "1", "a"]),
+            OperationResult::Array(vec![OperationResult::Int(-1)])
+        );
+
+        exec(&mut repo, &["HSET", "h", "b", "new"]);
+        assert_eq!(
+            exec(&mut repo, &["HTTL", "h", "FIELDS", "1", "b"]),
+            OperationResult::Array(vec![OperationResult::Int(-1)])
+        );
+    }
+
+    #[test]
+    fn test_hexpire_in_the_past_deletes() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["HSET", "h", "a", "1", "b", "2"]);
+        assert_eq!(
+            exec(&mut repo, &["HEXPIREAT", "h", "1", "FIELDS", "1", "a"]),
+            OperationResult::Array(vec![OperationResult::Int(2)])
+        );
+        assert_eq!(exec(&mut repo, &["HGET", "h", "a"]), OperationResult::Nil);
+        assert_eq!(
+            exec(&mut repo, &["HEXPIRE", "h", "0", "FIELDS", "1", "b"]),
+            OperationResult::Array(vec![OperationResult::Int(2)])
+        );
+        assert_eq!(repo.get(b"h"), None);
+    }
+
+    #[test]
+    fn test_expired_fields_are_hidden() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["HSET", "h", "a", "1", "b", "2"]);
+        exec(&mut repo, &["HPEXPIRE", "h", "1", "FIELDS", "1", "a"]);
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(exec(&mut repo, &["HGET", "h", "a"]), OperationResult::Nil);
+        assert_eq!(exec(&mut repo, &["HLEN", "h"]), OperationResult::Int(1));
+
+        exec(&mut repo, &["HPEXPIRE", "h", "1", "FIELDS", "1", "b"]);
+        std::thread::sleep(Duration::from_millis(5));
+        repo.remove_expired();
+        assert_eq!(repo.get(b"h"), None);
+    }
+
+    #[test]
+    fn test_hexpire_errors() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["HSET", "h", "a", "1"]);
+        assert_eq!(
+            exec(&mut repo, &["HEXPIRE", "h", "10", "FIELDS", "2", "a"]),
+            OperationError::NumFields.into()
+        );
+        assert_eq!(
+            exec(&mut repo, &["HEXPIRE", "h", "10", "a", "b", "c"]),
+            OperationError::MissingFields.into()
+        );
+        assert_eq!(
+            exec(&mut repo, &["HEXPIRE", "h", "-1", "FIELDS", "1", "a"]),
+            OperationError::InvalidExpireTime("hexpire").into()
+        );
+        assert_eq!(
+            exec(&mut repo, &["HEXPIRE", "missing", "10", "FIELDS", "1", "a"]),
+            OperationResult::Array(vec![OperationResult::Int(-2)])
+        );
+
+        exec(&mut repo, &["SET", "s", "v"]);
+        assert_eq!(
+            exec(&mut repo, &["HEXPIRE", "s", "10", "FIELDS", "1", "a"]),
+            OperationError::WrongType.into()
+        );
+        assert_eq!(exec(&mut repo, &["HTTL", "s", "FIELDS", "1", "a"]), OperationError::WrongType.into());
+    }
+
+    #[test]
+    fn test_hrandfield() {
+        let mut repo = Repository::new();
+        assert_eq!(exec(&mut repo, &["HRANDFIELD", "h"]), OperationResult::Nil);
+        assert_eq!(exec(&mut repo, &["HRANDFIELD", "h", "2"]), OperationResult::Array(vec![]));
+
+        exec(&mut repo, &["HSET", "h", "a", "1", "b", "2"]);
+        assert!(matches!(exec(&mut repo, &["HRANDFIELD", "h"]), OperationResult::StringRes(_)));
+        assert_eq!(sorted(exec(&mut repo, &["HRANDFIELD", "h", "5"])), vec![bulk("a"), bulk("b")]);
+        assert_eq!(
+            sorted(exec(&mut repo, &["HRANDFIELD", "h", "2", "WITHVALUES"])),
+            vec![bulk("1"), bulk("2"), bulk("a"), bulk("b")]
+        );
+
+        let OperationResult::Array(repeated) = exec(&mut repo, &["HRANDFIELD", "h", "-5"]) else {
+            panic!("HRANDFIELD with a count should reply with an array");
+        };
+        assert_eq!(repeated.len(), 5);
+        assert_eq!(exec(&mut repo, &["HRANDFIELD", "h", "1", "VALUES"]), OperationError::Syntax.into());
+        assert_eq!(
+            exec(&mut repo, &["HRANDFIELD", "h", "-9223372036854775808"]),
+            OperationError::OutOfRange.into()
+        );
+        assert_eq!(
+            exec(&mut repo, &["HRANDFIELD", "h", "-1000000000000", "WITHVALUES"]),
+            OperationError::OutOfRange.into()
+        );
+    }
+}
@@ -1,26 +1,68 @@
 use std::time::{Instant, Duration};
 
-use crate::{repository::Repository, request::Request};
+use crate::{glob, request::Request};
 
-use super::OperationResult;
+use super::{parse_int, Context, OperationError, OperationResult};
 
-pub fn expire(repo: &mut Repository, req: &Request) -> OperationResult {
+pub fn expire(ctx: &mut Context, req: &Request) -> OperationResult {
     let key = &req.arguments()[0];
-    if repo.get(key.to_string()).is_none() {
+    if ctx.repo.get(key).is_none() {
         return OperationResult::Int(0)
     }
     
-    let secs = &req.arguments()[1];
-    let Ok(secs) = secs.parse::<i64>() else {
-        return OperationResult::Error("Value is not an integer or out of range".to_string())
+    let Some(secs) = parse_int(&req.arguments()[1]) else {
+        return OperationError::NotAnInteger.into()
     };
 
     if !secs.is_positive() {
-        repo.delete(key.to_string());
+        ctx.repo.delete(key);
         return OperationResult::Int(1)
     };
 
-    let expires_at = Instant::now() + Duration::from_secs(secs as u64);
-    repo.set_expiration(key.to_string(), expires_at);
-    return OperationResult::Int(1)
-}
\ No newline at end of file
+    let Some(expires_at) = Instant::now().checked_add(Duration::from_secs(secs as u64)) else {
+        return OperationError::InvalidExpireTime("expire").into()
+    };
+    ctx.repo.set_expiration(key.to_vec(), expires_at);
+    OperationResult::Int(1)
+}
+
+pub fn keys(ctx: &mut Context, req: &Request) -> OperationResult {
+    let pattern = &req.arguments()[0];
+    let keys = ctx.repo
+        .keys()
+        .into_iter()
+        .filter(|key| glob::matches(pattern, key))
+        .map(OperationResult::StringRes)
+        .collect();
+    OperationResult::Array(keys)
+}
+
+pub fn ttl(ctx: &mut Context, req: &Request) -> OperationResult {
+    match remaining_ttl(ctx, &req.arguments()[0]) {
+        Ok(remaining) => OperationResult::Int(((remaining.as_millis() + 500) / 1000) as i64),
+        Err(code) => OperationResult::Int(code),
+    }
+}
+
+pub fn pttl(ctx: &mut Context, req: &Request) -> OperationResult {
+    match remaining_ttl(ctx, &req.arguments()[0]) {
+        Ok(remaining) => OperationResult::Int(remaining.as_millis() as i64),
+        Err(code) => OperationResult::Int(code),
+    }
+}
+
+/// Time left before a key expires, or the code `TTL` replies with when
+/// there is none: -2 if the key doesn't exist and -1 if it never expires.
+fn remaining_ttl(ctx: &mut Context, key: &[u8]) -> Result<Duration, i64> {
+    if ctx.repo.get(key).is_none() {
+        return Err(-2);
+    }
+    match ctx.repo.expiration(key) {
+        Some(at) => Ok(at.saturating_duration_since(Instant::now())),
+        None => Err(-1),
+    }
+}
+
+pub fn persist(ctx: &mut Context, req: &Request) -> OperationResult {
+    OperationResult::Int(ctx.repo.persist(&req.arguments()[0]) as i64)
+}
@@ -0,0 +1,726 @@
+use std::collections::VecDeque;
+
+use crate::{record::Record, repository::Repository, request::Request};
+
+use super::{parse_int, parse_timeout, Context, OperationError, OperationResult};
+
+type List = VecDeque<Vec<u8>>;
+
+/// Borrows the list stored at `key`, or `None` if the key doesn't exist.
+/// Keys holding other types fail with `WRONGTYPE`.
+fn read_list<'a>(
+    repo: &'a mut Repository,
+    key: &[u8],
+) -> Result<Option<&'a mut List>, OperationError> {
+    match repo.get_mut(key) {
+        Some(Record::List(list)) => Ok(Some(list)),
+        Some(_) => Err(OperationError::WrongType),
+        None => Ok(None),
+    }
+}
+
+/// Borrows the list stored at `key`, creating an empty one if the key
+/// doesn't exist.
+fn list_for_update<'a>(
+    repo: &'a mut Repository,
+    key: &[u8],
+) -> Result<&'a mut List, OperationError> {
+    if repo.get_mut(key).is_none() {
+        repo.set(key.to_vec(), Record::List(VecDeque::new()));
+    }
+    read_list(repo, key).map(|list| list.expect("list was just created"))
+}
+
+/// Drops a key whose list has no elements left, as Redis never keeps empty
+/// collections around.
+fn remove_if_empty(repo: &mut Repository, key: &[u8]) {
+    if let Some(Record::List(list)) = repo.get_mut(key) {
+        if list.is_empty() {
+            repo.delete(key);
+        }
+    }
+}
+
+/// One of the two ends of a list, as named by `LEFT` and `RIGHT`.
+#[derive(Clone, Copy)]
+enum End {
+    Left,
+    Right,
+}
+
+impl End {
+    fn parse(arg: &[u8]) -> Result<End, OperationError> {
+        if arg.eq_ignore_ascii_case(b"LEFT") {
+            Ok(End::Left)
+        } else if arg.eq_ignore_ascii_case(b"RIGHT") {
+            Ok(End::Right)
+        } else {
+            Err(OperationError::Syntax)
+        }
+    }
+
+    fn pop(self, list: &mut List) -> Option<Vec<u8>> {
+        match self {
+            End::Left => list.pop_front(),
+            End::Right => list.pop_back(),
+        }
+    }
+
+    fn push(self, list: &mut List, element: Vec<u8>) {
+        match self {
+            End::Left => list.push_front(element),
+            End::Right => list.push_back(element),
+        }
+    }
+}
+
+/// Turns a possibly negative index into a position in a list of `len`
+/// elements, or `None` if it falls outside of it.
+fn index(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { index + len as i64 } else { index };
+    (0..len as i64).contains(&index).then_some(index as usize)
+}
+
+/// Turns an inclusive range of possibly negative indices into positions in a
+/// list of `len` elements, clamping it to the list. `None` means the range
+/// selects nothing.
+fn range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 { (start + len).max(0) } else { start };
+    let stop = if stop < 0 { stop + len } else { stop.min(len - 1) };
+    (start <= stop && start < len).then_some((start as usize, stop as usize))
+}
+
+fn push(ctx: &mut Context, req: &Request, end: End, only_existing: bool) -> OperationResult {
+    let key = &req.arguments()[0];
+    let list = match read_list(ctx.repo, key) {
+        Ok(None) if only_existing => return OperationResult::Int(0),
+        Ok(_) => list_for_update(ctx.repo, key),
+        Err(e) => Err(e),
+    };
+    let list = match list {
+        Ok(list) => list,
+        Err(e) => return e.into(),
+    };
+    for element in &req.arguments()[1..] {
+        end.push(list, element.to_vec());
+    }
+    let len = list.len();
+    ctx.repo.signal_ready(key);
+    OperationResult::Int(len as i64)
+}
+
+pub fn lpush(ctx: &mut Context, req: &Request) -> OperationResult {
+    push(ctx, req, End::Left, false)
+}
+
+pub fn rpush(ctx: &mut Context, req: &Request) -> OperationResult {
+    push(ctx, req, End::Right, false)
+}
+
+pub fn lpushx(ctx: &mut Context, req: &Request) -> OperationResult {
+    push(ctx, req, End::Left, true)
+}
+
+pub fn rpushx(ctx: &mut Context, req: &Request) -> OperationResult {
+    push(ctx, req, End::Right, true)
+}
+
+/// `LPOP key [count]` and `RPOP key [count]`. Without a count the reply is
+/// a single element, with one it is an array of up to that many.
+fn pop(ctx: &mut Context, req: &Request, end: End, command: &'static str) -> OperationResult {
+    let args = req.arguments();
+    if args.len() > 2 {
+        return OperationError::WrongArity(command).into();
+    }
+    let count = match args.get(1).map(|count| parse_int(count)) {
+        Some(Some(count)) if count >= 0 => Some(count as usize),
+        Some(_) => return OperationError::MustBePositive.into(),
+        None => None,
+    };
+    let key = &args[0];
+    let list = match read_list(ctx.repo, key) {
+        Ok(Some(list)) => list,
+        Ok(None) if count.is_some() => return OperationResult::NullArray,
+        Ok(None) => return OperationResult::Nil,
+        Err(e) => return e.into(),
+    };
+
+    let reply = match count {
+        Some(count) => OperationResult::Array(
+            std::iter::from_fn(|| end.pop(list))
+                .take(count)
+                .map(OperationResult::StringRes)
+                .collect(),
+        ),
+        None => end.pop(list).map_or(OperationResult::Nil, OperationResult::StringRes),
+    };
+    remove_if_empty(ctx.repo, key);
+    reply
+}
+
+pub fn lpop(ctx: &mut Context, req: &Request) -> OperationResult {
+    pop(ctx, req, End::Left, "lpop")
+}
+
+pub fn rpop(ctx: &mut Context, req: &Request) -> OperationResult {
+    pop(ctx, req, End::Right, "rpop")
+}
+
+pub fn llen(ctx: &mut Context, req: &Request) -> OperationResult {
+    match read_list(ctx.repo, &req.arguments()[0]) {
+        Ok(list) => OperationResult::Int(list.map_or(0, |list| list.len()) as i64),
+        Err(e) => e.into(),
+    }
+}
+
+pub fn lrange(ctx: &mut Context, req: &Request) -> OperationResult {
+    let (Some(start), Some(stop)) = (parse_int(&req.arguments()[1]), parse_int(&req.arguments()[2]))
+    else {
+        return OperationError::NotAnInteger.into();
+    };
+    let list = match read_list(ctx.repo, &req.arguments()[0]) {
+        Ok(Some(list)) => list,
+        Ok(None) => return OperationResult::Array(vec![]),
+        Err(e) => return e.into(),
+    };
+    let elements = match range(start, stop, list.len()) {
+        Some((start, stop)) => list
+            .range(start..=stop)
+            .map(|element| OperationResult::StringRes(element.to_vec()))
+            .collect(),
+        None => vec![],
+    };
+    OperationResult::Array(elements)
+}
+
+pub fn lindex(ctx: &mut Context, req: &Request) -> OperationResult {
+    let Some(i) = parse_int(&req.arguments()[1]) else {
+        return OperationError::NotAnInteger.into();
+    };
+    match read_list(ctx.repo, &req.arguments()[0]) {
+        Ok(Some(list)) => match index(i, list.len()) {
+            Some(i) => OperationResult::StringRes(list[i].to_vec()),
+            None => OperationResult::Nil,
+        },
+        Ok(None) => OperationResult::Nil,
+        Err(e) => e.into(),
+    }
+}
+
+pub fn lset(ctx: &mut Context, req: &Request) -> OperationResult {
+    let Some(i) = parse_int(&req.arguments()[1]) else {
+        return OperationError::NotAnInteger.into();
+    };
+    let list = match read_list(ctx.repo, &req.arguments()[0]) {
+        Ok(Some(list)) => list,
+        Ok(None) => return OperationError::NoSuchKey.into(),
+        Err(e) => return e.into(),
+    };
+    match index(i, list.len()) {
+        Some(i) => {
+            list[i] = req.arguments()[2].to_vec();
+            OperationResult::Ok
+        }
+        None => OperationError::IndexOutOfRange.into(),
+    }
+}
+
+/// `LINSERT key BEFORE | AFTER pivot element`
+pub fn linsert(ctx: &mut Context, req: &Request) -> OperationResult {
+    let args = req.arguments();
+    let after = if args[1].eq_ignore_ascii_case(b"BEFORE") {
+        false
+    } else if args[1].eq_ignore_ascii_case(b"AFTER") {
+        true
+    } else {
+        return OperationError::Syntax.into();
+    };
+    let list = match read_list(ctx.repo, &args[0]) {
+        Ok(Some(list)) => list,
+        Ok(None) => return OperationResult::Int(0),
+        Err(e) => return e.into(),
+    };
+    match list.iter().position(|element| *element == args[2]) {
+        Some(pivot) => {
+            list.insert(pivot + after as usize, args[3].to_vec());
+            OperationResult::Int(list.len() as i64)
+        }
+        None => OperationResult::Int(-1),
+    }
+}
+
+/// `LREM key count element` removes up to `count` occurrences of `element`
+/// starting from the head, or from the tail when `count` is negative. Zero
+/// removes all of them.
+pub fn lrem(ctx: &mut Context, req: &Request) -> OperationResult {
+    let key = &req.arguments()[0];
+    let Some(count) = parse_int(&req.arguments()[1]) else {
+        return OperationError::NotAnInteger.into();
+    };
+    let target = &req.arguments()[2];
+    let list = match read_list(ctx.repo, key) {
+        Ok(Some(list)) => list,
+        Ok(None) => return OperationResult::Int(0),
+        Err(e) => return e.into(),
+    };
+
+    let limit = if count == 0 { usize::MAX } else { count.unsigned_abs() as usize };
+    let mut removed = 0;
+    let mut remove_next = |element: &Vec<u8>| {
+        let remove = removed < limit && element == target;
+        removed += remove as usize;
+        !remove
+    };
+    if count < 0 {
+        list.make_contiguous().reverse();
+        list.retain(&mut remove_next);
+        list.make_contiguous().reverse();
+    } else {
+        list.retain(&mut remove_next);
+    }
+
+    remove_if_empty(ctx.repo, key);
+    OperationResult::Int(removed as i64)
+}
+
+pub fn ltrim(ctx: &mut Context, req: &Request) -> OperationResult {
+    let key = &req.arguments()[0];
+    let (Some(start), Some(stop)) = (parse_int(&req.arguments()[1]), parse_int(&req.arguments()[2]))
+    else {
+        return OperationError::NotAnInteger.into();
+    };
+    let list = match read_list(ctx.repo, key) {
+        Ok(Some(list)) => list,
+        Ok(None) => return OperationResult::Ok,
+        Err(e) => return e.into(),
+    };
+    match range(start, stop, list.len()) {
+        Some((start, stop)) => {
+            list.truncate(stop + 1);
+            list.drain(..start);
+        }
+        None => list.clear(),
+    }
+    remove_if_empty(ctx.repo, key);
+    OperationResult::Ok
+}
+
+/// `LPOS key element [RANK rank] [COUNT num-matches] [MAXLEN len]`
+pub fn lpos(ctx: &mut Context, req: &Request) -> OperationResult {
+    let args = req.arguments();
+    let mut rank = 1;
+    let mut count = None;
+    let mut max_len = 0;
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        let Some(value) = options.next() else {
+            return OperationError::Syntax.into();
+        };
+        let Some(value) = parse_int(value) else {
+            return OperationError::NotAnInteger.into();
+        };
+        match option.to_ascii_uppercase().as_slice() {
+            b"RANK" if value == 0 => return OperationError::ZeroRank.into(),
+            b"RANK" => rank = value,
+            b"COUNT" if value < 0 => return OperationError::NegativeCount.into(),
+            b"COUNT" => count = Some(value as usize),
+            b"MAXLEN" if value < 0 => return OperationError::NegativeMaxLen.into(),
+            b"MAXLEN" => max_len = value as usize,
+            _ => return OperationError::Syntax.into(),
+        }
+    }
+
+    let list = match read_list(ctx.repo, &args[0]) {
+        Ok(list) => list,
+        Err(e) => return e.into(),
+    };
+    let scanned = match max_len {
+        0 => usize::MAX,
+        max_len => max_len,
+    };
+    let wanted = match count {
+        Some(0) => usize::MAX,
+        Some(count) => count,
+        None => 1,
+    };
+    let len = list.as_ref().map_or(0, |list| list.len());
+    let positions: Box<dyn Iterator<Item = usize>> = if rank > 0 {
+        Box::new(0..len)
+    } else {
+        Box::new((0..len).rev())
+    };
+    let matches: Vec<OperationResult> = positions
+        .take(scanned)
+        .filter(|i| list.as_ref().is_some_and(|list| list[*i] == args[1]))
+        .skip(rank.unsigned_abs() as usize - 1)
+        .take(wanted)
+        .map(|i| OperationResult::Int(i as i64))
+        .collect();
+
+    match count {
+        Some(_) => OperationResult::Array(matches),
+        None => matches.into_iter().next().unwrap_or(OperationResult::Nil),
+    }
+}
+
+/// Pops an element from one end of `source` and pushes it onto one end of
+/// `destination`, which may be the same list. Both keys are type checked
+/// before anything moves.
+fn move_element(
+    repo: &mut Repository,
+    source: &[u8],
+    destination: &[u8],
+    from: End,
+    to: End,
+) -> Result<Option<Vec<u8>>, OperationError> {
+    read_list(repo, destination)?;
+    let Some(list) = read_list(repo, source)? else {
+        return Ok(None);
+    };
+    let Some(element) = from.pop(list) else {
+        return Ok(None);
+    };
+    remove_if_empty(repo, source);
+    to.push(list_for_update(repo, destination)?, element.clone());
+    repo.signal_ready(destination);
+    Ok(Some(element))
+}
+
+/// `LMOVE source destination LEFT | RIGHT LEFT | RIGHT`
+pub fn lmove(ctx: &mut Context, req: &Request) -> OperationResult {
+    let args = req.arguments();
+    let ends = End::parse(&args[2]).and_then(|from| Ok((from, End::parse(&args[3])?)));
+    let (from, to) = match ends {
+        Ok(ends) => ends,
+        Err(e) => return e.into(),
+    };
+    match move_element(ctx.repo, &args[0], &args[1], from, to) {
+        Ok(Some(element)) => OperationResult::StringRes(element),
+        Ok(None) => OperationResult::Nil,
+        Err(e) => e.into(),
+    }
+}
+
+/// A key along with the elements popped from it.
+type Popped = (Vec<u8>, Vec<Vec<u8>>);
+
+/// Pops up to `count` elements from the first of `keys` that holds a
+/// non-empty list, returning that key along with them. Keys are type checked
+/// in order until one is found.
+fn pop_first(
+    repo: &mut Repository,
+    keys: &[Vec<u8>],
+    end: End,
+    count: usize,
+) -> Result<Option<Popped>, OperationError> {
+    for key in keys {
+        if let Some(list) = read_list(repo, key)? {
+            let elements = std::iter::from_fn(|| end.pop(list)).take(count).collect();
+            remove_if_empty(repo, key);
+            return Ok(Some((key.to_vec(), elements)));
+        }
+    }
+    Ok(None)
+}
+
+/// `BLPOP key [key ...] timeout` and `BRPOP key [key ...] timeout`
+fn blocking_pop(ctx: &mut Context, req: &Request, end: End) -> OperationResult {
+    let (timeout, keys) = req.arguments().split_last().expect("arity is checked");
+    let deadline = match parse_timeout(timeout) {
+        Ok(deadline) => deadline,
+        Err(e) => return e.into(),
+    };
+    match pop_first(ctx.repo, keys, end, 1) {
+        Ok(Some((key, mut elements))) => OperationResult::Array(vec![
+            OperationResult::StringRes(key),
+            OperationResult::StringRes(elements.remove(0)),
+        ]),
+        Ok(None) => ctx.block(keys, deadline),
+        Err(e) => e.into(),
+    }
+}
+
+pub fn blpop(ctx: &mut Context, req: &Request) -> OperationResult {
+    blocking_pop(ctx, req, End::Left)
+}
+
+pub fn brpop(ctx: &mut Context, req: &Request) -> OperationResult {
+    blocking_pop(ctx, req, End::Right)
+}
+
+/// `BLMOVE source destination LEFT | RIGHT LEFT | RIGHT timeout`
+pub fn blmove(ctx: &mut Context, req: &Request) -> OperationResult {
+    let args = req.arguments();
+    let ends = End::parse(&args[2]).and_then(|from| Ok((from, End::parse(&args[3])?)));
+    let ((from, to), deadline) = match ends.and_then(|ends| Ok((ends, parse_timeout(&args[4])?))) {
+        Ok(parsed) => parsed,
+        Err(e) => return e.into(),
+    };
+    match move_element(ctx.repo, &args[0], &args[1], from, to) {
+        Ok(Some(element)) => OperationResult::StringRes(element),
+        Ok(None) => ctx.block(&args[..1], deadline),
+        Err(e) => e.into(),
+    }
+}
+
+/// Parses `numkeys key [key ...] LEFT | RIGHT [COUNT count]`, the arguments
+/// shared by `LMPOP` and `BLMPOP`.
+fn parse_mpop(args: &[Vec<u8>]) -> Result<(&[Vec<u8>], End, usize), OperationError> {
+    let numkeys = parse_int(&args[0]).ok_or(OperationError::NotAnInteger)?;
+    if numkeys <= 0 {
+        return Err(OperationError::NumKeys);
+    }
+    let numkeys = numkeys as usize;
+    if args.len() < numkeys + 2 {
+        return Err(OperationError::Syntax);
+    }
+    let end = End::parse(&args[numkeys + 1])?;
+    let count = match &args[numkeys + 2..] {
+        [] => 1,
+        [option, count] if option.eq_ignore_ascii_case(b"COUNT") => match parse_int(count) {
+            Some(count) if count > 0 => count as usize,
+            _ => return Err(OperationError::CountNotPositive),
+        },
+        _ => return Err(OperationError::Syntax),
+    };
+    Ok((&args[1..=numkeys], end, count))
+}
+
+fn mpop_reply(key: Vec<u8>, elements: Vec<Vec<u8>>) -> OperationResult {
+    OperationResult::Array(vec![
+        OperationResult::StringRes(key),
+        OperationResult::Array(elements.into_iter().map(OperationResult::StringRes).collect()),
+    ])
+}
+
+/// `LMPOP numkeys key [key ...] LEFT | RIGHT [COUNT count]`
+pub fn lmpop(ctx: &mut Context, req: &Request) -> OperationResult {
+    let (keys, end, count) = match parse_mpop(req.arguments()) {
+        Ok(parsed) => parsed,
+        Err(e) => return e.into(),
+    };
+    match pop_first(ctx.repo, keys, end, count) {
+        Ok(Some((key, elements))) => mpop_reply(key, elements),
+        Ok(None) => OperationResult::NullArray,
+        Err(e) => e.into(),
+    }
+}
+
+/// `BLMPOP timeout numkeys key [key ...] LEFT | RIGHT [COUNT count]`
+pub fn blmpop(ctx: &mut Context, req: &Request) -> OperationResult {
+    let args = req.arguments();
+    let parsed = parse_timeout(&args[0])
+        .and_then(|deadline| Ok((deadline, parse_mpop(&args[1..])?)));
+    let (deadline, (keys, end, count)) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => return e.into(),
+    };
+    match pop_first(ctx.repo, keys, end, count) {
+        Ok(Some((key, elements))) => mpop_reply(key, elements),
+        Ok(None) => ctx.block(keys, deadline),
+        Err(e) => e.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{operations::exec, repository::Repository};
+
+    use super::*;
+
+    fn bulk(s: &str) -> OperationResult {
+        OperationResult::StringRes(s.as_bytes().to_vec())
+    }
+
+    fn bulks(items: &[&str]) -> OperationResult {
+        OperationResult::Array(items.iter().map(|item| bulk(item)).collect())
+    }
+
+    fn ints(items: &[i64]) -> OperationResult {
+        OperationResult::Array(items.iter().map(|item| OperationResult::Int(*item)).collect())
+    }
+
+    #[test]
+    fn test_push_and_range() {
+        let mut repo = Repository::new();
+        assert_eq!(exec(&mut repo, &["RPUSH", "l", "b", "c"]), OperationResult::Int(2));
+        assert_eq!(exec(&mut repo, &["LPUSH", "l", "a", "z"]), OperationResult::Int(4));
+        assert_eq!(exec(&mut repo, &["LRANGE", "l", "0", "-1"]), bulks(&["z", "a", "b", "c"]));
+        assert_eq!(exec(&mut repo, &["LRANGE", "l", "-3", "2"]), bulks(&["a", "b"]));
+        assert_eq!(exec(&mut repo, &["LRANGE", "l", "-100", "100"]), bulks(&["z", "a", "b", "c"]));
+        assert_eq!(exec(&mut repo, &["LRANGE", "l", "5", "10"]), bulks(&[]));
+        assert_eq!(exec(&mut repo, &["LRANGE", "l", "2", "1"]), bulks(&[]));
+        assert_eq!(exec(&mut repo, &["LLEN", "l"]), OperationResult::Int(4));
+
+        assert_eq!(exec(&mut repo, &["LPUSHX", "missing", "a"]), OperationResult::Int(0));
+        assert_eq!(exec(&mut repo, &["RPUSHX", "l", "d"]), OperationResult::Int(5));
+        assert_eq!(repo.get(b"missing"), None);
+
+        exec(&mut repo, &["SET", "s", "v"]);
+        assert_eq!(exec(&mut repo, &["LPUSH", "s", "a"]), OperationError::WrongType.into());
+        assert_eq!(exec(&mut repo, &["LPUSHX", "s", "a"]), OperationError::WrongType.into());
+        assert_eq!(exec(&mut repo, &["LLEN", "s"]), OperationError::WrongType.into());
+    }
+
+    #[test]
+    fn test_pop() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["RPUSH", "l", "a", "b", "c", "d"]);
+        assert_eq!(exec(&mut repo, &["LPOP", "l"]), bulk("a"));
+        assert_eq!(exec(&mut repo, &["RPOP", "l"]), bulk("d"));
+        assert_eq!(exec(&mut repo, &["LPOP", "l", "0"]), bulks(&[]));
+        assert_eq!(exec(&mut repo, &["RPOP", "l", "5"]), bulks(&["c", "b"]));
+        assert_eq!(repo.get(b"l"), None);
+        assert_eq!(exec(&mut repo, &["LPOP", "l"]), OperationResult::Nil);
+        assert_eq!(exec(&mut repo, &["LPOP", "l", "2"]), OperationResult::NullArray);
+        assert_eq!(exec(&mut repo, &["LPOP", "l", "-1"]), OperationError::MustBePositive.into());
+        assert_eq!(exec(&mut repo, &["LPOP", "l", "1", "2"]), OperationError::WrongArity("lpop").into());
+    }
+
+    #[test]
+    fn test_index_and_set() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["RPUSH", "l", "a", "b", "c"]);
+        assert_eq!(exec(&mut repo, &["LINDEX", "l", "0"]), bulk("a"));
+        assert_eq!(exec(&mut repo, &["LINDEX", "l", "-1"]), bulk("c"));
+        assert_eq!(exec(&mut repo, &["LINDEX", "l", "3"]), OperationResult::Nil);
+        assert_eq!(exec(&mut repo, &["LINDEX", "l", "-4"]), OperationResult::Nil);
+
+        assert_eq!(exec(&mut repo, &["LSET", "l", "-2", "B"]), OperationResult::Ok);
+        assert_eq!(exec(&mut repo, &["LINDEX", "l", "1"]), bulk("B"));
+        assert_eq!(exec(&mut repo, &["LSET", "l", "3", "x"]), OperationError::IndexOutOfRange.into());
+        assert_eq!(exec(&mut repo, &["LSET", "missing", "0", "x"]), OperationError::NoSuchKey.into());
+    }
+
+    #[test]
+    fn test_linsert() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["RPUSH", "l", "a", "c"]);
+        assert_eq!(exec(&mut repo, &["LINSERT", "l", "BEFORE", "c", "b"]), OperationResult::Int(3));
+        assert_eq!(exec(&mut repo, &["LINSERT", "l", "after", "c", "d"]), OperationResult::Int(4));
+        assert_eq!(exec(&mut repo, &["LRANGE", "l", "0", "-1"]), bulks(&["a", "b", "c", "d"]));
+        assert_eq!(exec(&mut repo, &["LINSERT", "l", "BEFORE", "z", "y"]), OperationResult::Int(-1));
+        assert_eq!(exec(&mut repo, &["LINSERT", "missing", "BEFORE", "a", "b"]), OperationResult::Int(0));
+        assert_eq!(exec(&mut repo, &["LINSERT", "l", "NEAR", "a", "b"]), OperationError::Syntax.into());
+    }
+
+    #[test]
+    fn test_lrem() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["RPUSH", "l", "x", "a", "x", "b", "x"]);
+        assert_eq!(exec(&mut repo, &["LREM", "l", "-2", "x"]), OperationResult::Int(2));
+        assert_eq!(exec(&mut repo, &["LRANGE", "l", "0", "-1"]), bulks(&["x", "a", "b"]));
+
+        exec(&mut repo, &["RPUSH", "l", "x", "x"]);
+        assert_eq!(exec(&mut repo, &["LREM", "l", "1", "x"]), OperationResult::Int(1));
+        assert_eq!(exec(&mut repo, &["LRANGE", "l", "0", "-1"]), bulks(&["a", "b", "x", "x"]));
+        assert_eq!(exec(&mut repo, &["LREM", "l", "0", "x"]), OperationResult::Int(2));
+        assert_eq!(exec(&mut repo, &["LREM", "l", "0", "a"]), OperationResult::Int(1));
+        assert_eq!(exec(&mut repo, &["LREM", "l", "0", "b"]), OperationResult::Int(1));
+        assert_eq!(repo.get(b"l"), None);
+    }
+
+    #[test]
+    fn test_ltrim() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["RPUSH", "l", "a", "b", "c", "d", "e"]);
+        assert_eq!(exec(&mut repo, &["LTRIM", "l", "1", "-2"]), OperationResult::Ok);
+        assert_eq!(exec(&mut repo, &["LRANGE", "l", "0", "-1"]), bulks(&["b", "c", "d"]));
+        assert_eq!(exec(&mut repo, &["LTRIM", "l", "-100", "100"]), OperationResult::Ok);
+        assert_eq!(exec(&mut repo, &["LLEN", "l"]), OperationResult::Int(3));
+        assert_eq!(exec(&mut repo, &["LTRIM", "l", "5", "10"]), OperationResult::Ok);
+        assert_eq!(repo.get(b"l"), None);
+    }
+
+    #[test]
+    fn test_lpos() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["RPUSH", "l", "a", "b", "c", "1", "2", "3", "c", "c"]);
+        assert_eq!(exec(&mut repo, &["LPOS", "l", "c"]), OperationResult::Int(2));
+        assert_eq!(exec(&mut repo, &["LPOS", "l", "c", "RANK", "2"]), OperationResult::Int(6));
+        assert_eq!(exec(&mut repo, &["LPOS", "l", "c", "RANK", "-1"]), OperationResult::Int(7));
+        assert_eq!(exec(&mut repo, &["LPOS", "l", "c", "COUNT", "2"]), ints(&[2, 6]));
+        assert_eq!(exec(&mut repo, &["LPOS", "l", "c", "COUNT", "0"]), ints(&[2, 6, 7]));
+        assert_eq!(exec(&mut repo, &["LPOS", "l", "c", "RANK", "-1", "COUNT", "2"]), ints(&[7, 6]));
+        assert_eq!(exec(&mut repo, &["LPOS", "l", "c", "COUNT", "0", "MAXLEN", "7"]), ints(&[2, 6]));
+        assert_eq!(exec(&mut repo, &["LPOS", "l", "z"]), OperationResult::Nil);
+        assert_eq!(exec(&mut repo, &["LPOS", "l", "z", "COUNT", "1"]), ints(&[]));
+        assert_eq!(exec(&mut repo, &["LPOS", "missing", "z"]), OperationResult::Nil);
+
+        assert_eq!(exec(&mut repo, &["LPOS", "l", "c", "RANK", "0"]), OperationError::ZeroRank.into());
+        assert_eq!(exec(&mut repo, &["LPOS", "l", "c", "COUNT", "-1"]), OperationError::NegativeCount.into());
+        assert_eq!(exec(&mut repo, &["LPOS", "l", "c", "MAXLEN", "-1"]), OperationError::NegativeMaxLen.into());
+        assert_eq!(exec(&mut repo, &["LPOS", "l", "c", "RANK"]), OperationError::Syntax.into());
+    }
+
+    #[test]
+    fn test_lmpop() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["RPUSH", "b", "1", "2", "3"]);
+        assert_eq!(
+            exec(&mut repo, &["LMPOP", "2", "a", "b", "RIGHT", "COUNT", "2"]),
+            OperationResult::Array(vec![bulk("b"), bulks(&["3", "2"])])
+        );
+        assert_eq!(
+            exec(&mut repo, &["LMPOP", "2", "a", "b", "LEFT"]),
+            OperationResult::Array(vec![bulk("b"), bulks(&["1"])])
+        );
+        assert_eq!(exec(&mut repo, &["LMPOP", "2", "a", "b", "LEFT"]), OperationResult::NullArray);
+
+        assert_eq!(exec(&mut repo, &["LMPOP", "0", "a", "LEFT"]), OperationError::NumKeys.into());
+        assert_eq!(exec(&mut repo, &["LMPOP", "3", "a", "LEFT"]), OperationError::Syntax.into());
+        assert_eq!(
+            exec(&mut repo, &["LMPOP", "1", "a", "LEFT", "COUNT", "0"]),
+            OperationError::CountNotPositive.into()
+        );
+    }
+
+    #[test]
+    fn test_blocking_pops_serve_available_elements() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["RPUSH", "b", "1", "2"]);
+        assert_eq!(
+            exec(&mut repo, &["BLPOP", "a", "b", "0"]),
+            OperationResult::Array(vec![bulk("b"), bulk("1")])
+        );
+        assert_eq!(
+            exec(&mut repo, &["BRPOP", "a", "b", "0.5"]),
+            OperationResult::Array(vec![bulk("b"), bulk("2")])
+        );
+        exec(&mut repo, &["RPUSH", "b", "3"]);
+        assert_eq!(exec(&mut repo, &["BLMOVE", "b", "c", "LEFT", "LEFT", "0"]), bulk("3"));
+        exec(&mut repo, &["RPUSH", "b", "4"]);
+        assert_eq!(
+            exec(&mut repo, &["BLMPOP", "0", "1", "b", "LEFT", "COUNT", "5"]),
+            OperationResult::Array(vec![bulk("b"), bulks(&["4"])])
+        );
+
+        assert_eq!(exec(&mut repo, &["BLPOP", "a", "-1"]), OperationError::NegativeTimeout.into());
+        assert_eq!(exec(&mut repo, &["BLPOP", "a", "soon"]), OperationError::InvalidTimeout.into());
+        exec(&mut repo, &["SET", "s", "v"]);
+        assert_eq!(exec(&mut repo, &["BLPOP", "s", "0"]), OperationError::WrongType.into());
+    }
+
+    #[test]
+    fn test_lmove() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["RPUSH", "src", "a", "b", "c"]);
+        assert_eq!(exec(&mut repo, &["LMOVE", "src", "dst", "RIGHT", "LEFT"]), bulk("c"));
+        assert_eq!(exec(&mut repo, &["LMOVE", "src", "dst", "LEFT", "RIGHT"]), bulk("a"));
+        assert_eq!(exec(&mut repo, &["LRANGE", "dst", "0", "-1"]), bulks(&["c", "a"]));
+
+        assert_eq!(exec(&mut repo, &["LMOVE", "dst", "dst", "LEFT", "RIGHT"]), bulk("c"));
+        assert_eq!(exec(&mut repo, &["LRANGE", "dst", "0", "-1"]), bulks(&["a", "c"]));
+
+        assert_eq!(exec(&mut repo, &["LMOVE", "src", "src", "LEFT", "LEFT"]), bulk("b"));
+        assert_eq!(exec(&mut repo, &["LRANGE", "src", "0", "-1"]), bulks(&["b"]));
+
+        assert_eq!(exec(&mut repo, &["LMOVE", "missing", "dst", "LEFT", "LEFT"]), OperationResult::Nil);
+        assert_eq!(exec(&mut repo, &["LMOVE", "src", "dst", "UP", "LEFT"]), OperationError::Syntax.into());
+
+        exec(&mut repo, &["SET", "s", "v"]);
+        assert_eq!(exec(&mut repo, &["LMOVE", "src", "s", "LEFT", "LEFT"]), OperationError::WrongType.into());
+        assert_eq!(exec(&mut repo, &["LLEN", "src"]), OperationResult::Int(1));
+    }
+}
@@ -1,8 +1,57 @@
-use crate::{repository::Repository, request::Request};
+use std::{fmt::Write, process};
 
-use super::OperationResult;
+use crate::request::Request;
 
-pub fn flush_all(repo: &mut Repository, _: &Request) -> OperationResult {
-    repo.clear();
+use super::{Context, OperationError, OperationResult};
+
+pub fn flush_all(ctx: &mut Context, _: &Request) -> OperationResult {
+    ctx.repo.clear();
     OperationResult::Ok
-}
\ No newline at end of file
+}
+
+pub fn ping(_: &mut Context, req: &Request) -> OperationResult {
+    match req.arguments() {
+        [] => OperationResult::Status("PONG".to_string()),
+        [message] => OperationResult::StringRes(message.to_vec()),
+        _ => OperationError::WrongArity("ping").into(),
+    }
+}
+
+
+/// `INFO [section ...]`
+pub fn info(ctx: &mut Context, req: &Request) -> OperationResult {
+    let wants = |section: &str| {
+        req.arguments().is_empty()
+            || req.arguments().iter().any(|arg| {
+                arg.eq_ignore_ascii_case(section.as_bytes())
+                    || arg.eq_ignore_ascii_case(b"all")
+                    || arg.eq_ignore_ascii_case(b"default")
+            })
+    };
+
+    let mut info = String::new();
+    if wants("server") {
+        let config_file = ctx.config.file.as_ref().map(|f| f.display().to_string());
+        let _ = write!(
+            info,
+            "# Server\r\nmuna_version:{}\r\nprocess_id:{}\r\ntcp_port:{}\r\nconfig_file:{}\r\n",
+            env!("CARGO_PKG_VERSION"),
+            process::id(),
+            ctx.config.port,
+            config_file.unwrap_or_default(),
+        );
+    }
+    if wants("stats") {
+        if !info.is_empty() {
+            info.push_str("\r\n");
+        }
+        let _ = write!(
+            info,
+            "# Stats\r\ntotal_connections_received:{}\r\ntotal_commands_processed:{}\r\nrejected_connections:{}\r\n",
+            ctx.stats.total_connections_received,
+            ctx.stats.total_commands_processed,
+            ctx.stats.rejected_connections,
+        );
+    }
+    OperationResult::Verbatim(info)
+}
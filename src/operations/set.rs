@@ -0,0 +1,496 @@
+use std::collections::HashSet;
+
+use rand::seq::{IteratorRandom, SliceRandom};
+
+use crate::{record::Record, repository::Repository, request::Request};
+
+use super::{check_random_count, parse_int, Context, OperationError, OperationResult};
+
+type Set = HashSet<Vec<u8>>;
+
+/// Borrows the set stored at `key`, or `None` if the key doesn't exist.
+/// Keys holding other types fail with `WRONGTYPE`.
+fn read_set<'a>(
+    repo: &'a mut Repository,
+    key: &[u8],
+) -> Result<Option<&'a mut Set>, OperationError> {
+    match repo.get_mut(key) {
+        Some(Record::Set(set)) => Ok(Some(set)),
+        Some(_) => Err(OperationError::WrongType),
+        None => Ok(None),
+    }
+}
+
+/// Borrows the set stored at `key`, creating an empty one if the key
+/// doesn't exist.
+fn set_for_update<'a>(repo: &'a mut Repository, key: &[u8]) -> Result<&'a mut Set, OperationError> {
+    if repo.get_mut(key).is_none() {
+        repo.set(key.to_vec(), Record::Set(HashSet::new()));
+    }
+    read_set(repo, key).map(|set| set.expect("set was just created"))
+}
+
+/// Drops a key whose set has no members left, as Redis never keeps empty
+/// collections around.
+fn remove_if_empty(repo: &mut Repository, key: &[u8]) {
+    if let Some(Record::Set(set)) = repo.get_mut(key) {
+        if set.is_empty() {
+            repo.delete(key);
+        }
+    }
+}
+
+fn members_reply<'a>(members: impl IntoIterator<Item = &'a Vec<u8>>) -> OperationResult {
+    OperationResult::Set(
+        members
+            .into_iter()
+            .map(|member| OperationResult::StringRes(member.to_vec()))
+            .collect(),
+    )
+}
+
+pub fn sadd(ctx: &mut Context, req: &Request) -> OperationResult {
+    let set = match set_for_update(ctx.repo, &req.arguments()[0]) {
+        Ok(set) => set,
+        Err(e) => return e.into(),
+    };
+    let added = req.arguments()[1..]
+        .iter()
+        .filter(|member| set.insert(member.to_vec()))
+        .count();
+    OperationResult::Int(added as i64)
+}
+
+pub fn srem(ctx: &mut Context, req: &Request) -> OperationResult {
+    let key = &req.arguments()[0];
+    let removed = match read_set(ctx.repo, key) {
+        Ok(Some(set)) => req.arguments()[1..]
+            .iter()
+            .filter(|member| set.remove(*member))
+            .count(),
+        Ok(None) => 0,
+        Err(e) => return e.into(),
+    };
+    remove_if_empty(ctx.repo, key);
+    OperationResult::Int(removed as i64)
+}
+
+pub fn smembers(ctx: &mut Context, req: &Request) -> OperationResult {
+    match read_set(ctx.repo, &req.arguments()[0]) {
+        Ok(set) => members_reply(set.iter().flat_map(|set| set.iter())),
+        Err(e) => e.into(),
+    }
+}
+
+pub fn sismember(ctx: &mut Context, req: &Request) -> OperationResult {
+    match read_set(ctx.repo, &req.arguments()[0]) {
+        Ok(set) => {
+            let contains = set.is_some_and(|set| set.contains(&req.arguments()[1]));
+            OperationResult::Int(contains as i64)
+        }
+        Err(e) => e.into(),
+    }
+}
+
+pub fn smismember(ctx: &mut Context, req: &Request) -> OperationResult {
+    let set = match read_set(ctx.repo, &req.arguments()[0]) {
+        Ok(set) => set,
+        Err(e) => return e.into(),
+    };
+    OperationResult::Array(
+        req.arguments()[1..]
+            .iter()
+            .map(|member| {
+                let contains = set.as_ref().is_some_and(|set| set.contains(member));
+                OperationResult::Int(contains as i64)
+            })
+            .collect(),
+    )
+}
+
+pub fn scard(ctx: &mut Context, req: &Request) -> OperationResult {
+    match read_set(ctx.repo, &req.arguments()[0]) {
+        Ok(set) => OperationResult::Int(set.map_or(0, |set| set.len()) as i64),
+        Err(e) => e.into(),
+    }
+}
+
+/// Parses the optional count of `SPOP` and `SRANDMEMBER`, rejecting
+/// anything past it.
+fn parse_count(args: &[Vec<u8>], command: &'static str) -> Result<Option<i64>, OperationError> {
+    match args {
+        [_] => Ok(None),
+        [_, count] => parse_int(count).map(Some).ok_or(OperationError::NotAnInteger),
+        _ => Err(OperationError::WrongArity(command)),
+    }
+}
+
+/// `SPOP key [count]` removes and returns random members.
+pub fn spop(ctx: &mut Context, req: &Request) -> OperationResult {
+    let key = &req.arguments()[0];
+    let count = match parse_count(req.arguments(), "spop") {
+        Ok(Some(count)) if count < 0 => return OperationError::MustBePositive.into(),
+        Ok(count) => count,
+        Err(e) => return e.into(),
+    };
+    let set = match read_set(ctx.repo, key) {
+        Ok(Some(set)) => set,
+        Ok(None) if count.is_some() => return OperationResult::Set(vec![]),
+        Ok(None) => return OperationResult::Nil,
+        Err(e) => return e.into(),
+    };
+
+    let mut rng = rand::thread_rng();
+    let amount = count.map_or(1, |count| count.min(set.len() as i64) as usize);
+    let picked: Vec<Vec<u8>> = set
+        .iter()
+        .choose_multiple(&mut rng, amount)
+        .into_iter()
+        .cloned()
+        .collect();
+    for member in &picked {
+        set.remove(member);
+    }
+    remove_if_empty(ctx.repo, key);
+
+    match count {
+        Some(_) => members_reply(&picked),
+        None => picked
+            .into_iter()
+            .next()
+            .map_or(OperationResult::Nil, OperationResult::StringRes),
+    }
+}
+
+/// `SRANDMEMBER key [count]`
+///
+/// A positive count picks that many distinct members, a negative one allows
+/// the same member to be picked more than once.
+pub fn srandmember(ctx: &mut Context, req: &Request) -> OperationResult {
+    let count = parse_count(req.arguments(), "srandmember")
+        .and_then(|count| count.map(check_random_count).transpose());
+    let count = match count {
+        Ok(count) => count,
+        Err(e) => return e.into(),
+    };
+    let set = match read_set(ctx.repo, &req.arguments()[0]) {
+        Ok(set) => set,
+        Err(e) => return e.into(),
+    };
+
+    let mut rng = rand::thread_rng();
+    let Some(count) = count else {
+        return match set.and_then(|set| set.iter().choose(&mut rng)) {
+            Some(member) => OperationResult::StringRes(member.to_vec()),
+            None => OperationResult::Nil,
+        };
+    };
+    let Some(set) = set else {
+        return OperationResult::Array(vec![]);
+    };
+
+    // Distinct members are sampled in a single pass over the set; repeats
+    // index into a snapshot so that each pick is constant time.
+    let picked: Vec<_> = if count >= 0 {
+        set.iter().choose_multiple(&mut rng, count.min(set.len() as i64) as usize)
+    } else {
+        let members: Vec<_> = set.iter().collect();
+        (0..count.unsigned_abs()).filter_map(|_| members.choose(&mut rng).copied()).collect()
+    };
+    OperationResult::Array(
+        picked
+            .into_iter()
+            .map(|member| OperationResult::StringRes(member.to_vec()))
+            .collect(),
+    )
+}
+
+/// `SMOVE source destination member`
+pub fn smove(ctx: &mut Context, req: &Request) -> OperationResult {
+    let args = req.arguments();
+    let (source, destination, member) = (&args[0], &args[1], &args[2]);
+    if let Err(e) = read_set(ctx.repo, destination) {
+        return e.into();
+    }
+    let removed = match read_set(ctx.repo, source) {
+        Ok(Some(set)) => set.remove(member),
+        Ok(None) => false,
+        Err(e) => return e.into(),
+    };
+    if !removed {
+        return OperationResult::Int(0);
+    }
+    remove_if_empty(ctx.repo, source);
+    match set_for_update(ctx.repo, destination) {
+        Ok(set) => {
+            set.insert(member.to_vec());
+            OperationResult::Int(1)
+        }
+        Err(e) => e.into(),
+    }
+}
+
+/// Reads the sets at each of `keys`, treating missing keys as empty sets.
+/// Fails with `WRONGTYPE` if any key holds another type.
+fn read_sets(repo: &mut Repository, keys: &[Vec<u8>]) -> Result<Vec<Set>, OperationError> {
+    keys.iter()
+        .map(|key| Ok(read_set(repo, key)?.cloned().unwrap_or_default()))
+        .collect()
+}
+
+/// Members of the first set that are in all of the others.
+fn intersection(mut sets: Vec<Set>) -> Set {
+    sets.sort_by_key(|set| set.len());
+    let mut sets = sets.into_iter();
+    let smallest = sets.next().unwrap_or_default();
+    let rest: Vec<Set> = sets.collect();
+    smallest
+        .into_iter()
+        .filter(|member| rest.iter().all(|set| set.contains(member)))
+        .collect()
+}
+
+fn union(sets: Vec<Set>) -> Set {
+    sets.into_iter().flatten().collect()
+}
+
+/// Members of the first set that are in none of the others.
+fn difference(sets: Vec<Set>) -> Set {
+    let mut sets = sets.into_iter();
+    let first = sets.next().unwrap_or_default();
+    let rest: Vec<Set> = sets.collect();
+    first
+        .into_iter()
+        .filter(|member| !rest.iter().any(|set| set.contains(member)))
+        .collect()
+}
+
+fn combine(ctx: &mut Context, keys: &[Vec<u8>], op: fn(Vec<Set>) -> Set) -> OperationResult {
+    match read_sets(ctx.repo, keys) {
+        Ok(sets) => members_reply(&op(sets)),
+        Err(e) => e.into(),
+    }
+}
+
+/// Stores the result of a set operation at the first argument, replacing
+/// whatever was there, and replies with its size.
+fn combine_and_store(
+    ctx: &mut Context,
+    args: &[Vec<u8>],
+    op: fn(Vec<Set>) -> Set,
+) -> OperationResult {
+    let (destination, keys) = args.split_first().expect("arity is checked");
+    let set = match read_sets(ctx.repo, keys) {
+        Ok(sets) => op(sets),
+        Err(e) => return e.into(),
+    };
+    let len = set.len();
+    if set.is_empty() {
+        ctx.repo.delete(destination);
+    } else {
+        ctx.repo.set(destination.to_vec(), Record::Set(set));
+        ctx.repo.persist(destination);
+    }
+    OperationResult::Int(len as i64)
+}
+
+pub fn sinter(ctx: &mut Context, req: &Request) -> OperationResult {
+    combine(ctx, req.arguments(), intersection)
+}
+
+pub fn sunion(ctx: &mut Context, req: &Request) -> OperationResult {
+    combine(ctx, req.arguments(), union)
+}
+
+pub fn sdiff(ctx: &mut Context, req: &Request) -> OperationResult {
+    combine(ctx, req.arguments(), difference)
+}
+
+pub fn sinterstore(ctx: &mut Context, req: &Request) -> OperationResult {
+    combine_and_store(ctx, req.arguments(), intersection)
+}
+
+pub fn sunionstore(ctx: &mut Context, req: &Request) -> OperationResult {
+    combine_and_store(ctx, req.arguments(), union)
+}
+
+pub fn sdiffstore(ctx: &mut Context, req: &Request) -> OperationResult {
+    combine_and_store(ctx, req.arguments(), difference)
+}
+
+/// `SINTERCARD numkeys key [key ...] [LIMIT limit]` counts the members of
+/// the intersection, stopping at `limit` if it isn't zero.
+pub fn sintercard(ctx: &mut Context, req: &Request) -> OperationResult {
+    let args = req.arguments();
+    let numkeys = match parse_int(&args[0]) {
+        Some(numkeys) if numkeys > 0 => numkeys as usize,
+        Some(_) => return OperationError::NumKeys.into(),
+        None => return OperationError::NotAnInteger.into(),
+    };
+    if args.len() < numkeys + 1 {
+        return OperationError::Syntax.into();
+    }
+    let limit = match &args[numkeys + 1..] {
+        [] => 0,
+        [option, limit] if option.eq_ignore_ascii_case(b"LIMIT") => match parse_int(limit) {
+            Some(limit) if limit >= 0 => limit as usize,
+            Some(_) => return OperationError::NegativeLimit.into(),
+            None => return OperationError::NotAnInteger.into(),
+        },
+        _ => return OperationError::Syntax.into(),
+    };
+    let sets = match read_sets(ctx.repo, &args[1..=numkeys]) {
+        Ok(sets) => sets,
+        Err(e) => return e.into(),
+    };
+    let len = intersection(sets).len();
+    OperationResult::Int(if limit == 0 { len } else { len.min(limit) } as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{operations::exec, repository::Repository};
+
+    use super::*;
+
+    fn members(reply: OperationResult) -> Vec<String> {
+        let (OperationResult::Set(items) | OperationResult::Array(items)) = reply else {
+            panic!("expected a set or an array, got {:?}", reply);
+        };
+        let mut members: Vec<String> = items
+            .into_iter()
+            .map(|item| match item {
+                OperationResult::StringRes(s) => String::from_utf8(s).unwrap(),
+                other => panic!("expected a bulk string, got {:?}", other),
+            })
+            .collect();
+        members.sort();
+        members
+    }
+
+    #[test]
+    fn test_membership() {
+        let mut repo = Repository::new();
+        assert_eq!(exec(&mut repo, &["SADD", "s", "a", "b", "a"]), OperationResult::Int(2));
+        assert_eq!(exec(&mut repo, &["SADD", "s", "b", "c"]), OperationResult::Int(1));
+        assert_eq!(members(exec(&mut repo, &["SMEMBERS", "s"])), ["a", "b", "c"]);
+        assert_eq!(exec(&mut repo, &["SCARD", "s"]), OperationResult::Int(3));
+        assert_eq!(exec(&mut repo, &["SISMEMBER", "s", "a"]), OperationResult::Int(1));
+        assert_eq!(exec(&mut repo, &["SISMEMBER", "s", "z"]), OperationResult::Int(0));
+        assert_eq!(
+            exec(&mut repo, &["SMISMEMBER", "s", "a", "z"]),
+            OperationResult::Array(vec![OperationResult::Int(1), OperationResult::Int(0)])
+        );
+
+        assert_eq!(exec(&mut repo, &["SREM", "s", "a", "z"]), OperationResult::Int(1));
+        assert_eq!(exec(&mut repo, &["SREM", "s", "b", "c"]), OperationResult::Int(2));
+        assert_eq!(repo.get(b"s"), None);
+        assert_eq!(exec(&mut repo, &["SMEMBERS", "s"]), OperationResult::Set(vec![]));
+        assert_eq!(exec(&mut repo, &["SCARD", "s"]), OperationResult::Int(0));
+
+        exec(&mut repo, &["SET", "str", "v"]);
+        assert_eq!(exec(&mut repo, &["SADD", "str", "a"]), OperationError::WrongType.into());
+        assert_eq!(exec(&mut repo, &["SISMEMBER", "str", "a"]), OperationError::WrongType.into());
+    }
+
+    #[test]
+    fn test_spop_and_srandmember() {
+        let mut repo = Repository::new();
+        assert_eq!(exec(&mut repo, &["SPOP", "s"]), OperationResult::Nil);
+        assert_eq!(exec(&mut repo, &["SPOP", "s", "2"]), OperationResult::Set(vec![]));
+        assert_eq!(exec(&mut repo, &["SRANDMEMBER", "s"]), OperationResult::Nil);
+        assert_eq!(exec(&mut repo, &["SRANDMEMBER", "s", "2"]), OperationResult::Array(vec![]));
+
+        exec(&mut repo, &["SADD", "s", "a", "b", "c"]);
+        assert_eq!(members(exec(&mut repo, &["SRANDMEMBER", "s", "10"])), ["a", "b", "c"]);
+        assert_eq!(members(exec(&mut repo, &["SRANDMEMBER", "s", "-5"])).len(), 5);
+        assert_eq!(members(exec(&mut repo, &["SRANDMEMBER", "s", "9223372036854775807"])).len(), 3);
+        assert_eq!(
+            exec(&mut repo, &["SRANDMEMBER", "s", "-9223372036854775808"]),
+            OperationError::OutOfRange.into()
+        );
+        assert_eq!(
+            exec(&mut repo, &["SRANDMEMBER", "s", "-1000000000000"]),
+            OperationError::OutOfRange.into()
+        );
+
+        let OperationResult::StringRes(popped) = exec(&mut repo, &["SPOP", "s"]) else {
+            panic!("SPOP without a count should reply with a bulk string");
+        };
+        let popped = String::from_utf8(popped).unwrap();
+        assert_eq!(exec(&mut repo, &["SISMEMBER", "s", &popped]), OperationResult::Int(0));
+        assert_eq!(members(exec(&mut repo, &["SPOP", "s", "9223372036854775807"])).len(), 2);
+        assert_eq!(repo.get(b"s"), None);
+
+        assert_eq!(exec(&mut repo, &["SPOP", "s", "-1"]), OperationError::MustBePositive.into());
+        assert_eq!(exec(&mut repo, &["SPOP", "s", "1", "2"]), OperationError::WrongArity("spop").into());
+    }
+
+    #[test]
+    fn test_smove() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["SADD", "src", "a", "b"]);
+        assert_eq!(exec(&mut repo, &["SMOVE", "src", "dst", "a"]), OperationResult::Int(1));
+        assert_eq!(exec(&mut repo, &["SMOVE", "src", "dst", "z"]), OperationResult::Int(0));
+        assert_eq!(exec(&mut repo, &["SMOVE", "src", "src", "b"]), OperationResult::Int(1));
+        assert_eq!(members(exec(&mut repo, &["SMEMBERS", "src"])), ["b"]);
+        assert_eq!(members(exec(&mut repo, &["SMEMBERS", "dst"])), ["a"]);
+
+        exec(&mut repo, &["SET", "str", "v"]);
+        assert_eq!(exec(&mut repo, &["SMOVE", "src", "str", "b"]), OperationError::WrongType.into());
+        assert_eq!(exec(&mut repo, &["SCARD", "src"]), OperationResult::Int(1));
+    }
+
+    #[test]
+    fn test_algebra() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["SADD", "a", "1", "2", "3"]);
+        exec(&mut repo, &["SADD", "b", "2", "3", "4"]);
+        exec(&mut repo, &["SADD", "c", "3", "5"]);
+
+        assert_eq!(members(exec(&mut repo, &["SINTER", "a", "b", "c"])), ["3"]);
+        assert_eq!(members(exec(&mut repo, &["SUNION", "a", "b", "c"])), ["1", "2", "3", "4", "5"]);
+        assert_eq!(members(exec(&mut repo, &["SDIFF", "a", "b"])), ["1"]);
+        assert_eq!(members(exec(&mut repo, &["SDIFF", "a", "missing"])), ["1", "2", "3"]);
+        assert_eq!(exec(&mut repo, &["SINTER", "a", "missing"]), OperationResult::Set(vec![]));
+        assert_eq!(members(exec(&mut repo, &["SUNION", "missing", "c"])), ["3", "5"]);
+
+        exec(&mut repo, &["SET", "str", "v"]);
+        assert_eq!(exec(&mut repo, &["SINTER", "missing", "str"]), OperationError::WrongType.into());
+        assert_eq!(exec(&mut repo, &["SUNION", "a", "str"]), OperationError::WrongType.into());
+    }
+
+    #[test]
+    fn test_store_variants() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["SADD", "a", "1", "2", "3"]);
+        exec(&mut repo, &["SADD", "b", "2", "3", "4"]);
+        exec(&mut repo, &["SET", "dst", "v", "EX", "100"]);
+
+        assert_eq!(exec(&mut repo, &["SINTERSTORE", "dst", "a", "b"]), OperationResult::Int(2));
+        assert_eq!(members(exec(&mut repo, &["SMEMBERS", "dst"])), ["2", "3"]);
+        assert_eq!(exec(&mut repo, &["TTL", "dst"]), OperationResult::Int(-1));
+        assert_eq!(exec(&mut repo, &["SUNIONSTORE", "dst", "a", "b"]), OperationResult::Int(4));
+        assert_eq!(exec(&mut repo, &["SDIFFSTORE", "dst", "b", "a"]), OperationResult::Int(1));
+        assert_eq!(members(exec(&mut repo, &["SMEMBERS", "dst"])), ["4"]);
+        assert_eq!(exec(&mut repo, &["SDIFFSTORE", "a", "a", "a"]), OperationResult::Int(0));
+        assert_eq!(repo.get(b"a"), None);
+    }
+
+    #[test]
+    fn test_sintercard() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["SADD", "a", "1", "2", "3"]);
+        exec(&mut repo, &["SADD", "b", "1", "2", "3", "4"]);
+        assert_eq!(exec(&mut repo, &["SINTERCARD", "2", "a", "b"]), OperationResult::Int(3));
+        assert_eq!(exec(&mut repo, &["SINTERCARD", "2", "a", "b", "LIMIT", "2"]), OperationResult::Int(2));
+        assert_eq!(exec(&mut repo, &["SINTERCARD", "2", "a", "missing"]), OperationResult::Int(0));
+        assert_eq!(exec(&mut repo, &["SINTERCARD", "0", "a"]), OperationError::NumKeys.into());
+        assert_eq!(exec(&mut repo, &["SINTERCARD", "3", "a", "b"]), OperationError::Syntax.into());
+        assert_eq!(
+            exec(&mut repo, &["SINTERCARD", "1", "a", "LIMIT", "-1"]),
+            OperationError::NegativeLimit.into()
+        );
+        assert_eq!(exec(&mut repo, &["SINTERCARD", "1", "a", "b"]), OperationError::Syntax.into());
+    }
+}
@@ -0,0 +1,1057 @@
+use std::collections::HashMap;
+
+use crate::{
+    protocol::ProtocolVersion,
+    record::Record,
+    repository::Repository,
+    request::Request,
+    sorted_set::{parse_score, LexBound, LexRange, ScoreBound, ScoreRange, SortedSet},
+};
+
+use super::{parse_int, parse_timeout, Context, OperationError, OperationResult};
+
+/// Borrows the sorted set stored at `key`, or `None` if the key doesn't
+/// exist. Keys holding other types fail with `WRONGTYPE`.
+fn read_zset<'a>(
+    repo: &'a mut Repository,
+    key: &[u8],
+) -> Result<Option<&'a mut SortedSet>, OperationError> {
+    match repo.get_mut(key) {
+        Some(Record::SortedSet(zset)) => Ok(Some(zset)),
+        Some(_) => Err(OperationError::WrongType),
+        None => Ok(None),
+    }
+}
+
+/// Borrows the sorted set stored at `key`, creating an empty one if the key
+/// doesn't exist.
+fn zset_for_update<'a>(
+    repo: &'a mut Repository,
+    key: &[u8],
+) -> Result<&'a mut SortedSet, OperationError> {
+    if repo.get_mut(key).is_none() {
+        repo.set(key.to_vec(), Record::SortedSet(SortedSet::new()));
+    }
+    read_zset(repo, key).map(|zset| zset.expect("sorted set was just created"))
+}
+
+/// Drops a key whose sorted set has no members left, as Redis never keeps
+/// empty collections around.
+fn remove_if_empty(repo: &mut Repository, key: &[u8]) {
+    if let Some(Record::SortedSet(zset)) = repo.get_mut(key) {
+        if zset.is_empty() {
+            repo.delete(key);
+        }
+    }
+}
+
+/// Replies with members, followed by their scores if `with_scores` is set.
+/// RESP3 clients get a pair per member and RESP2 clients a flat array.
+fn members_reply(
+    members: Vec<(Vec<u8>, f64)>,
+    with_scores: bool,
+    protocol: ProtocolVersion,
+) -> OperationResult {
+    let members = members.into_iter();
+    OperationResult::Array(match (with_scores, protocol) {
+        (false, _) => members
+            .map(|(member, _)| OperationResult::StringRes(member))
+            .collect(),
+        (true, ProtocolVersion::Resp2) => members
+            .flat_map(|(member, score)| {
+                [
+                    OperationResult::StringRes(member),
+                    OperationResult::Double(score),
+                ]
+            })
+            .collect(),
+        (true, ProtocolVersion::Resp3) => members
+            .map(|(member, score)| {
+                OperationResult::Array(vec![
+                    OperationResult::StringRes(member),
+                    OperationResult::Double(score),
+                ])
+            })
+            .collect(),
+    })
+}
+
+/// `ZADD key [NX | XX] [GT | LT] [CH] [INCR] score member [score member ...]`
+pub fn zadd(ctx: &mut Context, req: &Request) -> OperationResult {
+    let args = req.arguments();
+    let key = &args[0];
+    let (mut nx, mut xx, mut gt, mut lt, mut ch, mut incr) =
+        (false, false, false, false, false, false);
+    let mut options = 1;
+    while let Some(option) = args.get(options) {
+        match option.to_ascii_uppercase().as_slice() {
+            b"NX" => nx = true,
+            b"XX" => xx = true,
+            b"GT" => gt = true,
+            b"LT" => lt = true,
+            b"CH" => ch = true,
+            b"INCR" => incr = true,
+            _ => break,
+        }
+        options += 1;
+    }
+
+    let pairs = &args[options..];
+    if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+        return OperationError::Syntax.into();
+    }
+    if nx && xx {
+        return OperationError::XxAndNx.into();
+    }
+    if (gt && lt) || ((gt || lt) && nx) {
+        return OperationError::GtLtNx.into();
+    }
+    if incr && pairs.len() > 2 {
+        return OperationError::IncrPair.into();
+    }
+    let Some(pairs) = pairs
+        .chunks(2)
+        .map(|pair| Some((parse_score(&pair[0])?, &pair[1])))
+        .collect::<Option<Vec<_>>>()
+    else {
+        return OperationError::NotAFloat.into();
+    };
+
+    let zset = match read_zset(ctx.repo, key) {
+        Ok(None) if xx => {
+            return if incr {
+                OperationResult::Nil
+            } else {
+                OperationResult::Int(0)
+            };
+        }
+        Ok(_) => zset_for_update(ctx.repo, key),
+        Err(e) => Err(e),
+    };
+    let zset = match zset {
+        Ok(zset) => zset,
+        Err(e) => return e.into(),
+    };
+
+    let (mut added, mut changed) = (0, 0);
+    let mut incremented = None;
+    for (score, member) in pairs {
+        let new_score = match zset.score(member) {
+            Some(_) if nx => continue,
+            Some(current) => {
+                let new_score = if incr { current + score } else { score };
+                if new_score.is_nan() {
+                    return OperationError::NanScore.into();
+                }
+                if (gt && new_score <= current) || (lt && new_score >= current) {
+                    continue;
+                }
+                if new_score != current {
+                    changed += 1;
+                }
+                new_score
+            }
+            None if xx => continue,
+            None => {
+                added += 1;
+                score
+            }
+        };
+        zset.insert(member.to_vec(), new_score);
+        incremented = Some(new_score);
+    }
+    remove_if_empty(ctx.repo, key);
+    if added > 0 {
+        ctx.repo.signal_ready(key);
+    }
+
+    if incr {
+        incremented.map_or(OperationResult::Nil, OperationResult::Double)
+    } else if ch {
+        OperationResult::Int(added + changed)
+    } else {
+        OperationResult::Int(added)
+    }
+}
+
+pub fn zincrby(ctx: &mut Context, req: &Request) -> OperationResult {
+    let args = req.arguments();
+    let Some(increment) = parse_score(&args[1]) else {
+        return OperationError::NotAFloat.into();
+    };
+    let zset = match zset_for_update(ctx.repo, &args[0]) {
+        Ok(zset) => zset,
+        Err(e) => return e.into(),
+    };
+    let score = zset.score(&args[2]).unwrap_or(0.0) + increment;
+    if score.is_nan() {
+        remove_if_empty(ctx.repo, &args[0]);
+        return OperationError::NanScore.into();
+    }
+    zset.insert(args[2].to_vec(), score);
+    ctx.repo.signal_ready(&args[0]);
+    OperationResult::Double(score)
+}
+
+pub fn zrem(ctx: &mut Context, req: &Request) -> OperationResult {
+    let key = &req.arguments()[0];
+    let removed = match read_zset(ctx.repo, key) {
+        Ok(Some(zset)) => req.arguments()[1..]
+            .iter()
+            .filter(|member| zset.remove(member))
+            .count(),
+        Ok(None) => 0,
+        Err(e) => return e.into(),
+    };
+    remove_if_empty(ctx.repo, key);
+    OperationResult::Int(removed as i64)
+}
+
+pub fn zcard(ctx: &mut Context, req: &Request) -> OperationResult {
+    match read_zset(ctx.repo, &req.arguments()[0]) {
+        Ok(zset) => OperationResult::Int(zset.map_or(0, |zset| zset.len()) as i64),
+        Err(e) => e.into(),
+    }
+}
+
+pub fn zscore(ctx: &mut Context, req: &Request) -> OperationResult {
+    match read_zset(ctx.repo, &req.arguments()[0]) {
+        Ok(zset) => zset
+            .and_then(|zset| zset.score(&req.arguments()[1]))
+            .map_or(OperationResult::Nil, OperationResult::Double),
+        Err(e) => e.into(),
+    }
+}
+
+/// `ZRANK key member [WITHSCORE]` and `ZREVRANK key member [WITHSCORE]`
+fn rank(ctx: &mut Context, req: &Request, reverse: bool) -> OperationResult {
+    let args = req.arguments();
+    let with_score = match args.get(2) {
+        Some(option) if args.len() == 3 && option.eq_ignore_ascii_case(b"WITHSCORE") => true,
+        Some(_) => return OperationError::Syntax.into(),
+        None => false,
+    };
+    let zset = match read_zset(ctx.repo, &args[0]) {
+        Ok(Some(zset)) => zset,
+        Ok(None) => return OperationResult::Nil,
+        Err(e) => return e.into(),
+    };
+    let (Some(rank), Some(score)) = (zset.rank(&args[1]), zset.score(&args[1])) else {
+        return OperationResult::Nil;
+    };
+    let rank = if reverse { zset.len() - 1 - rank } else { rank };
+    if with_score {
+        OperationResult::Array(vec![
+            OperationResult::Int(rank as i64),
+            OperationResult::Double(score),
+        ])
+    } else {
+        OperationResult::Int(rank as i64)
+    }
+}
+
+pub fn zrank(ctx: &mut Context, req: &Request) -> OperationResult {
+    rank(ctx, req, false)
+}
+
+pub fn zrevrank(ctx: &mut Context, req: &Request) -> OperationResult {
+    rank(ctx, req, true)
+}
+
+fn parse_score_range(min: &[u8], max: &[u8]) -> Result<ScoreRange, OperationError> {
+    match (ScoreBound::parse(min), ScoreBound::parse(max)) {
+        (Some(min), Some(max)) => Ok(ScoreRange { min, max }),
+        _ => Err(OperationError::MinMaxNotFloat),
+    }
+}
+
+fn parse_lex_range(min: &[u8], max: &[u8]) -> Result<LexRange, OperationError> {
+    match (LexBound::parse(min), LexBound::parse(max)) {
+        (Some(min), Some(max)) => Ok(LexRange { min, max }),
+        _ => Err(OperationError::MinMaxNotString),
+    }
+}
+
+pub fn zcount(ctx: &mut Context, req: &Request) -> OperationResult {
+    let args = req.arguments();
+    let range = match parse_score_range(&args[1], &args[2]) {
+        Ok(range) => range,
+        Err(e) => return e.into(),
+    };
+    match read_zset(ctx.repo, &args[0]) {
+        Ok(zset) => OperationResult::Int(zset.map_or(0, |zset| zset.count_by_score(&range)) as i64),
+        Err(e) => e.into(),
+    }
+}
+
+#[derive(PartialEq)]
+enum RangeBy {
+    Rank,
+    Score,
+    Lex,
+}
+
+/// `ZRANGE key start stop [BYSCORE | BYLEX] [REV] [LIMIT offset count]
+/// [WITHSCORES]`
+pub fn zrange(ctx: &mut Context, req: &Request) -> OperationResult {
+    let args = req.arguments();
+    let mut by = RangeBy::Rank;
+    let mut reverse = false;
+    let mut limit = None;
+    let mut with_scores = false;
+    let mut options = args[3..].iter();
+    while let Some(option) = options.next() {
+        match option.to_ascii_uppercase().as_slice() {
+            b"BYSCORE" => by = RangeBy::Score,
+            b"BYLEX" => by = RangeBy::Lex,
+            b"REV" => reverse = true,
+            b"WITHSCORES" => with_scores = true,
+            b"LIMIT" => {
+                let (Some(offset), Some(count)) = (options.next(), options.next()) else {
+                    return OperationError::Syntax.into();
+                };
+                let (Some(offset), Some(count)) = (parse_int(offset), parse_int(count)) else {
+                    return OperationError::NotAnInteger.into();
+                };
+                limit = Some((offset, count));
+            }
+            _ => return OperationError::Syntax.into(),
+        }
+    }
+    if limit.is_some() && by == RangeBy::Rank {
+        return OperationError::LimitWithoutBy.into();
+    }
+    if with_scores && by == RangeBy::Lex {
+        return OperationError::WithScoresByLex.into();
+    }
+
+    // Score and lex ranges are given from the high end down when reversed.
+    let (start, stop) = if reverse && by != RangeBy::Rank {
+        (&args[2], &args[1])
+    } else {
+        (&args[1], &args[2])
+    };
+    let (offset, count) = match limit {
+        Some((offset, _)) if offset < 0 => return OperationResult::Array(vec![]),
+        Some((offset, count)) => (offset as usize, (count >= 0).then_some(count as usize)),
+        None => (0, None),
+    };
+
+    let members = match by {
+        RangeBy::Rank => {
+            let (Some(start), Some(stop)) = (parse_int(start), parse_int(stop)) else {
+                return OperationError::NotAnInteger.into();
+            };
+            match read_zset(ctx.repo, &args[0]) {
+                Ok(Some(zset)) => match rank_range(start, stop, zset.len()) {
+                    Some((start, stop)) => zset.range_by_rank(start, stop, reverse),
+                    None => vec![],
+                },
+                Ok(None) => vec![],
+                Err(e) => return e.into(),
+            }
+        }
+        RangeBy::Score => {
+            let range = match parse_score_range(start, stop) {
+                Ok(range) => range,
+                Err(e) => return e.into(),
+            };
+            match read_zset(ctx.repo, &args[0]) {
+                Ok(zset) => zset.map_or_else(Vec::new, |zset| {
+                    zset.range_by_score(&range, reverse, offset, count)
+                }),
+                Err(e) => return e.into(),
+            }
+        }
+        RangeBy::Lex => {
+            let range = match parse_lex_range(start, stop) {
+                Ok(range) => range,
+                Err(e) => return e.into(),
+            };
+            match read_zset(ctx.repo, &args[0]) {
+                Ok(zset) => zset.map_or_else(Vec::new, |zset| {
+                    zset.range_by_lex(&range, reverse, offset, count)
+                }),
+                Err(e) => return e.into(),
+            }
+        }
+    };
+    members_reply(members, with_scores, ctx.session.protocol)
+}
+
+/// Turns an inclusive range of possibly negative ranks into positions in a
+/// sorted set of `len` members, clamping it to the set. `None` means the
+/// range selects nothing.
+fn rank_range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (start + len).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        stop + len
+    } else {
+        stop.min(len - 1)
+    };
+    (start <= stop && start < len).then_some((start as usize, stop as usize))
+}
+
+/// A key along with the members popped from it and their scores.
+type Popped = (Vec<u8>, Vec<(Vec<u8>, f64)>);
+
+/// Pops up to `count` members from the low end of the first of `keys` that
+/// holds a sorted set, or the high end with `max`, returning that key along
+/// with them. Keys are type checked in order until one is found.
+fn pop_first(
+    repo: &mut Repository,
+    keys: &[Vec<u8>],
+    max: bool,
+    count: usize,
+) -> Result<Option<Popped>, OperationError> {
+    for key in keys {
+        if let Some(zset) = read_zset(repo, key)? {
+            let popped = std::iter::from_fn(|| zset.pop(max)).take(count).collect();
+            remove_if_empty(repo, key);
+            return Ok(Some((key.to_vec(), popped)));
+        }
+    }
+    Ok(None)
+}
+
+/// `ZPOPMIN key [count]` and `ZPOPMAX key [count]`
+fn pop(ctx: &mut Context, req: &Request, max: bool, command: &'static str) -> OperationResult {
+    let args = req.arguments();
+    let count = match args {
+        [_] => None,
+        [_, count] => match parse_int(count) {
+            Some(count) if count >= 0 => Some(count as usize),
+            _ => return OperationError::MustBePositive.into(),
+        },
+        _ => return OperationError::WrongArity(command).into(),
+    };
+    let popped = match pop_first(ctx.repo, &args[..1], max, count.unwrap_or(1)) {
+        Ok(popped) => popped.map_or_else(Vec::new, |(_, popped)| popped),
+        Err(e) => return e.into(),
+    };
+    // A single pop is a flat pair whatever the protocol.
+    let protocol = if count.is_some() {
+        ctx.session.protocol
+    } else {
+        ProtocolVersion::Resp2
+    };
+    members_reply(popped, true, protocol)
+}
+
+pub fn zpopmin(ctx: &mut Context, req: &Request) -> OperationResult {
+    pop(ctx, req, false, "zpopmin")
+}
+
+pub fn zpopmax(ctx: &mut Context, req: &Request) -> OperationResult {
+    pop(ctx, req, true, "zpopmax")
+}
+
+/// `BZPOPMIN key [key ...] timeout` and `BZPOPMAX key [key ...] timeout`
+fn blocking_pop(ctx: &mut Context, req: &Request, max: bool) -> OperationResult {
+    let (timeout, keys) = req.arguments().split_last().expect("arity is checked");
+    let deadline = match parse_timeout(timeout) {
+        Ok(deadline) => deadline,
+        Err(e) => return e.into(),
+    };
+    match pop_first(ctx.repo, keys, max, 1) {
+        Ok(Some((key, mut popped))) => {
+            let (member, score) = popped.remove(0);
+            OperationResult::Array(vec![
+                OperationResult::StringRes(key),
+                OperationResult::StringRes(member),
+                OperationResult::Double(score),
+            ])
+        }
+        Ok(None) => ctx.block(keys, deadline),
+        Err(e) => e.into(),
+    }
+}
+
+pub fn bzpopmin(ctx: &mut Context, req: &Request) -> OperationResult {
+    blocking_pop(ctx, req, false)
+}
+
+pub fn bzpopmax(ctx: &mut Context, req: &Request) -> OperationResult {
+    blocking_pop(ctx, req, true)
+}
+
+/// Parses `numkeys key [key ...] MIN | MAX [COUNT count]`, the arguments
+/// shared by `ZMPOP` and `BZMPOP`.
+fn parse_mpop(args: &[Vec<u8>]) -> Result<(&[Vec<u8>], bool, usize), OperationError> {
+    let numkeys = parse_int(&args[0]).ok_or(OperationError::NotAnInteger)?;
+    if numkeys <= 0 {
+        return Err(OperationError::NumKeys);
+    }
+    let numkeys = numkeys as usize;
+    if args.len() < numkeys + 2 {
+        return Err(OperationError::Syntax);
+    }
+    let max = match args[numkeys + 1].to_ascii_uppercase().as_slice() {
+        b"MIN" => false,
+        b"MAX" => true,
+        _ => return Err(OperationError::Syntax),
+    };
+    let count = match &args[numkeys + 2..] {
+        [] => 1,
+        [option, count] if option.eq_ignore_ascii_case(b"COUNT") => match parse_int(count) {
+            Some(count) if count > 0 => count as usize,
+            _ => return Err(OperationError::CountNotPositive),
+        },
+        _ => return Err(OperationError::Syntax),
+    };
+    Ok((&args[1..=numkeys], max, count))
+}
+
+/// The key followed by a pair for each popped member, whatever the protocol.
+fn mpop_reply(key: Vec<u8>, popped: Vec<(Vec<u8>, f64)>) -> OperationResult {
+    OperationResult::Array(vec![
+        OperationResult::StringRes(key),
+        members_reply(popped, true, ProtocolVersion::Resp3),
+    ])
+}
+
+/// `ZMPOP numkeys key [key ...] MIN | MAX [COUNT count]`
+pub fn zmpop(ctx: &mut Context, req: &Request) -> OperationResult {
+    let (keys, max, count) = match parse_mpop(req.arguments()) {
+        Ok(parsed) => parsed,
+        Err(e) => return e.into(),
+    };
+    match pop_first(ctx.repo, keys, max, count) {
+        Ok(Some((key, popped))) => mpop_reply(key, popped),
+        Ok(None) => OperationResult::NullArray,
+        Err(e) => e.into(),
+    }
+}
+
+/// `BZMPOP timeout numkeys key [key ...] MIN | MAX [COUNT count]`
+pub fn bzmpop(ctx: &mut Context, req: &Request) -> OperationResult {
+    let args = req.arguments();
+    let parsed = parse_timeout(&args[0])
+        .and_then(|deadline| Ok((deadline, parse_mpop(&args[1..])?)));
+    let (deadline, (keys, max, count)) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => return e.into(),
+    };
+    match pop_first(ctx.repo, keys, max, count) {
+        Ok(Some((key, popped))) => mpop_reply(key, popped),
+        Ok(None) => ctx.block(keys, deadline),
+        Err(e) => e.into(),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    fn apply(self, a: f64, b: f64) -> f64 {
+        let result = match self {
+            Aggregate::Sum => a + b,
+            Aggregate::Min => a.min(b),
+            Aggregate::Max => a.max(b),
+        };
+        // Adding opposite infinities gives zero, as in Redis.
+        if result.is_nan() {
+            0.0
+        } else {
+            result
+        }
+    }
+}
+
+/// Reads a source of `ZUNIONSTORE` or `ZINTERSTORE`. Plain sets count as
+/// sorted sets whose members all score 1 and missing keys as empty.
+fn read_scores(repo: &mut Repository, key: &[u8]) -> Result<HashMap<Vec<u8>, f64>, OperationError> {
+    match repo.get_mut(key) {
+        Some(Record::SortedSet(zset)) => Ok(zset
+            .iter()
+            .map(|(member, score)| (member.to_vec(), score))
+            .collect()),
+        Some(Record::Set(set)) => Ok(set.iter().map(|member| (member.clone(), 1.0)).collect()),
+        Some(_) => Err(OperationError::WrongType),
+        None => Ok(HashMap::new()),
+    }
+}
+
+/// `ZUNIONSTORE` and `ZINTERSTORE`: `destination numkeys key [key ...]
+/// [WEIGHTS weight [weight ...]] [AGGREGATE SUM | MIN | MAX]`
+fn combine_and_store(
+    ctx: &mut Context,
+    req: &Request,
+    command: &'static str,
+    intersect: bool,
+) -> OperationResult {
+    let args = req.arguments();
+    let destination = &args[0];
+    let numkeys = match parse_int(&args[1]) {
+        Some(numkeys) if numkeys > 0 => numkeys as usize,
+        Some(_) => return OperationError::NoInputKeys(command).into(),
+        None => return OperationError::NotAnInteger.into(),
+    };
+    if args.len() < numkeys + 2 {
+        return OperationError::Syntax.into();
+    }
+    let keys = &args[2..numkeys + 2];
+
+    let mut weights = vec![1.0; numkeys];
+    let mut aggregate = Aggregate::Sum;
+    let mut options = args[numkeys + 2..].iter();
+    while let Some(option) = options.next() {
+        match option.to_ascii_uppercase().as_slice() {
+            b"WEIGHTS" => {
+                for weight in weights.iter_mut() {
+                    let Some(value) = options.next() else {
+                        return OperationError::Syntax.into();
+                    };
+                    let Some(value) = parse_score(value) else {
+                        return OperationError::WeightNotAFloat.into();
+                    };
+                    *weight = value;
+                }
+            }
+            b"AGGREGATE" => {
+                aggregate = match options.next().map(|value| value.to_ascii_uppercase()) {
+                    Some(value) if value == b"SUM" => Aggregate::Sum,
+                    Some(value) if value == b"MIN" => Aggregate::Min,
+                    Some(value) if value == b"MAX" => Aggregate::Max,
+                    _ => return OperationError::Syntax.into(),
+                }
+            }
+            _ => return OperationError::Syntax.into(),
+        }
+    }
+
+    let mut sources = Vec::with_capacity(numkeys);
+    for (key, weight) in keys.iter().zip(&weights) {
+        match read_scores(ctx.repo, key) {
+            Ok(mut scores) => {
+                scores.values_mut().for_each(|score| {
+                    let weighted = *score * weight;
+                    *score = if weighted.is_nan() { 0.0 } else { weighted };
+                });
+                sources.push(scores);
+            }
+            Err(e) => return e.into(),
+        }
+    }
+
+    let mut result = SortedSet::new();
+    if intersect {
+        sources.sort_by_key(|scores| scores.len());
+        let (smallest, rest) = sources.split_first().expect("numkeys is positive");
+        for (member, score) in smallest {
+            let others: Option<Vec<f64>> = rest
+                .iter()
+                .map(|scores| scores.get(member).copied())
+                .collect();
+            if let Some(others) = others {
+                let score = others
+                    .into_iter()
+                    .fold(*score, |a, b| aggregate.apply(a, b));
+                result.insert(member.clone(), score);
+            }
+        }
+    } else {
+        let mut combined: HashMap<Vec<u8>, f64> = HashMap::new();
+        for (member, score) in sources.into_iter().flatten() {
+            combined
+                .entry(member)
+                .and_modify(|current| *current = aggregate.apply(*current, score))
+                .or_insert(score);
+        }
+        for (member, score) in combined {
+            result.insert(member, score);
+        }
+    }
+
+    let len = result.len();
+    if result.is_empty() {
+        ctx.repo.delete(destination);
+    } else {
+        ctx.repo
+            .set(destination.to_vec(), Record::SortedSet(result));
+        ctx.repo.persist(destination);
+        ctx.repo.signal_ready(destination);
+    }
+    OperationResult::Int(len as i64)
+}
+
+pub fn zunionstore(ctx: &mut Context, req: &Request) -> OperationResult {
+    combine_and_store(ctx, req, "zunionstore", false)
+}
+
+pub fn zinterstore(ctx: &mut Context, req: &Request) -> OperationResult {
+    combine_and_store(ctx, req, "zinterstore", true)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{operations::exec, repository::Repository};
+
+    use super::*;
+
+    fn strings(reply: OperationResult) -> Vec<String> {
+        let OperationResult::Array(items) = reply else {
+            panic!("expected an array, got {:?}", reply);
+        };
+        items
+            .into_iter()
+            .map(|item| match item {
+                OperationResult::StringRes(s) => String::from_utf8(s).unwrap(),
+                OperationResult::Double(score) => score.to_string(),
+                other => panic!("expected a bulk string or a double, got {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_zadd_options() {
+        let mut repo = Repository::new();
+        assert_eq!(
+            exec(&mut repo, &["ZADD", "z", "1", "a", "2", "b"]),
+            OperationResult::Int(2)
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZADD", "z", "NX", "5", "a", "3", "c"]),
+            OperationResult::Int(1)
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZSCORE", "z", "a"]),
+            OperationResult::Double(1.0)
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZADD", "z", "XX", "CH", "5", "a", "4", "d"]),
+            OperationResult::Int(1)
+        );
+        assert_eq!(exec(&mut repo, &["ZSCORE", "z", "d"]), OperationResult::Nil);
+        assert_eq!(
+            exec(&mut repo, &["ZADD", "z", "GT", "CH", "1", "a", "9", "b"]),
+            OperationResult::Int(1)
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZSCORE", "z", "a"]),
+            OperationResult::Double(5.0)
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZADD", "z", "LT", "CH", "0", "e"]),
+            OperationResult::Int(1)
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZADD", "z", "INCR", "2.5", "a"]),
+            OperationResult::Double(7.5)
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZADD", "z", "LT", "INCR", "1", "a"]),
+            OperationResult::Nil
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZINCRBY", "z", "-0.5", "a"]),
+            OperationResult::Double(7.0)
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZADD", "missing", "XX", "1", "a"]),
+            OperationResult::Int(0)
+        );
+        assert_eq!(repo.get(b"missing"), None);
+
+        assert_eq!(
+            exec(&mut repo, &["ZADD", "z", "NX", "XX", "1", "a"]),
+            OperationError::XxAndNx.into()
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZADD", "z", "GT", "NX", "1", "a"]),
+            OperationError::GtLtNx.into()
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZADD", "z", "INCR", "1", "a", "2", "b"]),
+            OperationError::IncrPair.into()
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZADD", "z", "1", "a", "2"]),
+            OperationError::Syntax.into()
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZADD", "z", "nan", "a"]),
+            OperationError::NotAFloat.into()
+        );
+        exec(&mut repo, &["ZADD", "z", "inf", "a"]);
+        assert_eq!(
+            exec(&mut repo, &["ZINCRBY", "z", "-inf", "a"]),
+            OperationError::NanScore.into()
+        );
+
+        assert_eq!(
+            exec(&mut repo, &["ZREM", "z", "a", "b", "x"]),
+            OperationResult::Int(2)
+        );
+        assert_eq!(exec(&mut repo, &["ZCARD", "z"]), OperationResult::Int(2));
+        assert_eq!(
+            exec(&mut repo, &["ZREM", "z", "c", "e"]),
+            OperationResult::Int(2)
+        );
+        assert_eq!(repo.get(b"z"), None);
+    }
+
+    #[test]
+    fn test_rank_and_count() {
+        let mut repo = Repository::new();
+        exec(
+            &mut repo,
+            &["ZADD", "z", "1", "a", "2", "b", "2", "c", "3", "d"],
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZRANK", "z", "c"]),
+            OperationResult::Int(2)
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZREVRANK", "z", "c"]),
+            OperationResult::Int(1)
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZRANK", "z", "d", "WITHSCORE"]),
+            OperationResult::Array(vec![OperationResult::Int(3), OperationResult::Double(3.0)])
+        );
+        assert_eq!(exec(&mut repo, &["ZRANK", "z", "x"]), OperationResult::Nil);
+        assert_eq!(
+            exec(&mut repo, &["ZCOUNT", "z", "2", "+inf"]),
+            OperationResult::Int(3)
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZCOUNT", "z", "(1", "(3"]),
+            OperationResult::Int(2)
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZCOUNT", "z", "x", "3"]),
+            OperationError::MinMaxNotFloat.into()
+        );
+    }
+
+    #[test]
+    fn test_zrange() {
+        let mut repo = Repository::new();
+        exec(
+            &mut repo,
+            &["ZADD", "z", "1", "a", "2", "b", "3", "c", "4", "d"],
+        );
+        assert_eq!(
+            strings(exec(&mut repo, &["ZRANGE", "z", "1", "-2"])),
+            ["b", "c"]
+        );
+        assert_eq!(
+            strings(exec(
+                &mut repo,
+                &["ZRANGE", "z", "0", "0", "REV", "WITHSCORES"]
+            )),
+            ["d", "4"]
+        );
+        assert_eq!(
+            strings(exec(&mut repo, &["ZRANGE", "z", "5", "10"])),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            strings(exec(
+                &mut repo,
+                &["ZRANGE", "z", "(1", "3", "BYSCORE", "WITHSCORES"]
+            )),
+            ["b", "2", "c", "3"]
+        );
+        assert_eq!(
+            strings(exec(
+                &mut repo,
+                &["ZRANGE", "z", "+inf", "-inf", "BYSCORE", "REV", "LIMIT", "1", "2"]
+            )),
+            ["c", "b"]
+        );
+        assert_eq!(
+            strings(exec(
+                &mut repo,
+                &["ZRANGE", "z", "-inf", "2", "BYSCORE", "LIMIT", "-1", "1"]
+            )),
+            Vec::<String>::new()
+        );
+
+        exec(
+            &mut repo,
+            &["ZADD", "lex", "0", "a", "0", "b", "0", "c", "0", "d"],
+        );
+        assert_eq!(
+            strings(exec(&mut repo, &["ZRANGE", "lex", "[b", "(d", "BYLEX"])),
+            ["b", "c"]
+        );
+        assert_eq!(
+            strings(exec(
+                &mut repo,
+                &["ZRANGE", "lex", "+", "-", "BYLEX", "REV", "LIMIT", "0", "-1"]
+            )),
+            ["d", "c", "b", "a"]
+        );
+
+        assert_eq!(
+            exec(&mut repo, &["ZRANGE", "z", "0", "1", "LIMIT", "0", "1"]),
+            OperationError::LimitWithoutBy.into()
+        );
+        assert_eq!(
+            exec(
+                &mut repo,
+                &["ZRANGE", "lex", "-", "+", "BYLEX", "WITHSCORES"]
+            ),
+            OperationError::WithScoresByLex.into()
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZRANGE", "lex", "a", "+", "BYLEX"]),
+            OperationError::MinMaxNotString.into()
+        );
+    }
+
+    #[test]
+    fn test_pop() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["ZADD", "z", "1", "a", "2", "b", "3", "c"]);
+        assert_eq!(strings(exec(&mut repo, &["ZPOPMIN", "z"])), ["a", "1"]);
+        assert_eq!(
+            strings(exec(&mut repo, &["ZPOPMAX", "z", "5"])),
+            ["c", "3", "b", "2"]
+        );
+        assert_eq!(repo.get(b"z"), None);
+        assert_eq!(
+            strings(exec(&mut repo, &["ZPOPMIN", "z"])),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZPOPMIN", "z", "-1"]),
+            OperationError::MustBePositive.into()
+        );
+    }
+
+    #[test]
+    fn test_mpop() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["ZADD", "b", "1", "x", "2", "y", "3", "z"]);
+        let pair = |member: &str, score: f64| {
+            OperationResult::Array(vec![
+                OperationResult::StringRes(member.as_bytes().to_vec()),
+                OperationResult::Double(score),
+            ])
+        };
+        assert_eq!(
+            exec(&mut repo, &["ZMPOP", "2", "a", "b", "MAX", "COUNT", "2"]),
+            OperationResult::Array(vec![
+                OperationResult::StringRes(b"b".to_vec()),
+                OperationResult::Array(vec![pair("z", 3.0), pair("y", 2.0)]),
+            ])
+        );
+        assert_eq!(
+            exec(&mut repo, &["BZMPOP", "0", "1", "b", "MIN"]),
+            OperationResult::Array(vec![
+                OperationResult::StringRes(b"b".to_vec()),
+                OperationResult::Array(vec![pair("x", 1.0)]),
+            ])
+        );
+        assert_eq!(exec(&mut repo, &["ZMPOP", "1", "b", "MIN"]), OperationResult::NullArray);
+        assert_eq!(exec(&mut repo, &["ZMPOP", "1", "b", "UP"]), OperationError::Syntax.into());
+        assert_eq!(exec(&mut repo, &["ZMPOP", "0", "b", "MIN"]), OperationError::NumKeys.into());
+        assert_eq!(
+            exec(&mut repo, &["ZMPOP", "1", "b", "MIN", "COUNT", "0"]),
+            OperationError::CountNotPositive.into()
+        );
+    }
+
+    #[test]
+    fn test_blocking_pops_serve_available_members() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["ZADD", "b", "1", "x", "2", "y"]);
+        assert_eq!(strings(exec(&mut repo, &["BZPOPMIN", "a", "b", "0"])), ["b", "x", "1"]);
+        assert_eq!(strings(exec(&mut repo, &["BZPOPMAX", "a", "b", "0.5"])), ["b", "y", "2"]);
+        assert_eq!(repo.get(b"b"), None);
+
+        assert_eq!(exec(&mut repo, &["BZPOPMIN", "a", "-1"]), OperationError::NegativeTimeout.into());
+        exec(&mut repo, &["SET", "s", "v"]);
+        assert_eq!(exec(&mut repo, &["BZPOPMAX", "s", "0"]), OperationError::WrongType.into());
+    }
+
+    #[test]
+    fn test_union_and_intersection() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["ZADD", "z1", "1", "a", "2", "b"]);
+        exec(&mut repo, &["ZADD", "z2", "10", "b", "20", "c"]);
+        exec(&mut repo, &["SADD", "s", "a", "b"]);
+
+        assert_eq!(
+            exec(&mut repo, &["ZUNIONSTORE", "out", "2", "z1", "z2"]),
+            OperationResult::Int(3)
+        );
+        assert_eq!(
+            strings(exec(&mut repo, &["ZRANGE", "out", "0", "-1", "WITHSCORES"])),
+            ["a", "1", "b", "12", "c", "20"]
+        );
+        assert_eq!(
+            exec(
+                &mut repo,
+                &[
+                    "ZUNIONSTORE",
+                    "out",
+                    "2",
+                    "z1",
+                    "z2",
+                    "WEIGHTS",
+                    "2",
+                    "1",
+                    "AGGREGATE",
+                    "MAX"
+                ]
+            ),
+            OperationResult::Int(3)
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZSCORE", "out", "a"]),
+            OperationResult::Double(2.0)
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZSCORE", "out", "b"]),
+            OperationResult::Double(10.0)
+        );
+
+        assert_eq!(
+            exec(&mut repo, &["ZINTERSTORE", "out", "3", "z1", "z2", "s"]),
+            OperationResult::Int(1)
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZSCORE", "out", "b"]),
+            OperationResult::Double(13.0)
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZINTERSTORE", "out", "2", "z1", "missing"]),
+            OperationResult::Int(0)
+        );
+        assert_eq!(repo.get(b"out"), None);
+
+        assert_eq!(
+            exec(&mut repo, &["ZUNIONSTORE", "out", "0", "z1"]),
+            OperationError::NoInputKeys("zunionstore").into()
+        );
+        assert_eq!(
+            exec(&mut repo, &["ZUNIONSTORE", "out", "3", "z1", "z2"]),
+            OperationError::Syntax.into()
+        );
+        assert_eq!(
+            exec(
+                &mut repo,
+                &["ZUNIONSTORE", "out", "1", "z1", "WEIGHTS", "x"]
+            ),
+            OperationError::WeightNotAFloat.into()
+        );
+        exec(&mut repo, &["SET", "str", "v"]);
+        assert_eq!(
+            exec(&mut repo, &["ZUNIONSTORE", "out", "1", "str"]),
+            OperationError::WrongType.into()
+        );
+    }
+}
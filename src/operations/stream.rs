@@ -0,0 +1,1594 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::{
+    protocol::ProtocolVersion,
+    record::Record,
+    repository::Repository,
+    request::Request,
+    stream::{Claim, ClaimOptions, ConsumerGroup, Fields, Stream, StreamId},
+};
+
+use super::{parse_int, Context, OperationError, OperationResult};
+
+/// Borrows the stream stored at `key`, or `None` if the key doesn't exist.
+/// Keys holding other types fail with `WRONGTYPE`.
+fn read_stream<'a>(
+    repo: &'a mut Repository,
+    key: &[u8],
+) -> Result<Option<&'a mut Stream>, OperationError> {
+    match repo.get_mut(key) {
+        Some(Record::Stream(stream)) => Ok(Some(stream)),
+        Some(_) => Err(OperationError::WrongType),
+        None => Ok(None),
+    }
+}
+
+/// Borrows the stream stored at `key`, creating an empty one if the key
+/// doesn't exist.
+fn stream_for_update<'a>(
+    repo: &'a mut Repository,
+    key: &[u8],
+) -> Result<&'a mut Stream, OperationError> {
+    if repo.get_mut(key).is_none() {
+        repo.set(key.to_vec(), Record::Stream(Stream::new()));
+    }
+    read_stream(repo, key).map(|stream| stream.expect("stream was just created"))
+}
+
+/// Borrows the stream stored at `key`, failing with `NOGROUP` unless it
+/// exists and has the consumer group `group`.
+fn read_group_stream<'a>(
+    repo: &'a mut Repository,
+    key: &[u8],
+    group: &[u8],
+) -> Result<&'a mut Stream, OperationError> {
+    match read_stream(repo, key)? {
+        Some(stream) if stream.groups.contains_key(group) => Ok(stream),
+        _ => Err(OperationError::NoGroup(
+            String::from_utf8_lossy(key).to_string(),
+            String::from_utf8_lossy(group).to_string(),
+        )),
+    }
+}
+
+/// The current Unix time in milliseconds, which new IDs and delivery times
+/// are based on.
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+fn parse_id(arg: &[u8]) -> Result<StreamId, OperationError> {
+    StreamId::parse(arg, 0).ok_or(OperationError::InvalidStreamId)
+}
+
+/// Parses the low end of an interval: `-`, an ID, or an ID preceded by `(`
+/// to exclude it. `None` if nothing can follow an excluded ID.
+fn parse_range_start(arg: &[u8]) -> Result<Option<StreamId>, OperationError> {
+    match arg {
+        b"-" => Ok(Some(StreamId::MIN)),
+        b"+" => Ok(Some(StreamId::MAX)),
+        [b'(', id @ ..] => Ok(parse_id(id)?.next()),
+        _ => parse_id(arg).map(Some),
+    }
+}
+
+/// Parses the high end of an interval. A bare millisecond time covers every
+/// sequence number within it.
+fn parse_range_end(arg: &[u8]) -> Result<Option<StreamId>, OperationError> {
+    let parse = |id| StreamId::parse(id, u64::MAX).ok_or(OperationError::InvalidStreamId);
+    match arg {
+        b"+" => Ok(Some(StreamId::MAX)),
+        b"-" => Ok(Some(StreamId::MIN)),
+        [b'(', id @ ..] => Ok(parse(id)?.prev()),
+        _ => parse(arg).map(Some),
+    }
+}
+
+fn id_reply(id: StreamId) -> OperationResult {
+    OperationResult::StringRes(id.to_string().into_bytes())
+}
+
+/// An entry as its ID followed by its fields and values, which are null for
+/// a pending entry deleted from the stream.
+fn entry_reply(id: StreamId, fields: Option<Fields>) -> OperationResult {
+    let fields = fields.map_or(OperationResult::NullArray, |fields| {
+        OperationResult::Array(fields.into_iter().map(OperationResult::StringRes).collect())
+    });
+    OperationResult::Array(vec![id_reply(id), fields])
+}
+
+fn entries_reply(entries: Vec<(StreamId, Fields)>) -> OperationResult {
+    OperationResult::Array(
+        entries
+            .into_iter()
+            .map(|(id, fields)| entry_reply(id, Some(fields)))
+            .collect(),
+    )
+}
+
+/// Replies to `XREAD` and `XREADGROUP` with the entries read from each key:
+/// a map for RESP3 clients and an array of pairs for RESP2 ones.
+fn streams_reply(
+    streams: Vec<(Vec<u8>, OperationResult)>,
+    protocol: ProtocolVersion,
+) -> OperationResult {
+    match protocol {
+        ProtocolVersion::Resp2 => OperationResult::Array(
+            streams
+                .into_iter()
+                .map(|(key, entries)| {
+                    OperationResult::Array(vec![OperationResult::StringRes(key), entries])
+                })
+                .collect(),
+        ),
+        ProtocolVersion::Resp3 => OperationResult::Map(
+            streams
+                .into_iter()
+                .map(|(key, entries)| (OperationResult::StringRes(key), entries))
+                .collect(),
+        ),
+    }
+}
+
+enum TrimStrategy {
+    MaxLen(usize),
+    MinId(StreamId),
+}
+
+/// `MAXLEN | MINID [= | ~] threshold [LIMIT count]`, the trimming options
+/// shared by `XADD` and `XTRIM`. Approximate trimming is done exactly, as
+/// entries aren't stored in blocks that would make it cheaper.
+#[derive(Default)]
+struct Trim {
+    strategy: Option<TrimStrategy>,
+    approximate: bool,
+    limit: Option<usize>,
+}
+
+impl Trim {
+    /// Parses the trimming option `args` starts with, returning how many
+    /// arguments it took, or zero if `args` starts with something else.
+    fn parse_option(&mut self, args: &[Vec<u8>]) -> Result<usize, OperationError> {
+        let Some(option) = args.first() else {
+            return Ok(0);
+        };
+        match option.to_ascii_uppercase().as_slice() {
+            strategy @ (b"MAXLEN" | b"MINID") => {
+                let mut consumed = 1;
+                match args.get(1).map(Vec::as_slice) {
+                    Some(b"~") => {
+                        self.approximate = true;
+                        consumed += 1;
+                    }
+                    Some(b"=") => consumed += 1,
+                    _ => {}
+                }
+                let threshold = args.get(consumed).ok_or(OperationError::Syntax)?;
+                self.strategy = Some(if strategy == b"MAXLEN" {
+                    match parse_int(threshold) {
+                        Some(max_len) if max_len >= 0 => TrimStrategy::MaxLen(max_len as usize),
+                        Some(_) => return Err(OperationError::StreamMaxLen),
+                        None => return Err(OperationError::NotAnInteger),
+                    }
+                } else {
+                    TrimStrategy::MinId(parse_id(threshold)?)
+                });
+                Ok(consumed + 1)
+            }
+            b"LIMIT" => {
+                let limit = args.get(1).ok_or(OperationError::Syntax)?;
+                self.limit = match parse_int(limit) {
+                    Some(limit) if limit >= 0 => Some(limit as usize),
+                    Some(_) => return Err(OperationError::StreamLimit),
+                    None => return Err(OperationError::NotAnInteger),
+                };
+                Ok(2)
+            }
+            _ => Ok(0),
+        }
+    }
+
+    fn validate(&self) -> Result<(), OperationError> {
+        if self.limit.is_some() && !self.approximate {
+            return Err(OperationError::LimitWithoutApprox);
+        }
+        Ok(())
+    }
+
+    /// Trims `stream`, returning how many entries were evicted. A limit of
+    /// zero means no limit.
+    fn apply(&self, stream: &mut Stream) -> usize {
+        let limit = self.limit.filter(|limit| *limit > 0);
+        match self.strategy {
+            Some(TrimStrategy::MaxLen(max_len)) => stream.trim_to_len(max_len, limit),
+            Some(TrimStrategy::MinId(min_id)) => stream.trim_to_min_id(min_id, limit),
+            None => 0,
+        }
+    }
+}
+
+/// The ID asked for by `XADD`.
+enum NewId {
+    /// `*`: the current time and the next free sequence number.
+    Auto,
+    /// `ms-*`: the given time and the next free sequence number.
+    AutoSeq(u64),
+    Explicit(StreamId),
+}
+
+impl NewId {
+    fn parse(arg: &[u8]) -> Result<NewId, OperationError> {
+        match arg {
+            b"*" => Ok(NewId::Auto),
+            [ms @ .., b'-', b'*'] => StreamId::parse(ms, 0)
+                .filter(|_| !ms.contains(&b'-'))
+                .map(|id| NewId::AutoSeq(id.ms))
+                .ok_or(OperationError::InvalidStreamId),
+            _ => match parse_id(arg)? {
+                StreamId::MIN => Err(OperationError::StreamIdZero),
+                id => Ok(NewId::Explicit(id)),
+            },
+        }
+    }
+
+    /// Picks the ID of an entry added to `stream`.
+    fn resolve(self, stream: &Stream) -> Result<StreamId, OperationError> {
+        let last = stream.last_id;
+        match self {
+            NewId::Auto => stream
+                .next_id(unix_millis())
+                .ok_or(OperationError::StreamExhausted),
+            NewId::AutoSeq(ms) if ms > last.ms => Ok(StreamId::new(ms, 0)),
+            NewId::AutoSeq(ms) if ms == last.ms => last
+                .seq
+                .checked_add(1)
+                .map(|seq| StreamId::new(ms, seq))
+                .ok_or(OperationError::StreamExhausted),
+            NewId::Explicit(id) if id > last => Ok(id),
+            _ => Err(OperationError::StreamIdTooSmall),
+        }
+    }
+}
+
+/// `XADD key [NOMKSTREAM] [MAXLEN | MINID [= | ~] threshold [LIMIT count]]
+/// * | id field value [field value ...]`
+pub fn xadd(ctx: &mut Context, req: &Request) -> OperationResult {
+    let args = req.arguments();
+    let key = &args[0];
+    let mut nomkstream = false;
+    let mut trim = Trim::default();
+    let mut position = 1;
+    loop {
+        if args
+            .get(position)
+            .is_some_and(|option| option.eq_ignore_ascii_case(b"NOMKSTREAM"))
+        {
+            nomkstream = true;
+            position += 1;
+            continue;
+        }
+        match trim.parse_option(&args[position..]) {
+            Ok(0) => break,
+            Ok(consumed) => position += consumed,
+            Err(e) => return e.into(),
+        }
+    }
+    if let Err(e) = trim.validate() {
+        return e.into();
+    }
+
+    let Some(id) = args.get(position) else {
+        return OperationError::Syntax.into();
+    };
+    let fields = &args[position + 1..];
+    if fields.is_empty() || !fields.len().is_multiple_of(2) {
+        return OperationError::WrongArity("xadd").into();
+    }
+    let id = match NewId::parse(id) {
+        Ok(id) => id,
+        Err(e) => return e.into(),
+    };
+
+    // A new stream accepts any ID, so creating it before checking the ID
+    // never leaves an empty stream behind.
+    let stream = match read_stream(ctx.repo, key) {
+        Ok(None) if nomkstream => return OperationResult::Nil,
+        Ok(_) => stream_for_update(ctx.repo, key),
+        Err(e) => Err(e),
+    };
+    let added = stream.and_then(|stream| {
+        let id = id.resolve(stream)?;
+        stream.add(id, fields.to_vec());
+        trim.apply(stream);
+        Ok(id)
+    });
+    match added {
+        Ok(id) => {
+            ctx.repo.signal_ready(key);
+            id_reply(id)
+        }
+        Err(e) => e.into(),
+    }
+}
+
+pub fn xlen(ctx: &mut Context, req: &Request) -> OperationResult {
+    match read_stream(ctx.repo, &req.arguments()[0]) {
+        Ok(stream) => OperationResult::Int(stream.map_or(0, |stream| stream.len()) as i64),
+        Err(e) => e.into(),
+    }
+}
+
+/// `XRANGE key start end [COUNT count]` and `XREVRANGE key end start [COUNT
+/// count]`
+fn range(ctx: &mut Context, req: &Request, reverse: bool) -> OperationResult {
+    let args = req.arguments();
+    let (start, end) = if reverse {
+        (&args[2], &args[1])
+    } else {
+        (&args[1], &args[2])
+    };
+    let count = match &args[3..] {
+        [] => None,
+        [option, count] if option.eq_ignore_ascii_case(b"COUNT") => match parse_int(count) {
+            Some(count) => Some(count.max(0) as usize),
+            None => return OperationError::NotAnInteger.into(),
+        },
+        _ => return OperationError::Syntax.into(),
+    };
+    let bounds = parse_range_start(start).and_then(|start| Ok((start, parse_range_end(end)?)));
+    let (start, end) = match bounds {
+        Ok((Some(start), Some(end))) => (start, end),
+        Ok(_) => return OperationResult::Array(vec![]),
+        Err(e) => return e.into(),
+    };
+    match read_stream(ctx.repo, &args[0]) {
+        Ok(Some(stream)) if count != Some(0) => {
+            entries_reply(stream.range(start, end, reverse, count))
+        }
+        Ok(_) => OperationResult::Array(vec![]),
+        Err(e) => e.into(),
+    }
+}
+
+pub fn xrange(ctx: &mut Context, req: &Request) -> OperationResult {
+    range(ctx, req, false)
+}
+
+pub fn xrevrange(ctx: &mut Context, req: &Request) -> OperationResult {
+    range(ctx, req, true)
+}
+
+pub fn xdel(ctx: &mut Context, req: &Request) -> OperationResult {
+    let args = req.arguments();
+    let ids = match args[1..]
+        .iter()
+        .map(|id| parse_id(id))
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(ids) => ids,
+        Err(e) => return e.into(),
+    };
+    match read_stream(ctx.repo, &args[0]) {
+        Ok(Some(stream)) => {
+            OperationResult::Int(ids.iter().filter(|id| stream.delete(id)).count() as i64)
+        }
+        Ok(None) => OperationResult::Int(0),
+        Err(e) => e.into(),
+    }
+}
+
+/// `XTRIM key MAXLEN | MINID [= | ~] threshold [LIMIT count]`
+pub fn xtrim(ctx: &mut Context, req: &Request) -> OperationResult {
+    let args = req.arguments();
+    let mut trim = Trim::default();
+    let mut position = 1;
+    while position < args.len() {
+        match trim.parse_option(&args[position..]) {
+            Ok(0) => return OperationError::Syntax.into(),
+            Ok(consumed) => position += consumed,
+            Err(e) => return e.into(),
+        }
+    }
+    if trim.strategy.is_none() {
+        return OperationError::Syntax.into();
+    }
+    if let Err(e) = trim.validate() {
+        return e.into();
+    }
+    match read_stream(ctx.repo, &args[0]) {
+        Ok(Some(stream)) => OperationResult::Int(trim.apply(stream) as i64),
+        Ok(None) => OperationResult::Int(0),
+        Err(e) => e.into(),
+    }
+}
+
+/// Parses the `BLOCK` timeout of `XREAD` and `XREADGROUP`, given in
+/// milliseconds, into the deadline to wait until. Zero waits forever.
+fn parse_block(arg: &[u8]) -> Result<Option<Instant>, OperationError> {
+    let millis = parse_int(arg).ok_or(OperationError::TimeoutNotInteger)?;
+    if millis < 0 {
+        return Err(OperationError::NegativeTimeout);
+    }
+    if millis == 0 {
+        return Ok(None);
+    }
+    Instant::now()
+        .checked_add(Duration::from_millis(millis as u64))
+        .map(Some)
+        .ok_or(OperationError::TimeoutNotInteger)
+}
+
+/// `[COUNT count] [BLOCK milliseconds] [NOACK] STREAMS key [key ...] id [id
+/// ...]`, the options shared by `XREAD` and `XREADGROUP`.
+struct ReadOptions<'a> {
+    count: Option<usize>,
+    /// The deadline to block until when nothing can be read, if blocking.
+    block: Option<Option<Instant>>,
+    noack: bool,
+    keys: &'a [Vec<u8>],
+    ids: &'a [Vec<u8>],
+}
+
+impl<'a> ReadOptions<'a> {
+    fn parse(args: &'a [Vec<u8>], command: &'static str) -> Result<Self, OperationError> {
+        let mut options = ReadOptions {
+            count: None,
+            block: None,
+            noack: false,
+            keys: &[],
+            ids: &[],
+        };
+        let mut args = args.iter();
+        while let Some(option) = args.next() {
+            match option.to_ascii_uppercase().as_slice() {
+                b"COUNT" => {
+                    let count = args.next().ok_or(OperationError::Syntax)?;
+                    let count = parse_int(count).ok_or(OperationError::NotAnInteger)?;
+                    options.count = (count > 0).then_some(count as usize);
+                }
+                b"BLOCK" => {
+                    let timeout = args.next().ok_or(OperationError::Syntax)?;
+                    options.block = Some(parse_block(timeout)?);
+                }
+                b"NOACK" if command == "xreadgroup" => options.noack = true,
+                b"STREAMS" => {
+                    let streams = args.as_slice();
+                    if streams.is_empty() || !streams.len().is_multiple_of(2) {
+                        return Err(OperationError::UnbalancedStreams(command));
+                    }
+                    (options.keys, options.ids) = streams.split_at(streams.len() / 2);
+                    return Ok(options);
+                }
+                _ => return Err(OperationError::Syntax),
+            }
+        }
+        Err(OperationError::Syntax)
+    }
+}
+
+/// `XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id
+/// ...]`
+pub fn xread(ctx: &mut Context, req: &Request) -> OperationResult {
+    let options = match ReadOptions::parse(req.arguments(), "xread") {
+        Ok(options) => options,
+        Err(e) => return e.into(),
+    };
+
+    // `$` stands for the last ID of the stream, so only newer entries count.
+    let mut ids = Vec::with_capacity(options.ids.len());
+    for (key, id) in options.keys.iter().zip(options.ids) {
+        let id = match read_stream(ctx.repo, key) {
+            Ok(stream) if id == b"$" => Ok(stream.map_or(StreamId::MIN, |stream| stream.last_id)),
+            Ok(_) => parse_id(id),
+            Err(e) => Err(e),
+        };
+        match id {
+            Ok(id) => ids.push(id),
+            Err(e) => return e.into(),
+        }
+    }
+
+    let mut streams = vec![];
+    for (key, id) in options.keys.iter().zip(&ids) {
+        let Ok(Some(stream)) = read_stream(ctx.repo, key) else {
+            continue;
+        };
+        let Some(start) = id.next() else {
+            continue;
+        };
+        let entries = stream.range(start, StreamId::MAX, false, options.count);
+        if !entries.is_empty() {
+            streams.push((key.to_vec(), entries_reply(entries)));
+        }
+    }
+    if !streams.is_empty() {
+        return streams_reply(streams, ctx.session.protocol);
+    }
+
+    let Some(deadline) = options.block else {
+        return OperationResult::NullArray;
+    };
+    if !options.ids.iter().any(|id| id == b"$") {
+        return ctx.block(options.keys, deadline);
+    }
+    // Waking up must not resolve `$` again, or entries added in between
+    // would be missed.
+    let args = req.arguments();
+    let mut retry = vec![req.command().to_vec()];
+    retry.extend_from_slice(&args[..args.len() - ids.len()]);
+    retry.extend(ids.iter().map(|id| id.to_string().into_bytes()));
+    ctx.block_with_retry(options.keys, deadline, Request::new(retry))
+}
+
+/// `XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds]
+/// [NOACK] STREAMS key [key ...] id [id ...]`
+///
+/// `>` reads entries never delivered to the group, and any other ID the
+/// entries pending for the consumer after it.
+pub fn xreadgroup(ctx: &mut Context, req: &Request) -> OperationResult {
+    let args = req.arguments();
+    if !args[0].eq_ignore_ascii_case(b"GROUP") {
+        return OperationError::Syntax.into();
+    }
+    let (group, consumer) = (&args[1], &args[2]);
+    let options = match ReadOptions::parse(&args[3..], "xreadgroup") {
+        Ok(options) => options,
+        Err(e) => return e.into(),
+    };
+
+    let mut ids = Vec::with_capacity(options.ids.len());
+    for (key, id) in options.keys.iter().zip(options.ids) {
+        if let Err(e) = read_group_stream(ctx.repo, key, group) {
+            return e.into();
+        }
+        match id.as_slice() {
+            b">" => ids.push(None),
+            id => match parse_id(id) {
+                Ok(id) => ids.push(Some(id)),
+                Err(e) => return e.into(),
+            },
+        }
+    }
+
+    let now = unix_millis();
+    let mut streams = vec![];
+    for (key, id) in options.keys.iter().zip(ids) {
+        let stream = match read_group_stream(ctx.repo, key, group) {
+            Ok(stream) => stream,
+            Err(e) => return e.into(),
+        };
+        let Some(id) = id else {
+            let entries = stream
+                .deliver(group, consumer, options.count, options.noack, now)
+                .expect("group was checked");
+            if !entries.is_empty() {
+                streams.push((key.to_vec(), entries_reply(entries)));
+            }
+            continue;
+        };
+
+        stream
+            .groups
+            .get_mut(group)
+            .expect("group was checked")
+            .consumer(consumer, now);
+        let history = match id.next() {
+            Some(start) => stream.groups[group]
+                .pending
+                .range(start..)
+                .filter(|(_, entry)| entry.consumer == *consumer)
+                .take(options.count.unwrap_or(usize::MAX))
+                .map(|(id, _)| entry_reply(*id, stream.get(id).cloned()))
+                .collect(),
+            None => vec![],
+        };
+        streams.push((key.to_vec(), OperationResult::Array(history)));
+    }
+    if !streams.is_empty() {
+        return streams_reply(streams, ctx.session.protocol);
+    }
+    match options.block {
+        Some(deadline) => ctx.block(options.keys, deadline),
+        None => OperationResult::NullArray,
+    }
+}
+
+/// `XACK key group id [id ...]`
+pub fn xack(ctx: &mut Context, req: &Request) -> OperationResult {
+    let args = req.arguments();
+    let ids = match args[2..]
+        .iter()
+        .map(|id| parse_id(id))
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(ids) => ids,
+        Err(e) => return e.into(),
+    };
+    match read_stream(ctx.repo, &args[0]) {
+        Ok(stream) => {
+            let acknowledged = stream
+                .and_then(|stream| stream.groups.get_mut(&args[1]))
+                .map_or(0, |group| {
+                    ids.iter()
+                        .filter(|id| group.pending.remove(id).is_some())
+                        .count()
+                });
+            OperationResult::Int(acknowledged as i64)
+        }
+        Err(e) => e.into(),
+    }
+}
+
+/// `XPENDING key group [[IDLE min-idle-time] start end count [consumer]]`
+///
+/// Without a range, summarizes the pending entries of the group and how
+/// many each consumer has.
+pub fn xpending(ctx: &mut Context, req: &Request) -> OperationResult {
+    let args = req.arguments();
+    let group = match read_group_stream(ctx.repo, &args[0], &args[1]) {
+        Ok(stream) => &stream.groups[&args[1]],
+        Err(e) => return e.into(),
+    };
+    if args.len() == 2 {
+        return pending_summary(group);
+    }
+
+    let mut range = &args[2..];
+    let mut min_idle = 0;
+    if range[0].eq_ignore_ascii_case(b"IDLE") {
+        let Some(idle) = range.get(1) else {
+            return OperationError::Syntax.into();
+        };
+        match parse_int(idle) {
+            Some(idle) => min_idle = idle.max(0) as u64,
+            None => return OperationError::NotAnInteger.into(),
+        }
+        range = &range[2..];
+    }
+    let (start, end, count, consumer) = match range {
+        [start, end, count] => (start, end, count, None),
+        [start, end, count, consumer] => (start, end, count, Some(consumer)),
+        _ => return OperationError::Syntax.into(),
+    };
+    let Some(count) = parse_int(count) else {
+        return OperationError::NotAnInteger.into();
+    };
+    let bounds = parse_range_start(start).and_then(|start| Ok((start, parse_range_end(end)?)));
+    let (start, end) = match bounds {
+        Ok((Some(start), Some(end))) if start <= end => (start, end),
+        Ok(_) => return OperationResult::Array(vec![]),
+        Err(e) => return e.into(),
+    };
+
+    let now = unix_millis();
+    OperationResult::Array(
+        group
+            .pending
+            .range(start..=end)
+            .filter(|(_, entry)| consumer.is_none_or(|consumer| entry.consumer == *consumer))
+            .filter(|(_, entry)| now.saturating_sub(entry.delivered_at) >= min_idle)
+            .take(count.max(0) as usize)
+            .map(|(id, entry)| {
+                OperationResult::Array(vec![
+                    id_reply(*id),
+                    OperationResult::StringRes(entry.consumer.clone()),
+                    OperationResult::Int(now.saturating_sub(entry.delivered_at) as i64),
+                    OperationResult::Int(entry.delivery_count as i64),
+                ])
+            })
+            .collect(),
+    )
+}
+
+fn pending_summary(group: &ConsumerGroup) -> OperationResult {
+    let (Some((first, _)), Some((last, _))) = (
+        group.pending.first_key_value(),
+        group.pending.last_key_value(),
+    ) else {
+        return OperationResult::Array(vec![
+            OperationResult::Int(0),
+            OperationResult::Nil,
+            OperationResult::Nil,
+            OperationResult::NullArray,
+        ]);
+    };
+    let consumers = group
+        .consumers
+        .keys()
+        .filter_map(|name| match group.pending_of(name).count() {
+            0 => None,
+            count => Some(OperationResult::Array(vec![
+                OperationResult::StringRes(name.clone()),
+                OperationResult::StringRes(count.to_string().into_bytes()),
+            ])),
+        })
+        .collect();
+    OperationResult::Array(vec![
+        OperationResult::Int(group.pending.len() as i64),
+        id_reply(*first),
+        id_reply(*last),
+        OperationResult::Array(consumers),
+    ])
+}
+
+/// `XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms] [TIME
+/// unix-time-milliseconds] [RETRYCOUNT count] [FORCE] [JUSTID] [LASTID
+/// lastid]`
+pub fn xclaim(ctx: &mut Context, req: &Request) -> OperationResult {
+    let args = req.arguments();
+    let (key, group, consumer) = (&args[0], &args[1], &args[2]);
+    let Some(min_idle) = parse_int(&args[3]) else {
+        return OperationError::NotAnInteger.into();
+    };
+    let ids: Vec<StreamId> = args[4..]
+        .iter()
+        .map_while(|id| StreamId::parse(id, 0))
+        .collect();
+    if ids.is_empty() {
+        return OperationError::InvalidStreamId.into();
+    }
+
+    let now = unix_millis();
+    let mut claim = ClaimOptions {
+        min_idle: min_idle.max(0) as u64,
+        delivered_at: now,
+        ..ClaimOptions::default()
+    };
+    let mut last_id = None;
+    let mut options = args[4 + ids.len()..].iter();
+    while let Some(option) = options.next() {
+        match option.to_ascii_uppercase().as_slice() {
+            b"FORCE" => claim.force = true,
+            b"JUSTID" => claim.just_id = true,
+            b"LASTID" => match options.next().map(|id| parse_id(id)) {
+                Some(Ok(id)) => last_id = Some(id),
+                Some(Err(e)) => return e.into(),
+                None => return OperationError::Syntax.into(),
+            },
+            option @ (b"IDLE" | b"TIME" | b"RETRYCOUNT") => {
+                let value = match options.next().map(|value| parse_int(value)) {
+                    Some(Some(value)) => value.max(0) as u64,
+                    Some(None) => return OperationError::NotAnInteger.into(),
+                    None => return OperationError::Syntax.into(),
+                };
+                match option {
+                    b"IDLE" => claim.delivered_at = now.saturating_sub(value),
+                    b"TIME" => claim.delivered_at = value,
+                    _ => claim.retry_count = Some(value),
+                }
+            }
+            _ => return OperationError::Syntax.into(),
+        }
+    }
+
+    let stream = match read_group_stream(ctx.repo, key, group) {
+        Ok(stream) => stream,
+        Err(e) => return e.into(),
+    };
+    let cursor = stream.groups.get_mut(group).expect("group was checked");
+    cursor.consumer(consumer, now);
+    if let Some(last_id) = last_id {
+        cursor.last_delivered = cursor.last_delivered.max(last_id);
+    }
+    OperationResult::Array(
+        ids.into_iter()
+            .filter_map(|id| match stream.claim(group, consumer, id, &claim, now)? {
+                Claim::Claimed(_) if claim.just_id => Some(id_reply(id)),
+                Claim::Claimed(fields) => Some(entry_reply(id, Some(fields))),
+                Claim::Deleted | Claim::Skipped => None,
+            })
+            .collect(),
+    )
+}
+
+/// `XAUTOCLAIM key group consumer min-idle-time start [COUNT count]
+/// [JUSTID]`
+///
+/// Claims up to `count` entries idle long enough, looking at no more than
+/// ten times as many, and replies with the ID to continue from along with
+/// the claimed entries and the IDs of pending entries no longer in the
+/// stream.
+pub fn xautoclaim(ctx: &mut Context, req: &Request) -> OperationResult {
+    let args = req.arguments();
+    let (key, group, consumer) = (&args[0], &args[1], &args[2]);
+    let Some(min_idle) = parse_int(&args[3]) else {
+        return OperationError::NotAnInteger.into();
+    };
+    let start = match parse_range_start(&args[4]) {
+        Ok(start) => start,
+        Err(e) => return e.into(),
+    };
+    let mut count = 100;
+    let mut just_id = false;
+    let mut options = args[5..].iter();
+    while let Some(option) = options.next() {
+        match option.to_ascii_uppercase().as_slice() {
+            b"COUNT" => match options.next().map(|count| parse_int(count)) {
+                Some(Some(value)) if value > 0 => count = value as usize,
+                Some(Some(_)) => return OperationError::CountNotPositive.into(),
+                Some(None) => return OperationError::NotAnInteger.into(),
+                None => return OperationError::Syntax.into(),
+            },
+            b"JUSTID" => just_id = true,
+            _ => return OperationError::Syntax.into(),
+        }
+    }
+
+    let stream = match read_group_stream(ctx.repo, key, group) {
+        Ok(stream) => stream,
+        Err(e) => return e.into(),
+    };
+    let now = unix_millis();
+    let cursor = stream.groups.get_mut(group).expect("group was checked");
+    cursor.consumer(consumer, now);
+    let candidates: Vec<StreamId> = match start {
+        Some(start) => cursor
+            .pending
+            .range(start..)
+            .map(|(id, _)| *id)
+            .take(count.saturating_mul(10))
+            .collect(),
+        None => vec![],
+    };
+
+    let claim = ClaimOptions {
+        min_idle: min_idle.max(0) as u64,
+        delivered_at: now,
+        just_id,
+        ..ClaimOptions::default()
+    };
+    let (mut claimed, mut deleted) = (vec![], vec![]);
+    let mut examined = 0;
+    for id in &candidates {
+        if claimed.len() == count {
+            break;
+        }
+        examined += 1;
+        match stream.claim(group, consumer, *id, &claim, now) {
+            Some(Claim::Claimed(_)) if just_id => claimed.push(id_reply(*id)),
+            Some(Claim::Claimed(fields)) => claimed.push(entry_reply(*id, Some(fields))),
+            Some(Claim::Deleted) => deleted.push(id_reply(*id)),
+            Some(Claim::Skipped) | None => {}
+        }
+    }
+
+    let next = candidates[..examined]
+        .last()
+        .and_then(|last| last.next())
+        .and_then(|after| stream.groups[group].pending.range(after..).next())
+        .map_or(StreamId::MIN, |(id, _)| *id);
+    OperationResult::Array(vec![
+        id_reply(next),
+        OperationResult::Array(claimed),
+        OperationResult::Array(deleted),
+    ])
+}
+
+/// `XGROUP CREATE | SETID | DESTROY | CREATECONSUMER | DELCONSUMER ...`
+pub fn xgroup(ctx: &mut Context, req: &Request) -> OperationResult {
+    let subcommand = &req.arguments()[0];
+    let args = &req.arguments()[1..];
+    let result = match subcommand.to_ascii_uppercase().as_slice() {
+        b"CREATE" => xgroup_create(ctx, args),
+        b"SETID" => xgroup_setid(ctx, args),
+        b"DESTROY" => xgroup_destroy(ctx, args),
+        b"CREATECONSUMER" => xgroup_consumer(ctx, args, true),
+        b"DELCONSUMER" => xgroup_consumer(ctx, args, false),
+        _ => Err(OperationError::UnknownSubcommand(
+            String::from_utf8_lossy(subcommand).to_string(),
+        )),
+    };
+    result.unwrap_or_else(OperationResult::from)
+}
+
+/// Parses the ID a group reads after, where `$`, given as `None`, means only
+/// entries added from now on.
+fn parse_group_id(arg: &[u8]) -> Result<Option<StreamId>, OperationError> {
+    match arg {
+        b"$" => Ok(None),
+        _ => parse_id(arg).map(Some),
+    }
+}
+
+/// Parses the `ENTRIESREAD` option `XGROUP CREATE` and `XGROUP SETID` accept.
+/// Lag isn't tracked, so the value only needs to be valid.
+fn parse_entries_read(args: &[Vec<u8>]) -> Result<(), OperationError> {
+    match args {
+        [] => Ok(()),
+        [option, value] if option.eq_ignore_ascii_case(b"ENTRIESREAD") => parse_int(value)
+            .map(|_| ())
+            .ok_or(OperationError::NotAnInteger),
+        _ => Err(OperationError::Syntax),
+    }
+}
+
+/// `XGROUP CREATE key group id | $ [MKSTREAM] [ENTRIESREAD entries-read]`
+fn xgroup_create(ctx: &mut Context, args: &[Vec<u8>]) -> Result<OperationResult, OperationError> {
+    let [key, group, id, options @ ..] = args else {
+        return Err(OperationError::WrongArity("xgroup|create"));
+    };
+    let mkstream = options
+        .first()
+        .is_some_and(|option| option.eq_ignore_ascii_case(b"MKSTREAM"));
+    parse_entries_read(&options[mkstream as usize..])?;
+    let id = parse_group_id(id)?;
+
+    let stream = match read_stream(ctx.repo, key)? {
+        Some(stream) => stream,
+        None if mkstream => stream_for_update(ctx.repo, key)?,
+        None => return Err(OperationError::XgroupNoKey),
+    };
+    let id = id.unwrap_or(stream.last_id);
+    if stream.groups.contains_key(group) {
+        return Err(OperationError::BusyGroup);
+    }
+    stream.groups.insert(group.to_vec(), ConsumerGroup::new(id));
+    Ok(OperationResult::Ok)
+}
+
+/// `XGROUP SETID key group id | $ [ENTRIESREAD entries-read]`
+fn xgroup_setid(ctx: &mut Context, args: &[Vec<u8>]) -> Result<OperationResult, OperationError> {
+    let [key, group, id, options @ ..] = args else {
+        return Err(OperationError::WrongArity("xgroup|setid"));
+    };
+    parse_entries_read(options)?;
+    let id = parse_group_id(id)?;
+    if read_stream(ctx.repo, key)?.is_none() {
+        return Err(OperationError::XgroupNoKey);
+    }
+    let stream = read_group_stream(ctx.repo, key, group)?;
+    let id = id.unwrap_or(stream.last_id);
+    stream
+        .groups
+        .get_mut(group)
+        .expect("group was checked")
+        .last_delivered = id;
+    Ok(OperationResult::Ok)
+}
+
+/// `XGROUP DESTROY key group`
+fn xgroup_destroy(ctx: &mut Context, args: &[Vec<u8>]) -> Result<OperationResult, OperationError> {
+    let [key, group] = args else {
+        return Err(OperationError::WrongArity("xgroup|destroy"));
+    };
+    let stream = read_stream(ctx.repo, key)?.ok_or(OperationError::XgroupNoKey)?;
+    Ok(OperationResult::Int(
+        stream.groups.remove(group).is_some() as i64
+    ))
+}
+
+/// `XGROUP CREATECONSUMER key group consumer` and `XGROUP DELCONSUMER key
+/// group consumer`. Deleting replies with how many entries were pending for
+/// the consumer.
+fn xgroup_consumer(
+    ctx: &mut Context,
+    args: &[Vec<u8>],
+    create: bool,
+) -> Result<OperationResult, OperationError> {
+    let [key, group, consumer] = args else {
+        let subcommand = if create {
+            "xgroup|createconsumer"
+        } else {
+            "xgroup|delconsumer"
+        };
+        return Err(OperationError::WrongArity(subcommand));
+    };
+    if read_stream(ctx.repo, key)?.is_none() {
+        return Err(OperationError::XgroupNoKey);
+    }
+    let stream = read_group_stream(ctx.repo, key, group)?;
+    let group = stream.groups.get_mut(group).expect("group was checked");
+    let reply = if create {
+        group.create_consumer(consumer, unix_millis()) as usize
+    } else {
+        group.delete_consumer(consumer)
+    };
+    Ok(OperationResult::Int(reply as i64))
+}
+
+/// `XINFO STREAM | GROUPS | CONSUMERS ...`
+pub fn xinfo(ctx: &mut Context, req: &Request) -> OperationResult {
+    let subcommand = &req.arguments()[0];
+    let args = &req.arguments()[1..];
+    let result = match subcommand.to_ascii_uppercase().as_slice() {
+        b"STREAM" => xinfo_stream(ctx, args),
+        b"GROUPS" => xinfo_groups(ctx, args),
+        b"CONSUMERS" => xinfo_consumers(ctx, args),
+        _ => Err(OperationError::UnknownSubcommand(
+            String::from_utf8_lossy(subcommand).to_string(),
+        )),
+    };
+    result.unwrap_or_else(OperationResult::from)
+}
+
+fn info_field(name: &str, value: OperationResult) -> (OperationResult, OperationResult) {
+    (OperationResult::StringRes(name.as_bytes().to_vec()), value)
+}
+
+/// Like `read_stream`, but a missing key is an error.
+fn read_existing_stream<'a>(
+    repo: &'a mut Repository,
+    key: &[u8],
+) -> Result<&'a mut Stream, OperationError> {
+    read_stream(repo, key)?.ok_or(OperationError::NoSuchKey)
+}
+
+/// `XINFO STREAM key`
+fn xinfo_stream(ctx: &mut Context, args: &[Vec<u8>]) -> Result<OperationResult, OperationError> {
+    let [key] = args else {
+        return Err(OperationError::WrongArity("xinfo|stream"));
+    };
+    let stream = read_existing_stream(ctx.repo, key)?;
+    let entry = |entry: Option<(StreamId, &Fields)>| {
+        entry.map_or(OperationResult::Nil, |(id, fields)| {
+            entry_reply(id, Some(fields.clone()))
+        })
+    };
+    Ok(OperationResult::Map(vec![
+        info_field("length", OperationResult::Int(stream.len() as i64)),
+        info_field("last-generated-id", id_reply(stream.last_id)),
+        info_field("max-deleted-entry-id", id_reply(stream.max_deleted_id)),
+        info_field(
+            "entries-added",
+            OperationResult::Int(stream.entries_added as i64),
+        ),
+        info_field("groups", OperationResult::Int(stream.groups.len() as i64)),
+        info_field("first-entry", entry(stream.first())),
+        info_field("last-entry", entry(stream.last())),
+    ]))
+}
+
+/// `XINFO GROUPS key`
+fn xinfo_groups(ctx: &mut Context, args: &[Vec<u8>]) -> Result<OperationResult, OperationError> {
+    let [key] = args else {
+        return Err(OperationError::WrongArity("xinfo|groups"));
+    };
+    let stream = read_existing_stream(ctx.repo, key)?;
+    Ok(OperationResult::Array(
+        stream
+            .groups
+            .iter()
+            .map(|(name, group)| {
+                OperationResult::Map(vec![
+                    info_field("name", OperationResult::StringRes(name.clone())),
+                    info_field(
+                        "consumers",
+                        OperationResult::Int(group.consumers.len() as i64),
+                    ),
+                    info_field("pending", OperationResult::Int(group.pending.len() as i64)),
+                    info_field("last-delivered-id", id_reply(group.last_delivered)),
+                ])
+            })
+            .collect(),
+    ))
+}
+
+/// `XINFO CONSUMERS key group`. Idle times are the milliseconds since a
+/// consumer last tried to read or claim, and inactive times since it last
+/// got something, or -1 if it never did.
+fn xinfo_consumers(ctx: &mut Context, args: &[Vec<u8>]) -> Result<OperationResult, OperationError> {
+    let [key, group] = args else {
+        return Err(OperationError::WrongArity("xinfo|consumers"));
+    };
+    read_existing_stream(ctx.repo, key)?;
+    let group = &read_group_stream(ctx.repo, key, group)?.groups[group];
+    let now = unix_millis();
+    Ok(OperationResult::Array(
+        group
+            .consumers
+            .iter()
+            .map(|(name, consumer)| {
+                let inactive = consumer
+                    .active_at
+                    .map_or(-1, |active_at| now.saturating_sub(active_at) as i64);
+                OperationResult::Map(vec![
+                    info_field("name", OperationResult::StringRes(name.clone())),
+                    info_field(
+                        "pending",
+                        OperationResult::Int(group.pending_of(name).count() as i64),
+                    ),
+                    info_field(
+                        "idle",
+                        OperationResult::Int(now.saturating_sub(consumer.seen_at) as i64),
+                    ),
+                    info_field("inactive", OperationResult::Int(inactive)),
+                ])
+            })
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{operations::exec, repository::Repository};
+
+    use super::*;
+
+    fn bulk(s: &str) -> OperationResult {
+        OperationResult::StringRes(s.as_bytes().to_vec())
+    }
+
+    fn entry(id: &str, fields: &[&str]) -> OperationResult {
+        OperationResult::Array(vec![
+            bulk(id),
+            OperationResult::Array(fields.iter().map(|field| bulk(field)).collect()),
+        ])
+    }
+
+    fn ids(reply: OperationResult) -> Vec<String> {
+        let OperationResult::Array(entries) = reply else {
+            panic!("expected an array, got {:?}", reply);
+        };
+        entries
+            .into_iter()
+            .map(|entry| match entry {
+                OperationResult::Array(mut entry) => match entry.remove(0) {
+                    OperationResult::StringRes(id) => String::from_utf8(id).unwrap(),
+                    other => panic!("expected an ID, got {:?}", other),
+                },
+                other => panic!("expected an entry, got {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_xadd_ids() {
+        let mut repo = Repository::new();
+        assert_eq!(
+            exec(&mut repo, &["XADD", "s", "1-1", "f", "v"]),
+            bulk("1-1")
+        );
+        assert_eq!(
+            exec(&mut repo, &["XADD", "s", "1-*", "f", "v"]),
+            bulk("1-2")
+        );
+        assert_eq!(exec(&mut repo, &["XADD", "s", "5", "f", "v"]), bulk("5-0"));
+        assert_eq!(
+            exec(&mut repo, &["XADD", "s", "5-0", "f", "v"]),
+            OperationError::StreamIdTooSmall.into()
+        );
+        assert_eq!(
+            exec(&mut repo, &["XADD", "s", "4-*", "f", "v"]),
+            OperationError::StreamIdTooSmall.into()
+        );
+        let OperationResult::StringRes(id) =
+            exec(&mut repo, &["XADD", "s", "*", "a", "1", "b", "2"])
+        else {
+            panic!("expected an ID");
+        };
+        assert!(StreamId::parse(&id, 0).unwrap() > StreamId::new(5, 0));
+        assert_eq!(exec(&mut repo, &["XLEN", "s"]), OperationResult::Int(4));
+
+        assert_eq!(
+            exec(&mut repo, &["XADD", "new", "0-0", "f", "v"]),
+            OperationError::StreamIdZero.into()
+        );
+        assert_eq!(
+            exec(&mut repo, &["XADD", "new", "1-x", "f", "v"]),
+            OperationError::InvalidStreamId.into()
+        );
+        assert_eq!(
+            exec(&mut repo, &["XADD", "new", "NOMKSTREAM", "*", "f", "v"]),
+            OperationResult::Nil
+        );
+        assert_eq!(repo.get(b"new"), None);
+        assert_eq!(
+            exec(&mut repo, &["XADD", "new", "0-*", "f", "v"]),
+            bulk("0-1")
+        );
+        assert_eq!(
+            exec(&mut repo, &["XADD", "s", "*", "f"]),
+            OperationError::WrongArity("xadd").into()
+        );
+        exec(&mut repo, &["SET", "str", "v"]);
+        assert_eq!(
+            exec(&mut repo, &["XADD", "str", "*", "f", "v"]),
+            OperationError::WrongType.into()
+        );
+    }
+
+    #[test]
+    fn test_trimming() {
+        let mut repo = Repository::new();
+        for ms in 1..=5 {
+            exec(&mut repo, &["XADD", "s", &ms.to_string(), "f", "v"]);
+        }
+        assert_eq!(
+            exec(&mut repo, &["XADD", "s", "MAXLEN", "=", "4", "6", "f", "v"]),
+            bulk("6-0")
+        );
+        assert_eq!(
+            ids(exec(&mut repo, &["XRANGE", "s", "-", "+"])),
+            ["3-0", "4-0", "5-0", "6-0"]
+        );
+        assert_eq!(
+            exec(&mut repo, &["XTRIM", "s", "MAXLEN", "~", "0", "LIMIT", "1"]),
+            OperationResult::Int(1)
+        );
+        assert_eq!(
+            exec(&mut repo, &["XTRIM", "s", "MINID", "6"]),
+            OperationResult::Int(2)
+        );
+        assert_eq!(exec(&mut repo, &["XLEN", "s"]), OperationResult::Int(1));
+        let OperationResult::Map(info) = exec(&mut repo, &["XINFO", "STREAM", "s"]) else {
+            panic!("expected stream info");
+        };
+        assert_eq!(info[2], (bulk("max-deleted-entry-id"), bulk("5-0")));
+        assert_eq!(
+            exec(&mut repo, &["XTRIM", "missing", "MAXLEN", "0"]),
+            OperationResult::Int(0)
+        );
+
+        assert_eq!(
+            exec(&mut repo, &["XTRIM", "s", "MAXLEN", "1", "LIMIT", "1"]),
+            OperationError::LimitWithoutApprox.into()
+        );
+        assert_eq!(
+            exec(&mut repo, &["XTRIM", "s", "MAXLEN", "-1"]),
+            OperationError::StreamMaxLen.into()
+        );
+        assert_eq!(
+            exec(&mut repo, &["XTRIM", "s", "LIMIT", "1"]),
+            OperationError::Syntax.into()
+        );
+
+        assert_eq!(
+            exec(&mut repo, &["XDEL", "s", "6-0", "7-0"]),
+            OperationResult::Int(1)
+        );
+        assert_eq!(exec(&mut repo, &["XLEN", "s"]), OperationResult::Int(0));
+        assert_eq!(
+            exec(&mut repo, &["XADD", "s", "6-0", "f", "v"]),
+            OperationError::StreamIdTooSmall.into()
+        );
+    }
+
+    #[test]
+    fn test_xrange() {
+        let mut repo = Repository::new();
+        for id in ["1-0", "2-0", "2-1", "3-0"] {
+            exec(&mut repo, &["XADD", "s", id, "f", id]);
+        }
+        assert_eq!(
+            exec(&mut repo, &["XRANGE", "s", "-", "+", "COUNT", "1"]),
+            OperationResult::Array(vec![entry("1-0", &["f", "1-0"])])
+        );
+        assert_eq!(
+            ids(exec(&mut repo, &["XRANGE", "s", "2", "2"])),
+            ["2-0", "2-1"]
+        );
+        assert_eq!(
+            ids(exec(&mut repo, &["XRANGE", "s", "(2-0", "+"])),
+            ["2-1", "3-0"]
+        );
+        assert_eq!(
+            ids(exec(&mut repo, &["XRANGE", "s", "-", "(2-1"])),
+            ["1-0", "2-0"]
+        );
+        assert_eq!(
+            ids(exec(&mut repo, &["XREVRANGE", "s", "+", "-", "COUNT", "2"])),
+            ["3-0", "2-1"]
+        );
+        assert_eq!(
+            ids(exec(&mut repo, &["XRANGE", "s", "3", "1"])),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            ids(exec(&mut repo, &["XRANGE", "s", "-", "+", "COUNT", "0"])),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            exec(&mut repo, &["XRANGE", "s", "x", "+"]),
+            OperationError::InvalidStreamId.into()
+        );
+    }
+
+    #[test]
+    fn test_xread() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["XADD", "a", "1-0", "f", "v"]);
+        exec(&mut repo, &["XADD", "a", "2-0", "f", "w"]);
+        exec(&mut repo, &["XADD", "b", "1-0", "g", "x"]);
+        assert_eq!(
+            exec(
+                &mut repo,
+                &["XREAD", "COUNT", "1", "STREAMS", "a", "b", "0", "1"]
+            ),
+            OperationResult::Array(vec![OperationResult::Array(vec![
+                bulk("a"),
+                OperationResult::Array(vec![entry("1-0", &["f", "v"])]),
+            ])])
+        );
+        assert_eq!(
+            exec(&mut repo, &["XREAD", "STREAMS", "a", "missing", "$", "0"]),
+            OperationResult::NullArray
+        );
+        assert_eq!(
+            exec(&mut repo, &["XREAD", "STREAMS", "a", "b", "0"]),
+            OperationError::UnbalancedStreams("xread").into()
+        );
+        assert_eq!(
+            exec(&mut repo, &["XREAD", "BLOCK", "-1", "STREAMS", "a", "0"]),
+            OperationError::NegativeTimeout.into()
+        );
+        assert_eq!(
+            exec(&mut repo, &["XREAD", "BLOCK", "0.5", "STREAMS", "a", "0"]),
+            OperationError::TimeoutNotInteger.into()
+        );
+        assert_eq!(
+            exec(&mut repo, &["XREAD", "COUNT", "1", "a", "0"]),
+            OperationError::Syntax.into()
+        );
+    }
+
+    #[test]
+    fn test_consumer_groups() {
+        let mut repo = Repository::new();
+        for id in ["1-0", "2-0", "3-0"] {
+            exec(&mut repo, &["XADD", "s", id, "f", id]);
+        }
+        assert_eq!(
+            exec(&mut repo, &["XGROUP", "CREATE", "s", "g", "0"]),
+            OperationResult::Ok
+        );
+        assert_eq!(
+            exec(&mut repo, &["XGROUP", "CREATE", "s", "g", "$"]),
+            OperationError::BusyGroup.into()
+        );
+        assert_eq!(
+            exec(&mut repo, &["XGROUP", "CREATE", "missing", "g", "$"]),
+            OperationError::XgroupNoKey.into()
+        );
+        assert_eq!(
+            exec(
+                &mut repo,
+                &["XGROUP", "CREATE", "empty", "g", "$", "MKSTREAM"]
+            ),
+            OperationResult::Ok
+        );
+        assert_eq!(exec(&mut repo, &["XLEN", "empty"]), OperationResult::Int(0));
+
+        let read = |repo: &mut Repository, consumer: &str, id: &str| {
+            let reply = exec(
+                repo,
+                &[
+                    "XREADGROUP",
+                    "GROUP",
+                    "g",
+                    consumer,
+                    "COUNT",
+                    "2",
+                    "STREAMS",
+                    "s",
+                    id,
+                ],
+            );
+            match reply {
+                OperationResult::Array(mut streams) => match streams.remove(0) {
+                    OperationResult::Array(mut stream) => ids(stream.remove(1)),
+                    other => panic!("expected a stream, got {:?}", other),
+                },
+                other => panic!("expected streams, got {:?}", other),
+            }
+        };
+        assert_eq!(read(&mut repo, "alice", ">"), ["1-0", "2-0"]);
+        assert_eq!(read(&mut repo, "bob", ">"), ["3-0"]);
+        assert_eq!(
+            exec(
+                &mut repo,
+                &["XREADGROUP", "GROUP", "g", "bob", "STREAMS", "s", ">"]
+            ),
+            OperationResult::NullArray
+        );
+        assert_eq!(read(&mut repo, "alice", "0"), ["1-0", "2-0"]);
+        assert_eq!(read(&mut repo, "alice", "1-0"), ["2-0"]);
+
+        assert_eq!(
+            exec(&mut repo, &["XPENDING", "s", "g"]),
+            OperationResult::Array(vec![
+                OperationResult::Int(3),
+                bulk("1-0"),
+                bulk("3-0"),
+                OperationResult::Array(vec![
+                    OperationResult::Array(vec![bulk("alice"), bulk("2")]),
+                    OperationResult::Array(vec![bulk("bob"), bulk("1")]),
+                ]),
+            ])
+        );
+        assert_eq!(
+            exec(&mut repo, &["XACK", "s", "g", "1-0", "9-0"]),
+            OperationResult::Int(1)
+        );
+        assert_eq!(
+            ids(exec(
+                &mut repo,
+                &["XPENDING", "s", "g", "-", "+", "10", "alice"]
+            )),
+            ["2-0"]
+        );
+
+        assert_eq!(
+            exec(&mut repo, &["XCLAIM", "s", "g", "bob", "0", "2-0"]),
+            OperationResult::Array(vec![entry("2-0", &["f", "2-0"])])
+        );
+        assert_eq!(
+            exec(
+                &mut repo,
+                &["XCLAIM", "s", "g", "bob", "3600000", "3-0", "JUSTID"]
+            ),
+            OperationResult::Array(vec![])
+        );
+        let OperationResult::Array(pending) =
+            exec(&mut repo, &["XPENDING", "s", "g", "2-0", "2-0", "1"])
+        else {
+            panic!("expected pending entries");
+        };
+        let OperationResult::Array(pending) = &pending[0] else {
+            panic!("expected a pending entry");
+        };
+        assert_eq!((&pending[0], &pending[1]), (&bulk("2-0"), &bulk("bob")));
+        assert_eq!(pending[3], OperationResult::Int(2));
+
+        exec(&mut repo, &["XDEL", "s", "3-0"]);
+        assert_eq!(
+            exec(
+                &mut repo,
+                &["XAUTOCLAIM", "s", "g", "alice", "0", "0", "COUNT", "1"]
+            ),
+            OperationResult::Array(vec![
+                bulk("3-0"),
+                OperationResult::Array(vec![entry("2-0", &["f", "2-0"])]),
+                OperationResult::Array(vec![]),
+            ])
+        );
+        assert_eq!(
+            exec(
+                &mut repo,
+                &["XAUTOCLAIM", "s", "g", "alice", "0", "3-0", "JUSTID"]
+            ),
+            OperationResult::Array(vec![
+                bulk("0-0"),
+                OperationResult::Array(vec![]),
+                OperationResult::Array(vec![bulk("3-0")]),
+            ])
+        );
+
+        assert_eq!(
+            exec(&mut repo, &["XGROUP", "DELCONSUMER", "s", "g", "alice"]),
+            OperationResult::Int(1)
+        );
+        assert_eq!(
+            exec(&mut repo, &["XGROUP", "DESTROY", "s", "g"]),
+            OperationResult::Int(1)
+        );
+        let no_group = OperationError::NoGroup("s".to_string(), "g".to_string());
+        assert_eq!(
+            exec(&mut repo, &["XPENDING", "s", "g"]),
+            no_group.clone().into()
+        );
+        assert_eq!(
+            exec(
+                &mut repo,
+                &["XREADGROUP", "GROUP", "g", "bob", "STREAMS", "s", ">"]
+            ),
+            no_group.into()
+        );
+        assert_eq!(
+            exec(&mut repo, &["XACK", "s", "g", "2-0"]),
+            OperationResult::Int(0)
+        );
+    }
+
+    #[test]
+    fn test_noack_and_setid() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["XADD", "s", "1-0", "f", "v"]);
+        exec(&mut repo, &["XGROUP", "CREATE", "s", "g", "$"]);
+        assert_eq!(
+            exec(
+                &mut repo,
+                &["XREADGROUP", "GROUP", "g", "c", "STREAMS", "s", ">"]
+            ),
+            OperationResult::NullArray
+        );
+        assert_eq!(
+            exec(&mut repo, &["XGROUP", "SETID", "s", "g", "0"]),
+            OperationResult::Ok
+        );
+        exec(
+            &mut repo,
+            &[
+                "XREADGROUP",
+                "GROUP",
+                "g",
+                "c",
+                "NOACK",
+                "STREAMS",
+                "s",
+                ">",
+            ],
+        );
+        assert_eq!(
+            exec(&mut repo, &["XPENDING", "s", "g"]),
+            OperationResult::Array(vec![
+                OperationResult::Int(0),
+                OperationResult::Nil,
+                OperationResult::Nil,
+                OperationResult::NullArray,
+            ])
+        );
+        assert_eq!(
+            exec(&mut repo, &["XGROUP", "CREATECONSUMER", "s", "g", "c"]),
+            OperationResult::Int(0)
+        );
+        assert_eq!(
+            exec(&mut repo, &["XGROUP", "CREATECONSUMER", "s", "g", "d"]),
+            OperationResult::Int(1)
+        );
+        assert_eq!(
+            exec(&mut repo, &["XGROUP", "NOPE", "s", "g"]),
+            OperationError::UnknownSubcommand("NOPE".to_string()).into()
+        );
+    }
+
+    #[test]
+    fn test_xinfo() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["XADD", "s", "1-0", "f", "v"]);
+        exec(&mut repo, &["XADD", "s", "2-0", "f", "w"]);
+        exec(&mut repo, &["XDEL", "s", "2-0"]);
+        exec(&mut repo, &["XGROUP", "CREATE", "s", "g", "0"]);
+        exec(
+            &mut repo,
+            &["XREADGROUP", "GROUP", "g", "c", "STREAMS", "s", ">"],
+        );
+
+        let field = |name: &str, value| (bulk(name), value);
+        assert_eq!(
+            exec(&mut repo, &["XINFO", "STREAM", "s"]),
+            OperationResult::Map(vec![
+                field("length", OperationResult::Int(1)),
+                field("last-generated-id", bulk("2-0")),
+                field("max-deleted-entry-id", bulk("2-0")),
+                field("entries-added", OperationResult::Int(2)),
+                field("groups", OperationResult::Int(1)),
+                field("first-entry", entry("1-0", &["f", "v"])),
+                field("last-entry", entry("1-0", &["f", "v"])),
+            ])
+        );
+        assert_eq!(
+            exec(&mut repo, &["XINFO", "GROUPS", "s"]),
+            OperationResult::Array(vec![OperationResult::Map(vec![
+                field("name", bulk("g")),
+                field("consumers", OperationResult::Int(1)),
+                field("pending", OperationResult::Int(1)),
+                field("last-delivered-id", bulk("1-0")),
+            ])])
+        );
+        let OperationResult::Array(consumers) = exec(&mut repo, &["XINFO", "CONSUMERS", "s", "g"])
+        else {
+            panic!("expected consumers");
+        };
+        let OperationResult::Map(consumer) = &consumers[0] else {
+            panic!("expected a consumer");
+        };
+        assert_eq!(
+            consumer[..2],
+            [
+                field("name", bulk("c")),
+                field("pending", OperationResult::Int(1))
+            ]
+        );
+
+        assert_eq!(
+            exec(&mut repo, &["XINFO", "STREAM", "missing"]),
+            OperationError::NoSuchKey.into()
+        );
+        assert_eq!(
+            exec(&mut repo, &["XINFO", "CONSUMERS", "s", "nope"]),
+            OperationError::NoGroup("s".to_string(), "nope".to_string()).into()
+        );
+    }
+}
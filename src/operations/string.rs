@@ -1,24 +1,602 @@
-use crate::{repository::Repository, record::{Record}, request::Request};
+use std::time::{Duration, Instant};
 
-use super::OperationResult;
+use crate::{record::{parse_canonical_int, Record}, request::Request};
 
-pub fn get(repo: &mut Repository, req: &Request) -> OperationResult {
+use super::{
+    instant_from_unix_millis, parse_float, parse_int, Context, OperationError, OperationResult,
+};
+
+/// The largest string `SETRANGE` will grow a value to, matching Redis'
+/// default `proto-max-bulk-len` of 512MB.
+const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
+
+pub fn get(ctx: &mut Context, req: &Request) -> OperationResult {
     let key = &req.arguments()[0];
-    if let Some(record) = repo.get(key.to_string()) {
-        match record {
-            Record::String(s) => OperationResult::StringRes(s),
-            _ => OperationResult::Error("wrongtype".to_string()),
+    if let Some(record) = ctx.repo.get(key) {
+        match record.string_value() {
+            Some(s) => OperationResult::StringRes(s),
+            None => OperationError::WrongType.into(),
         }
     } else {
         OperationResult::Nil
     }
 }
 
-pub fn set(repo: &mut Repository, req: &Request) -> OperationResult {
+/// How `SET` treats the expiration of the key it writes.
+enum Expiry {
+    Clear,
+    Keep,
+    At(Instant),
+}
+
+#[derive(PartialEq)]
+enum Condition {
+    Always,
+    IfMissing,
+    IfExists,
+}
+
+/// `SET key value [NX | XX] [GET] [EX seconds | PX milliseconds |
+/// EXAT unix-time-seconds | PXAT unix-time-milliseconds | KEEPTTL]`
+pub fn set(ctx: &mut Context, req: &Request) -> OperationResult {
     let key = &req.arguments()[0];
     let val = &req.arguments()[1];
-    let record = Record::String(val.to_string());
 
-    repo.set(key.to_string(), record);
+    let mut condition = Condition::Always;
+    let mut expiry = Expiry::Clear;
+    let mut get_old = false;
+    let mut options = req.arguments()[2..].iter();
+    while let Some(option) = options.next() {
+        let option = option.to_ascii_uppercase();
+        match option.as_slice() {
+            b"NX" | b"XX" if condition != Condition::Always => return OperationError::Syntax.into(),
+            b"NX" => condition = Condition::IfMissing,
+            b"XX" => condition = Condition::IfExists,
+            b"GET" => get_old = true,
+            _ if !matches!(expiry, Expiry::Clear) => return OperationError::Syntax.into(),
+            b"KEEPTTL" => expiry = Expiry::Keep,
+            b"EX" | b"PX" | b"EXAT" | b"PXAT" => {
+                match parse_expiration("set", &option, options.next()) {
+                    Ok(at) => expiry = Expiry::At(at),
+                    Err(e) => return e.into(),
+                }
+            }
+            _ => return OperationError::Syntax.into(),
+        }
+    }
+
+    let old = match ctx.repo.get(key).map(|record| record.string_value()) {
+        Some(Some(s)) => Some(s),
+        Some(None) if get_old => return OperationError::WrongType.into(),
+        Some(None) => Some(vec![]),
+        None => None,
+    };
+    let old_reply = |old: Option<Vec<u8>>| match old {
+        Some(s) if get_old => OperationResult::StringRes(s),
+        _ => OperationResult::Nil,
+    };
+
+    match condition {
+        Condition::IfMissing if old.is_some() => return old_reply(old),
+        Condition::IfExists if old.is_none() => return old_reply(old),
+        _ => {}
+    }
+
+    ctx.repo.set(key.to_vec(), Record::from_string(val.to_vec()));
+    match expiry {
+        Expiry::Clear => {
+            ctx.repo.persist(key);
+        }
+        Expiry::Keep => {}
+        Expiry::At(at) => ctx.repo.set_expiration(key.to_vec(), at),
+    }
+
+    if get_old {
+        old_reply(old)
+    } else {
+        OperationResult::Ok
+    }
+}
+
+/// Parses the amount following an `EX`, `PX`, `EXAT` or `PXAT` option into
+/// the instant the key should expire at.
+fn parse_expiration(
+    command: &'static str,
+    unit: &[u8],
+    amount: Option<&Vec<u8>>,
+) -> Result<Instant, OperationError> {
+    let amount = amount.ok_or(OperationError::Syntax)?;
+    let amount = parse_int(amount).ok_or(OperationError::NotAnInteger)?;
+    let millis = match unit {
+        b"EX" | b"EXAT" => amount.checked_mul(1000),
+        _ => Some(amount),
+    };
+    let millis = millis
+        .filter(|millis| *millis > 0)
+        .ok_or(OperationError::InvalidExpireTime(command))?;
+    if unit.ends_with(b"AT") {
+        Ok(instant_from_unix_millis(millis as u64))
+    } else {
+        Instant::now()
+            .checked_add(Duration::from_millis(millis as u64))
+            .ok_or(OperationError::InvalidExpireTime(command))
+    }
+}
+
+/// `GETEX key [EX seconds | PX milliseconds | EXAT unix-time-seconds |
+/// PXAT unix-time-milliseconds | PERSIST]`
+pub fn getex(ctx: &mut Context, req: &Request) -> OperationResult {
+    let key = &req.arguments()[0];
+
+    let mut expiry = Expiry::Keep;
+    let mut options = req.arguments()[1..].iter();
+    while let Some(option) = options.next() {
+        let option = option.to_ascii_uppercase();
+        match option.as_slice() {
+            _ if !matches!(expiry, Expiry::Keep) => return OperationError::Syntax.into(),
+            b"PERSIST" => expiry = Expiry::Clear,
+            b"EX" | b"PX" | b"EXAT" | b"PXAT" => {
+                match parse_expiration("getex", &option, options.next()) {
+                    Ok(at) => expiry = Expiry::At(at),
+                    Err(e) => return e.into(),
+                }
+            }
+            _ => return OperationError::Syntax.into(),
+        }
+    }
+
+    let value = match ctx.repo.get(key).map(|record| record.string_value()) {
+        Some(Some(value)) => value,
+        Some(None) => return OperationError::WrongType.into(),
+        None => return OperationResult::Nil,
+    };
+    match expiry {
+        Expiry::Clear => {
+            ctx.repo.persist(key);
+        }
+        Expiry::Keep => {}
+        Expiry::At(at) => ctx.repo.set_expiration(key.to_vec(), at),
+    }
+    OperationResult::StringRes(value)
+}
+
+pub fn getdel(ctx: &mut Context, req: &Request) -> OperationResult {
+    let key = &req.arguments()[0];
+    match ctx.repo.get(key).map(|record| record.string_value()) {
+        Some(Some(value)) => {
+            ctx.repo.delete(key);
+            OperationResult::StringRes(value)
+        }
+        Some(None) => OperationError::WrongType.into(),
+        None => OperationResult::Nil,
+    }
+}
+
+pub fn setnx(ctx: &mut Context, req: &Request) -> OperationResult {
+    let key = &req.arguments()[0];
+    if ctx.repo.get(key).is_some() {
+        return OperationResult::Int(0);
+    }
+    ctx.repo.set(key.to_vec(), Record::from_string(req.arguments()[1].to_vec()));
+    OperationResult::Int(1)
+}
+
+/// Replies with the value of each key, or nil for keys that are missing or
+/// don't hold a string.
+pub fn mget(ctx: &mut Context, req: &Request) -> OperationResult {
+    let values = req
+        .arguments()
+        .iter()
+        .map(|key| match ctx.repo.get(key).and_then(|record| record.string_value()) {
+            Some(value) => OperationResult::StringRes(value),
+            None => OperationResult::Nil,
+        })
+        .collect();
+    OperationResult::Array(values)
+}
+
+pub fn mset(ctx: &mut Context, req: &Request) -> OperationResult {
+    let args = req.arguments();
+    if !args.len().is_multiple_of(2) {
+        return OperationError::WrongArity("mset").into();
+    }
+    set_pairs(ctx, args);
     OperationResult::Ok
-}
\ No newline at end of file
+}
+
+/// Sets every pair only if none of the keys exist yet.
+pub fn msetnx(ctx: &mut Context, req: &Request) -> OperationResult {
+    let args = req.arguments();
+    if !args.len().is_multiple_of(2) {
+        return OperationError::WrongArity("msetnx").into();
+    }
+    if args.chunks(2).any(|pair| ctx.repo.get(&pair[0]).is_some()) {
+        return OperationResult::Int(0);
+    }
+    set_pairs(ctx, args);
+    OperationResult::Int(1)
+}
+
+/// Writes alternating keys and values the way a plain `SET` would, dropping
+/// any previous expiration. Commands run one at a time, so no client sees
+/// the pairs half applied.
+fn set_pairs(ctx: &mut Context, args: &[Vec<u8>]) {
+    for pair in args.chunks(2) {
+        ctx.repo.set(pair[0].to_vec(), Record::from_string(pair[1].to_vec()));
+        ctx.repo.persist(&pair[0]);
+    }
+}
+
+/// Reads the string at `key`, or `None` if the key doesn't exist. Keys
+/// holding other types fail with `WRONGTYPE`.
+fn read_string(ctx: &mut Context, key: &[u8]) -> Result<Option<Vec<u8>>, OperationError> {
+    match ctx.repo.get(key).map(|record| record.string_value()) {
+        Some(Some(value)) => Ok(Some(value)),
+        Some(None) => Err(OperationError::WrongType),
+        None => Ok(None),
+    }
+}
+
+pub fn append(ctx: &mut Context, req: &Request) -> OperationResult {
+    let key = &req.arguments()[0];
+    let mut value = match read_string(ctx, key) {
+        Ok(value) => value.unwrap_or_default(),
+        Err(e) => return e.into(),
+    };
+    value.extend_from_slice(&req.arguments()[1]);
+    let len = value.len();
+    ctx.repo.set(key.to_vec(), Record::from_string(value));
+    OperationResult::Int(len as i64)
+}
+
+pub fn strlen(ctx: &mut Context, req: &Request) -> OperationResult {
+    match read_string(ctx, &req.arguments()[0]) {
+        Ok(value) => OperationResult::Int(value.map_or(0, |value| value.len()) as i64),
+        Err(e) => e.into(),
+    }
+}
+
+pub fn getrange(ctx: &mut Context, req: &Request) -> OperationResult {
+    let range = (parse_int(&req.arguments()[1]), parse_int(&req.arguments()[2]));
+    let (Some(start), Some(end)) = range else {
+        return OperationError::NotAnInteger.into();
+    };
+    let value = match read_string(ctx, &req.arguments()[0]) {
+        Ok(value) => value.unwrap_or_default(),
+        Err(e) => return e.into(),
+    };
+
+    let len = value.len() as i64;
+    let start = if start < 0 { (len + start).max(0) } else { start };
+    let end = if end < 0 { (len + end).max(0) } else { end.min(len - 1) };
+    if start > end || len == 0 {
+        return OperationResult::StringRes(vec![]);
+    }
+    OperationResult::StringRes(value[start as usize..=end as usize].to_vec())
+}
+
+pub fn setrange(ctx: &mut Context, req: &Request) -> OperationResult {
+    let key = &req.arguments()[0];
+    let patch = &req.arguments()[2];
+    let offset = match parse_int(&req.arguments()[1]) {
+        Some(offset) if offset < 0 => return OperationError::OffsetOutOfRange.into(),
+        Some(offset) => offset as usize,
+        None => return OperationError::NotAnInteger.into(),
+    };
+    let value = match read_string(ctx, key) {
+        Ok(value) => value,
+        Err(e) => return e.into(),
+    };
+
+    if patch.is_empty() {
+        return OperationResult::Int(value.map_or(0, |value| value.len()) as i64);
+    }
+    if offset + patch.len() > MAX_STRING_LEN {
+        return OperationError::StringTooLong.into();
+    }
+    let mut value = value.unwrap_or_default();
+    if value.len() < offset + patch.len() {
+        value.resize(offset + patch.len(), 0);
+    }
+    value[offset..offset + patch.len()].copy_from_slice(patch);
+    let len = value.len();
+    ctx.repo.set(key.to_vec(), Record::from_string(value));
+    OperationResult::Int(len as i64)
+}
+
+pub fn incr(ctx: &mut Context, req: &Request) -> OperationResult {
+    increment_by(ctx, &req.arguments()[0], 1)
+}
+
+pub fn decr(ctx: &mut Context, req: &Request) -> OperationResult {
+    increment_by(ctx, &req.arguments()[0], -1)
+}
+
+pub fn incrby(ctx: &mut Context, req: &Request) -> OperationResult {
+    match parse_int(&req.arguments()[1]) {
+        Some(increment) => increment_by(ctx, &req.arguments()[0], increment),
+        None => OperationError::NotAnInteger.into(),
+    }
+}
+
+pub fn decrby(ctx: &mut Context, req: &Request) -> OperationResult {
+    match parse_int(&req.arguments()[1]).map(i64::checked_neg) {
+        Some(Some(increment)) => increment_by(ctx, &req.arguments()[0], increment),
+        Some(None) => OperationError::Overflow.into(),
+        None => OperationError::NotAnInteger.into(),
+    }
+}
+
+/// Adds `increment` to the integer stored at `key`, treating a missing key
+/// as zero. The key keeps its expiration.
+fn increment_by(ctx: &mut Context, key: &[u8], increment: i64) -> OperationResult {
+    let current = match ctx.repo.get(key) {
+        Some(Record::Int(n)) => n,
+        Some(Record::String(s)) => match parse_canonical_int(&s) {
+            Some(n) => n,
+            None => return OperationError::NotAnInteger.into(),
+        },
+        Some(_) => return OperationError::WrongType.into(),
+        None => 0,
+    };
+    let Some(value) = current.checked_add(increment) else {
+        return OperationError::Overflow.into();
+    };
+    ctx.repo.set(key.to_vec(), Record::Int(value));
+    OperationResult::Int(value)
+}
+
+pub fn incrbyfloat(ctx: &mut Context, req: &Request) -> OperationResult {
+    let key = &req.arguments()[0];
+    let Some(increment) = parse_float(&req.arguments()[1]) else {
+        return OperationError::NotAFloat.into();
+    };
+    let current = match ctx.repo.get(key) {
+        Some(Record::Int(n)) => n as f64,
+        Some(Record::String(s)) => match parse_float(&s) {
+            Some(f) => f,
+            None => return OperationError::NotAFloat.into(),
+        },
+        Some(_) => return OperationError::WrongType.into(),
+        None => 0.0,
+    };
+    let value = current + increment;
+    if !value.is_finite() {
+        return OperationError::NanOrInfinity.into();
+    }
+    let value = value.to_string().into_bytes();
+    ctx.repo.set(key.to_vec(), Record::from_string(value.clone()));
+    OperationResult::StringRes(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{operations::exec, repository::Repository};
+
+    use super::*;
+
+    fn bulk(s: &str) -> OperationResult {
+        OperationResult::StringRes(s.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_set_conditions() {
+        let mut repo = Repository::new();
+        assert_eq!(exec(&mut repo, &["SET", "lock", "a", "XX"]), OperationResult::Nil);
+        assert_eq!(exec(&mut repo, &["SET", "lock", "a", "NX", "PX", "30000"]), OperationResult::Ok);
+        assert_eq!(exec(&mut repo, &["SET", "lock", "b", "NX"]), OperationResult::Nil);
+        assert_eq!(exec(&mut repo, &["SET", "lock", "b", "NX", "GET"]), bulk("a"));
+        assert_eq!(exec(&mut repo, &["GET", "lock"]), bulk("a"));
+        assert_eq!(exec(&mut repo, &["SET", "lock", "c", "XX", "GET"]), bulk("a"));
+        assert_eq!(exec(&mut repo, &["GET", "lock"]), bulk("c"));
+        assert_eq!(exec(&mut repo, &["SET", "fresh", "v", "GET"]), OperationResult::Nil);
+    }
+
+    #[test]
+    fn test_set_expiration() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["SET", "k", "v", "EX", "100"]);
+        assert_eq!(exec(&mut repo, &["TTL", "k"]), OperationResult::Int(100));
+
+        exec(&mut repo, &["SET", "k", "v2", "KEEPTTL"]);
+        assert_eq!(exec(&mut repo, &["TTL", "k"]), OperationResult::Int(100));
+
+        exec(&mut repo, &["SET", "k", "v3"]);
+        assert_eq!(exec(&mut repo, &["TTL", "k"]), OperationResult::Int(-1));
+
+        exec(&mut repo, &["SET", "k", "v4", "PXAT", "1"]);
+        assert_eq!(exec(&mut repo, &["GET", "k"]), OperationResult::Nil);
+
+        exec(&mut repo, &["SET", "k", "v5", "EXAT", "99999999999"]);
+        assert!(matches!(exec(&mut repo, &["TTL", "k"]), OperationResult::Int(ttl) if ttl > 0));
+    }
+
+    #[test]
+    fn test_set_invalid_options() {
+        let mut repo = Repository::new();
+        let syntax: OperationResult = OperationError::Syntax.into();
+        assert_eq!(exec(&mut repo, &["SET", "k", "v", "NX", "XX"]), syntax);
+        assert_eq!(exec(&mut repo, &["SET", "k", "v", "EX", "1", "PX", "1"]), syntax);
+        assert_eq!(exec(&mut repo, &["SET", "k", "v", "EX", "1", "KEEPTTL"]), syntax);
+        assert_eq!(exec(&mut repo, &["SET", "k", "v", "EX"]), syntax);
+        assert_eq!(exec(&mut repo, &["SET", "k", "v", "SOON"]), syntax);
+        assert_eq!(exec(&mut repo, &["SET", "k", "v", "EX", "ten"]), OperationError::NotAnInteger.into());
+        assert_eq!(
+            exec(&mut repo, &["SET", "k", "v", "PX", "0"]),
+            OperationError::InvalidExpireTime("set").into()
+        );
+        assert_eq!(exec(&mut repo, &["GET", "k"]), OperationResult::Nil);
+
+        exec(&mut repo, &["HSET", "h", "f", "v"]);
+        assert_eq!(exec(&mut repo, &["SET", "h", "v", "GET"]), OperationError::WrongType.into());
+    }
+
+    #[test]
+    fn test_append_and_strlen() {
+        let mut repo = Repository::new();
+        assert_eq!(exec(&mut repo, &["STRLEN", "s"]), OperationResult::Int(0));
+        assert_eq!(exec(&mut repo, &["APPEND", "s", "Hello"]), OperationResult::Int(5));
+        assert_eq!(exec(&mut repo, &["APPEND", "s", " World"]), OperationResult::Int(11));
+        assert_eq!(exec(&mut repo, &["GET", "s"]), bulk("Hello World"));
+        assert_eq!(exec(&mut repo, &["STRLEN", "s"]), OperationResult::Int(11));
+
+        exec(&mut repo, &["SET", "n", "12"]);
+        assert_eq!(exec(&mut repo, &["APPEND", "n", "3"]), OperationResult::Int(3));
+        assert_eq!(exec(&mut repo, &["INCR", "n"]), OperationResult::Int(124));
+        assert_eq!(exec(&mut repo, &["STRLEN", "n"]), OperationResult::Int(3));
+
+        exec(&mut repo, &["HSET", "h", "f", "v"]);
+        assert_eq!(exec(&mut repo, &["APPEND", "h", "x"]), OperationError::WrongType.into());
+        assert_eq!(exec(&mut repo, &["STRLEN", "h"]), OperationError::WrongType.into());
+    }
+
+    #[test]
+    fn test_getrange() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["SET", "s", "This is a string"]);
+        assert_eq!(exec(&mut repo, &["GETRANGE", "s", "0", "3"]), bulk("This"));
+        assert_eq!(exec(&mut repo, &["GETRANGE", "s", "-3", "-1"]), bulk("ing"));
+        assert_eq!(exec(&mut repo, &["GETRANGE", "s", "0", "-1"]), bulk("This is a string"));
+        assert_eq!(exec(&mut repo, &["GETRANGE", "s", "10", "100"]), bulk("string"));
+        assert_eq!(exec(&mut repo, &["GETRANGE", "s", "-100", "3"]), bulk("This"));
+        assert_eq!(exec(&mut repo, &["GETRANGE", "s", "5", "3"]), bulk(""));
+        assert_eq!(exec(&mut repo, &["GETRANGE", "s", "100", "200"]), bulk(""));
+        assert_eq!(exec(&mut repo, &["GETRANGE", "missing", "0", "-1"]), bulk(""));
+        assert_eq!(exec(&mut repo, &["GETRANGE", "s", "a", "1"]), OperationError::NotAnInteger.into());
+    }
+
+    #[test]
+    fn test_setrange() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["SET", "s", "Hello World"]);
+        assert_eq!(exec(&mut repo, &["SETRANGE", "s", "6", "Redis"]), OperationResult::Int(11));
+        assert_eq!(exec(&mut repo, &["GET", "s"]), bulk("Hello Redis"));
+
+        assert_eq!(exec(&mut repo, &["SETRANGE", "pad", "3", "x"]), OperationResult::Int(4));
+        assert_eq!(exec(&mut repo, &["GET", "pad"]), bulk("\0\0\0x"));
+
+        assert_eq!(exec(&mut repo, &["SETRANGE", "empty", "5", ""]), OperationResult::Int(0));
+        assert_eq!(exec(&mut repo, &["GET", "empty"]), OperationResult::Nil);
+
+        assert_eq!(exec(&mut repo, &["SETRANGE", "s", "-1", "x"]), OperationError::OffsetOutOfRange.into());
+        assert_eq!(
+            exec(&mut repo, &["SETRANGE", "s", "536870912", "x"]),
+            OperationError::StringTooLong.into()
+        );
+    }
+
+    #[test]
+    fn test_getdel_and_getex() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["SET", "s", "v"]);
+        assert_eq!(exec(&mut repo, &["GETEX", "s", "EX", "100"]), bulk("v"));
+        assert_eq!(exec(&mut repo, &["TTL", "s"]), OperationResult::Int(100));
+        assert_eq!(exec(&mut repo, &["GETEX", "s"]), bulk("v"));
+        assert_eq!(exec(&mut repo, &["TTL", "s"]), OperationResult::Int(100));
+        assert_eq!(exec(&mut repo, &["GETEX", "s", "PERSIST"]), bulk("v"));
+        assert_eq!(exec(&mut repo, &["TTL", "s"]), OperationResult::Int(-1));
+        assert_eq!(exec(&mut repo, &["GETEX", "s", "EX", "1", "PERSIST"]), OperationError::Syntax.into());
+        assert_eq!(
+            exec(&mut repo, &["GETEX", "s", "EX", "0"]),
+            OperationError::InvalidExpireTime("getex").into()
+        );
+        assert_eq!(exec(&mut repo, &["GETEX", "missing", "EX", "10"]), OperationResult::Nil);
+
+        assert_eq!(exec(&mut repo, &["GETDEL", "s"]), bulk("v"));
+        assert_eq!(exec(&mut repo, &["GETDEL", "s"]), OperationResult::Nil);
+
+        exec(&mut repo, &["HSET", "h", "f", "v"]);
+        assert_eq!(exec(&mut repo, &["GETDEL", "h"]), OperationError::WrongType.into());
+        assert_eq!(exec(&mut repo, &["GETEX", "h"]), OperationError::WrongType.into());
+    }
+
+    #[test]
+    fn test_setnx_and_msetnx() {
+        let mut repo = Repository::new();
+        assert_eq!(exec(&mut repo, &["SETNX", "a", "1"]), OperationResult::Int(1));
+        assert_eq!(exec(&mut repo, &["SETNX", "a", "2"]), OperationResult::Int(0));
+        assert_eq!(exec(&mut repo, &["GET", "a"]), bulk("1"));
+
+        assert_eq!(exec(&mut repo, &["MSETNX", "b", "1", "a", "3"]), OperationResult::Int(0));
+        assert_eq!(exec(&mut repo, &["GET", "b"]), OperationResult::Nil);
+        assert_eq!(exec(&mut repo, &["MSETNX", "b", "1", "c", "2"]), OperationResult::Int(1));
+        assert_eq!(exec(&mut repo, &["GET", "c"]), bulk("2"));
+        assert_eq!(exec(&mut repo, &["MSETNX", "d", "1", "e"]), OperationError::WrongArity("msetnx").into());
+    }
+
+    #[test]
+    fn test_mget_and_mset() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["SET", "b", "old", "EX", "100"]);
+        assert_eq!(exec(&mut repo, &["MSET", "a", "1", "b", "2"]), OperationResult::Ok);
+        assert_eq!(exec(&mut repo, &["TTL", "b"]), OperationResult::Int(-1));
+        exec(&mut repo, &["HSET", "h", "f", "v"]);
+
+        assert_eq!(
+            exec(&mut repo, &["MGET", "a", "missing", "h", "b"]),
+            OperationResult::Array(vec![bulk("1"), OperationResult::Nil, OperationResult::Nil, bulk("2")])
+        );
+        assert_eq!(exec(&mut repo, &["MSET", "c", "1", "d"]), OperationError::WrongArity("mset").into());
+        assert_eq!(exec(&mut repo, &["GET", "c"]), OperationResult::Nil);
+    }
+
+    #[test]
+    fn test_counters() {
+        let mut repo = Repository::new();
+        assert_eq!(exec(&mut repo, &["INCR", "n"]), OperationResult::Int(1));
+        assert_eq!(exec(&mut repo, &["INCRBY", "n", "41"]), OperationResult::Int(42));
+        assert_eq!(exec(&mut repo, &["DECR", "n"]), OperationResult::Int(41));
+        assert_eq!(exec(&mut repo, &["DECRBY", "n", "-9"]), OperationResult::Int(50));
+        assert_eq!(exec(&mut repo, &["GET", "n"]), bulk("50"));
+
+        exec(&mut repo, &["SET", "n", "10"]);
+        assert_eq!(repo.get(b"n"), Some(Record::Int(10)));
+        assert_eq!(exec(&mut repo, &["INCR", "n"]), OperationResult::Int(11));
+
+        exec(&mut repo, &["SET", "s", "010"]);
+        assert_eq!(exec(&mut repo, &["INCR", "s"]), OperationError::NotAnInteger.into());
+        exec(&mut repo, &["SET", "s", "+5"]);
+        assert_eq!(exec(&mut repo, &["INCR", "s"]), OperationError::NotAnInteger.into());
+
+        exec(&mut repo, &["SET", "t", "10", "EX", "100"]);
+        exec(&mut repo, &["INCR", "t"]);
+        assert_eq!(exec(&mut repo, &["TTL", "t"]), OperationResult::Int(100));
+    }
+
+    #[test]
+    fn test_counter_errors() {
+        let mut repo = Repository::new();
+        exec(&mut repo, &["SET", "word", "abc"]);
+        assert_eq!(exec(&mut repo, &["INCR", "word"]), OperationError::NotAnInteger.into());
+        assert_eq!(exec(&mut repo, &["INCRBY", "n", "1.5"]), OperationError::NotAnInteger.into());
+
+        exec(&mut repo, &["SET", "max", &i64::MAX.to_string()]);
+        assert_eq!(exec(&mut repo, &["INCR", "max"]), OperationError::Overflow.into());
+        assert_eq!(exec(&mut repo, &["GET", "max"]), bulk(&i64::MAX.to_string()));
+        assert_eq!(
+            exec(&mut repo, &["DECRBY", "n", &i64::MIN.to_string()]),
+            OperationError::Overflow.into()
+        );
+
+        exec(&mut repo, &["HSET", "h", "f", "v"]);
+        assert_eq!(exec(&mut repo, &["INCR", "h"]), OperationError::WrongType.into());
+    }
+
+    #[test]
+    fn test_incrbyfloat() {
+        let mut repo = Repository::new();
+        assert_eq!(exec(&mut repo, &["INCRBYFLOAT", "f", "10.5"]), bulk("10.5"));
+        assert_eq!(exec(&mut repo, &["INCRBYFLOAT", "f", "0.1"]), bulk("10.6"));
+        assert_eq!(exec(&mut repo, &["INCRBYFLOAT", "f", "-5.6"]), bulk("5"));
+        assert_eq!(repo.get(b"f"), Some(Record::Int(5)));
+        assert_eq!(exec(&mut repo, &["INCRBYFLOAT", "f", "5.0e3"]), bulk("5005"));
+        assert_eq!(exec(&mut repo, &["INCRBYFLOAT", "f", "abc"]), OperationError::NotAFloat.into());
+        assert_eq!(exec(&mut repo, &["INCRBYFLOAT", "f", "inf"]), OperationError::NotAFloat.into());
+
+        exec(&mut repo, &["SET", "big", "1e308"]);
+        assert_eq!(
+            exec(&mut repo, &["INCRBYFLOAT", "big", "1e308"]),
+            OperationError::NanOrInfinity.into()
+        );
+    }
+}
@@ -1,42 +1,114 @@
+use std::io::{self, Write};
+
 use thiserror::Error;
 
 use crate::{request::Request, operations::OperationResult};
 
+/// The protocol a connection speaks, chosen with `HELLO`. Connections start
+/// out on RESP2.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum ProtocolVersion {
+    #[default]
+    Resp2 = 2,
+    Resp3 = 3,
+}
+
 #[derive(PartialEq, Clone, Debug)]
 pub enum RespValueRef {
     String(String),
-    BulkString(String),
+    BulkString(Vec<u8>),
     Failure(String),
     Int(i64),
     Array(Vec<RespValueRef>),
     NullArray,
     NullBulkString,
+    // RESP3 only
+    Null,
+    Boolean(bool),
+    Double(f64),
+    BigNumber(String),
+    /// A string tagged with a three letter format such as `txt` or `mkd`.
+    Verbatim(String, Vec<u8>),
+    Map(Vec<(RespValueRef, RespValueRef)>),
+    Set(Vec<RespValueRef>),
+    Push(Vec<RespValueRef>),
+    /// Out of band key/value pairs describing the value that follows them.
+    Attribute(Vec<(RespValueRef, RespValueRef)>, Box<RespValueRef>),
 }
 
-impl From<OperationResult> for RespValueRef {
-    fn from(state_res: OperationResult) -> Self {
-        match state_res {
+impl RespValueRef {
+    /// Builds the reply for a command result, falling back to the closest
+    /// RESP2 types when the client hasn't negotiated RESP3.
+    pub fn from_result(result: OperationResult, protocol: ProtocolVersion) -> Self {
+        let resp3 = protocol == ProtocolVersion::Resp3;
+        let convert = |value| RespValueRef::from_result(value, protocol);
+        match result {
             OperationResult::Ok => RespValueRef::String("OK".to_string()),
+            OperationResult::Status(s) => RespValueRef::String(s),
+            OperationResult::Nil if resp3 => RespValueRef::Null,
             OperationResult::Nil => RespValueRef::NullBulkString,
             OperationResult::StringRes(s) => RespValueRef::BulkString(s),
+            OperationResult::Verbatim(s) if resp3 => {
+                RespValueRef::Verbatim("txt".to_string(), s.into_bytes())
+            }
+            OperationResult::Verbatim(s) => RespValueRef::BulkString(s.into_bytes()),
             OperationResult::Error(e) => RespValueRef::Failure(e),
             OperationResult::Int(i) => RespValueRef::Int(i),
+            OperationResult::Array(values) => {
+                RespValueRef::Array(values.into_iter().map(convert).collect())
+            }
+            OperationResult::NullArray if resp3 => RespValueRef::Null,
+            OperationResult::NullArray => RespValueRef::NullArray,
+            OperationResult::Map(pairs) if resp3 => RespValueRef::Map(
+                pairs
+                    .into_iter()
+                    .map(|(k, v)| (convert(k), convert(v)))
+                    .collect(),
+            ),
+            OperationResult::Map(pairs) => RespValueRef::Array(
+                pairs
+                    .into_iter()
+                    .flat_map(|(k, v)| [convert(k), convert(v)])
+                    .collect(),
+            ),
+            OperationResult::Set(values) if resp3 => {
+                RespValueRef::Set(values.into_iter().map(convert).collect())
+            }
+            OperationResult::Set(values) => {
+                RespValueRef::Array(values.into_iter().map(convert).collect())
+            }
+            OperationResult::Double(d) if resp3 => RespValueRef::Double(d),
+            OperationResult::Double(d) => RespValueRef::BulkString(format_double(d).into_bytes()),
         }
     }
 }
 
+/// Formats a double the way it is sent over the wire, using `inf`, `-inf` and
+/// `nan` for the special values.
+pub fn format_double(d: f64) -> String {
+    if d.is_nan() {
+        "nan".to_string()
+    } else if d.is_infinite() {
+        if d > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else if d != 0.0 && !(1e-5..1e17).contains(&d.abs()) {
+        format!("{:e}", d)
+    } else {
+        d.to_string()
+    }
+}
+
 impl TryInto<Request> for RespValueRef {
     type Error = ();
 
     fn try_into(self) -> Result<Request, Self::Error> {
         if let RespValueRef::Array(command_refs) = self {
-            let mut command_chunks: Vec<String> = vec![];
+            let mut command_chunks: Vec<Vec<u8>> = vec![];
             for v in command_refs {
-                if let RespValueRef::String(s) = v {
-                    command_chunks.push(s);
-                    continue;
+                match v {
+                    RespValueRef::BulkString(s) => command_chunks.push(s),
+                    RespValueRef::String(s) => command_chunks.push(s.into_bytes()),
+                    _ => return Err(()),
                 }
-                return Err(());
             }
             return Ok(Request::new(command_chunks));
         }
@@ -46,14 +118,34 @@ impl TryInto<Request> for RespValueRef {
 
 #[derive(Error, Debug)]
 pub enum RESPError {
-    #[error("Unknown Starting Byte")]
-    UnknownStartingByte,
+    #[error("Unknown Starting Byte `{}`", char::from(*.0))]
+    UnknownStartingByte(u8),
     #[error("Unparseable Int")]
     IntParseFailure,
     #[error("Bad Bulkstring size `{0}`")]
     BadBulkStringSize(i64),
     #[error("Bad Array size `{0}`")]
     BadArraySize(i64),
+    #[error("Unparseable Double")]
+    DoubleParseFailure,
+    #[error("Bad Boolean")]
+    BadBoolean,
+    #[error("Bad Null")]
+    BadNull,
+    #[error("Bad Verbatim string")]
+    BadVerbatimString,
+    #[error("Unbalanced quotes in inline request")]
+    UnbalancedQuotes,
+    #[error("Inline request too big")]
+    InlineTooBig,
+    #[error("invalid multibulk length")]
+    InvalidMultibulkLength,
+    #[error("invalid bulk length")]
+    InvalidBulkLength,
+    #[error("expected '$', got '{}'", char::from(*.0))]
+    ExpectedBulkString(u8),
+    #[error("too many nested aggregates")]
+    NestingTooDeep,
 }
 
 #[derive(Debug)]
@@ -68,21 +160,32 @@ impl BufSplit {
 #[derive(Debug)]
 enum RespBufSplit {
     String(BufSplit),
+    BulkString(BufSplit),
     Error(BufSplit),
     Int(i64),
     Array(Vec<RespBufSplit>),
     NullArray,
     NullBulkString,
+    Null,
+    Boolean(bool),
+    Double(f64),
+    BigNumber(BufSplit),
+    Verbatim(BufSplit),
+    Map(BufPairs),
+    Set(Vec<RespBufSplit>),
+    Push(Vec<RespBufSplit>),
+    Attribute(BufPairs, Box<RespBufSplit>),
 }
 
 impl RespBufSplit {
     fn redis_value(self, buf: &[u8]) -> RespValueRef {
         match self {
             RespBufSplit::String(bfs) => {
-                RespValueRef::String(String::from_utf8_lossy(&buf[bfs.0..bfs.1]).to_string())
+                RespValueRef::String(String::from_utf8_lossy(bfs.as_slice(buf)).to_string())
             }
+            RespBufSplit::BulkString(bfs) => RespValueRef::BulkString(bfs.as_slice(buf).to_vec()),
             RespBufSplit::Error(bfs) => {
-                RespValueRef::Failure(String::from_utf8_lossy(&buf[bfs.0..bfs.1]).to_string())
+                RespValueRef::Failure(String::from_utf8_lossy(bfs.as_slice(buf)).to_string())
             }
             RespBufSplit::Array(arr) => {
                 RespValueRef::Array(arr.into_iter().map(|bfs| bfs.redis_value(buf)).collect())
@@ -90,11 +193,39 @@ impl RespBufSplit {
             RespBufSplit::NullArray => RespValueRef::NullArray,
             RespBufSplit::NullBulkString => RespValueRef::NullBulkString,
             RespBufSplit::Int(i) => RespValueRef::Int(i),
+            RespBufSplit::Null => RespValueRef::Null,
+            RespBufSplit::Boolean(b) => RespValueRef::Boolean(b),
+            RespBufSplit::Double(d) => RespValueRef::Double(d),
+            RespBufSplit::BigNumber(bfs) => {
+                RespValueRef::BigNumber(String::from_utf8_lossy(bfs.as_slice(buf)).to_string())
+            }
+            RespBufSplit::Verbatim(bfs) => {
+                let (format, text) = bfs.as_slice(buf).split_at(3);
+                RespValueRef::Verbatim(String::from_utf8_lossy(format).to_string(), text[1..].to_vec())
+            }
+            RespBufSplit::Map(pairs) => RespValueRef::Map(pairs_value(pairs, buf)),
+            RespBufSplit::Set(arr) => {
+                RespValueRef::Set(arr.into_iter().map(|bfs| bfs.redis_value(buf)).collect())
+            }
+            RespBufSplit::Push(arr) => {
+                RespValueRef::Push(arr.into_iter().map(|bfs| bfs.redis_value(buf)).collect())
+            }
+            RespBufSplit::Attribute(pairs, value) => {
+                RespValueRef::Attribute(pairs_value(pairs, buf), Box::new(value.redis_value(buf)))
+            }
         }
     }
 }
 
+fn pairs_value(pairs: BufPairs, buf: &[u8]) -> Vec<(RespValueRef, RespValueRef)> {
+    pairs
+        .into_iter()
+        .map(|(k, v)| (k.redis_value(buf), v.redis_value(buf)))
+        .collect()
+}
+
 type RespResult = Result<Option<(usize, RespBufSplit)>, RESPError>;
+type BufPairs = Vec<(RespBufSplit, RespBufSplit)>;
 
 fn memchr(needle: u8, haystack: &[u8]) -> Option<usize> {
     haystack.iter().position(|&b| b == needle)
@@ -106,7 +237,7 @@ fn word(buf: &mut [u8], pos: usize) -> Option<(usize, BufSplit)> {
     }
 
     memchr(b'\r', &buf[pos..]).and_then(|end| {
-        if end + 1 < buf.len() {
+        if pos + end + 1 < buf.len() {
             Some((pos + end + 2, BufSplit(pos, pos + end)))
         } else {
             None
@@ -134,40 +265,172 @@ fn int(buf: &mut [u8], pos: usize) -> Result<Option<(usize, i64)>, RESPError> {
     }
 }
 
-fn array(buf: &mut [u8], pos: usize) -> RespResult {
+/// The largest bulk string a peer may send, matching the default
+/// `proto-max-bulk-len`.
+const PROTO_MAX_BULK_LEN: i64 = 512 * 1024 * 1024;
+/// The most elements a peer may announce for an aggregate.
+const PROTO_MAX_MULTIBULK_LEN: i64 = i32::MAX as i64;
+/// Aggregates grow past this many elements as their values arrive rather
+/// than trusting the announced length up front.
+const MAX_PREALLOCATED_ELEMENTS: i64 = 1024;
+/// How deeply aggregates may nest, since each level is parsed recursively.
+const MAX_NESTING_DEPTH: usize = 128;
+
+/// Parses `num_elements` values laid out back to back from `pos`, each
+/// nested `depth` aggregates deep.
+fn elements(
+    buf: &mut [u8],
+    pos: usize,
+    num_elements: i64,
+    depth: usize,
+) -> Result<Option<(usize, Vec<RespBufSplit>)>, RESPError> {
+    if num_elements > PROTO_MAX_MULTIBULK_LEN {
+        return Err(RESPError::InvalidMultibulkLength);
+    }
+    let mut values = Vec::with_capacity(num_elements.min(MAX_PREALLOCATED_ELEMENTS) as usize);
+    let mut curr_pos = pos;
+    for _ in 0..num_elements {
+        match parse(buf, curr_pos, depth)? {
+            Some((new_pos, value)) => {
+                curr_pos = new_pos;
+                values.push(value);
+            }
+            None => return Ok(None),
+        }
+    }
+    Ok(Some((curr_pos, values)))
+}
+
+fn array(buf: &mut [u8], pos: usize, depth: usize) -> RespResult {
     match int(buf, pos)? {
         None => Ok(None),
         Some((pos, -1)) => Ok(Some((pos, RespBufSplit::NullArray))),
         Some((pos, num_elements)) if num_elements >= 0 => {
-            let mut values = Vec::with_capacity(num_elements as usize);
-            let mut curr_pos = pos;
-            for _ in 0..num_elements {
-                match parse(buf, curr_pos)? {
-                    Some((new_pos, value)) => {
-                        curr_pos = new_pos;
-                        values.push(value);
-                    }
-                    None => return Ok(None),
+            Ok(elements(buf, pos, num_elements, depth + 1)?
+                .map(|(pos, values)| (pos, RespBufSplit::Array(values))))
+        }
+        Some((_pos, bad_num_elements)) => Err(RESPError::BadArraySize(bad_num_elements)),
+    }
+}
+
+/// Parses an aggregate whose header is just its element count, such as a
+/// set or a push.
+fn aggregate(
+    buf: &mut [u8],
+    pos: usize,
+    depth: usize,
+    kind: fn(Vec<RespBufSplit>) -> RespBufSplit,
+) -> RespResult {
+    match int(buf, pos)? {
+        None => Ok(None),
+        Some((pos, num_elements)) if num_elements >= 0 => {
+            Ok(elements(buf, pos, num_elements, depth + 1)?.map(|(pos, values)| (pos, kind(values))))
+        }
+        Some((_pos, bad_num_elements)) => Err(RESPError::BadArraySize(bad_num_elements)),
+    }
+}
+
+fn pairs(buf: &mut [u8], pos: usize, depth: usize) -> Result<Option<(usize, BufPairs)>, RESPError> {
+    match int(buf, pos)? {
+        None => Ok(None),
+        Some((pos, num_pairs)) if num_pairs >= 0 => {
+            let num_elements = num_pairs.checked_mul(2).ok_or(RESPError::BadArraySize(num_pairs))?;
+            Ok(elements(buf, pos, num_elements, depth + 1)?.map(|(pos, values)| {
+                let mut values = values.into_iter();
+                let mut pairs = Vec::with_capacity(values.len() / 2);
+                while let (Some(k), Some(v)) = (values.next(), values.next()) {
+                    pairs.push((k, v));
                 }
+                (pos, pairs)
+            }))
+        }
+        Some((_pos, bad_num_pairs)) => Err(RESPError::BadArraySize(bad_num_pairs)),
+    }
+}
+
+fn map(buf: &mut [u8], pos: usize, depth: usize) -> RespResult {
+    Ok(pairs(buf, pos, depth)?.map(|(pos, pairs)| (pos, RespBufSplit::Map(pairs))))
+}
+
+fn attribute(buf: &mut [u8], pos: usize, depth: usize) -> RespResult {
+    let Some((pos, pairs)) = pairs(buf, pos, depth)? else {
+        return Ok(None);
+    };
+    Ok(parse(buf, pos, depth)?.map(|(pos, value)| (pos, RespBufSplit::Attribute(pairs, Box::new(value)))))
+}
+
+fn null(buf: &mut [u8], pos: usize) -> RespResult {
+    match word(buf, pos) {
+        Some((pos, word)) if word.0 == word.1 => Ok(Some((pos, RespBufSplit::Null))),
+        Some(_) => Err(RESPError::BadNull),
+        None => Ok(None),
+    }
+}
+
+fn boolean(buf: &mut [u8], pos: usize) -> RespResult {
+    match word(buf, pos) {
+        Some((pos, word)) => match word.as_slice(buf) {
+            b"t" => Ok(Some((pos, RespBufSplit::Boolean(true)))),
+            b"f" => Ok(Some((pos, RespBufSplit::Boolean(false)))),
+            _ => Err(RESPError::BadBoolean),
+        },
+        None => Ok(None),
+    }
+}
+
+fn double(buf: &mut [u8], pos: usize) -> RespResult {
+    match word(buf, pos) {
+        Some((pos, word)) => {
+            let s = std::str::from_utf8(word.as_slice(buf)).map_err(|_| RESPError::DoubleParseFailure)?;
+            let d = s.parse().map_err(|_| RESPError::DoubleParseFailure)?;
+            Ok(Some((pos, RespBufSplit::Double(d))))
+        }
+        None => Ok(None),
+    }
+}
+
+fn big_number(buf: &mut [u8], pos: usize) -> RespResult {
+    Ok(word(buf, pos).map(|(pos, word)| (pos, RespBufSplit::BigNumber(word))))
+}
+
+fn verbatim_string(buf: &mut [u8], pos: usize) -> RespResult {
+    match bulk_string(buf, pos)? {
+        Some((pos, RespBufSplit::BulkString(bfs))) => {
+            if bfs.1 - bfs.0 < 4 || buf[bfs.0 + 3] != b':' {
+                return Err(RESPError::BadVerbatimString);
             }
-            Ok(Some((curr_pos, RespBufSplit::Array(values))))
+            Ok(Some((pos, RespBufSplit::Verbatim(bfs))))
         }
-        Some((_pos, bad_num_elements)) => Err(RESPError::BadArraySize(bad_num_elements)),
+        Some(_) => Err(RESPError::BadVerbatimString),
+        None => Ok(None),
     }
 }
 
-fn parse(buf: &mut [u8], pos: usize) -> RespResult {
-    if buf.is_empty() {
+/// Parses the value at `pos`, which sits inside `depth` aggregates.
+fn parse(buf: &mut [u8], pos: usize, depth: usize) -> RespResult {
+    if buf.len() <= pos {
         return Ok(None);
     }
+    if depth > MAX_NESTING_DEPTH {
+        return Err(RESPError::NestingTooDeep);
+    }
 
     match buf[pos] {
         b'+' => simple_string(buf, pos + 1),
         b'-' => error(buf, pos + 1),
         b'$' => bulk_string(buf, pos + 1),
         b':' => resp_int(buf, pos + 1),
-        b'*' => array(buf, pos + 1),
-        _ => Err(RESPError::UnknownStartingByte),
+        b'*' => array(buf, pos + 1, depth),
+        b'_' => null(buf, pos + 1),
+        b'#' => boolean(buf, pos + 1),
+        b',' => double(buf, pos + 1),
+        b'(' => big_number(buf, pos + 1),
+        b'=' => verbatim_string(buf, pos + 1),
+        b'%' => map(buf, pos + 1, depth),
+        b'~' => aggregate(buf, pos + 1, depth, RespBufSplit::Set),
+        b'>' => aggregate(buf, pos + 1, depth, RespBufSplit::Push),
+        b'|' => attribute(buf, pos + 1, depth),
+        byte => Err(RESPError::UnknownStartingByte(byte)),
     }
 }
 
@@ -178,12 +441,13 @@ fn resp_int(buf: &mut [u8], pos: usize) -> RespResult {
 fn bulk_string(buf: &mut [u8], pos: usize) -> RespResult {
     match int(buf, pos)? {
         Some((pos, -1)) => Ok(Some((pos, RespBufSplit::NullBulkString))),
+        Some((_pos, size)) if size > PROTO_MAX_BULK_LEN => Err(RESPError::InvalidBulkLength),
         Some((pos, size)) if size >= 0 => {
             let total_size = pos + size as usize;
             if buf.len() < total_size + 2 {
                 Ok(None)
             } else {
-                let bb = RespBufSplit::String(BufSplit(pos, total_size));
+                let bb = RespBufSplit::BulkString(BufSplit(pos, total_size));
                 Ok(Some((total_size + 2, bb)))
             }
         }
@@ -192,58 +456,447 @@ fn bulk_string(buf: &mut [u8], pos: usize) -> RespResult {
     }
 }
 
-pub fn decode(buf: &mut [u8]) -> Result<Option<RespValueRef>, RESPError> {
+const INLINE_MAX_SIZE: usize = 64 * 1024;
+
+fn is_type_byte(byte: u8) -> bool {
+    b"+-$:*_#,(=%~>|".contains(&byte)
+}
+
+/// Parses an inline command such as `SET key "hello world"\r\n`, the format
+/// people type into telnet or netcat, into an array of bulk strings.
+fn inline(buf: &[u8]) -> Result<Option<(usize, RespValueRef)>, RESPError> {
+    let Some(end) = memchr(b'\n', buf) else {
+        if buf.len() > INLINE_MAX_SIZE {
+            return Err(RESPError::InlineTooBig);
+        }
+        return Ok(None);
+    };
+
+    let line = buf[..end].strip_suffix(b"\r").unwrap_or(&buf[..end]);
+    let args = split_args(line)?;
+    Ok(Some((end + 1, RespValueRef::Array(args.into_iter().map(RespValueRef::BulkString).collect()))))
+}
+
+/// Splits an inline command into its arguments. Arguments are separated by
+/// whitespace and may be wrapped in double quotes, which understand escapes
+/// like `\n` and `\x41`, or single quotes, which only understand `\'`.
+pub fn split_args(line: &[u8]) -> Result<Vec<Vec<u8>>, RESPError> {
+    let mut args = Vec::new();
+    let mut i = 0;
+    loop {
+        while i < line.len() && line[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i == line.len() {
+            return Ok(args);
+        }
+
+        let mut arg = Vec::new();
+        let quote = match line[i] {
+            q @ (b'"' | b'\'') => {
+                i += 1;
+                Some(q)
+            }
+            _ => None,
+        };
+
+        loop {
+            let Some(&c) = line.get(i) else {
+                if quote.is_some() {
+                    return Err(RESPError::UnbalancedQuotes);
+                }
+                break;
+            };
+
+            match quote {
+                None if c.is_ascii_whitespace() => break,
+                None => arg.push(c),
+                Some(q) if c == q => {
+                    // A closing quote has to end the argument.
+                    if line.get(i + 1).is_some_and(|next| !next.is_ascii_whitespace()) {
+                        return Err(RESPError::UnbalancedQuotes);
+                    }
+                    i += 1;
+                    break;
+                }
+                Some(b'"') if c == b'\\' && i + 1 < line.len() => {
+                    let hex = line
+                        .get(i + 2..i + 4)
+                        .filter(|_| line[i + 1] == b'x')
+                        .and_then(|digits| std::str::from_utf8(digits).ok())
+                        .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+                    if let Some(byte) = hex {
+                        arg.push(byte);
+                        i += 3;
+                    } else {
+                        arg.push(match line[i + 1] {
+                            b'n' => b'\n',
+                            b'r' => b'\r',
+                            b't' => b'\t',
+                            b'b' => 0x08,
+                            b'a' => 0x07,
+                            other => other,
+                        });
+                        i += 1;
+                    }
+                }
+                Some(b'\'') if c == b'\\' && line.get(i + 1) == Some(&b'\'') => {
+                    arg.push(b'\'');
+                    i += 1;
+                }
+                Some(_) => arg.push(c),
+            }
+            i += 1;
+        }
+        args.push(arg);
+    }
+}
+
+/// Wraps `arg` in double quotes so that `split_args` reads it back as a
+/// single argument with exactly the same bytes. Quotes and backslashes are
+/// escaped and anything that isn't printable ASCII is written as `\xHH`.
+pub fn quote_arg(arg: &[u8]) -> String {
+    let mut quoted = String::from("\"");
+    for &byte in arg {
+        match byte {
+            b'"' => quoted.push_str("\\\""),
+            b'\\' => quoted.push_str("\\\\"),
+            b'\n' => quoted.push_str("\\n"),
+            b'\r' => quoted.push_str("\\r"),
+            b'\t' => quoted.push_str("\\t"),
+            b' '..=b'~' => quoted.push(char::from(byte)),
+            _ => quoted.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Decodes the first complete frame in `buf`, returning it together with the
+/// number of bytes it took up. `Ok(None)` means the frame is still incomplete
+/// and more bytes are needed. Anything that doesn't start with a RESP type
+/// byte is read as an inline command.
+pub fn decode(buf: &mut [u8]) -> Result<Option<(usize, RespValueRef)>, RESPError> {
     if buf.is_empty() {
         return Ok(None);
     }
 
-    match parse(buf, 0)? {
+    if !is_type_byte(buf[0]) {
+        return inline(buf);
+    }
+
+    match parse(buf, 0, 0)? {
         Some((pos, value)) => {
             let our_data = buf.split_at(pos).0;
-            Ok(Some(value.redis_value(&our_data)))
+            Ok(Some((pos, value.redis_value(our_data))))
         }
         None => Ok(None),
     }
 }
 
-impl RespValueRef {
-    pub fn write_resp_value(&self) -> String {
-        let mut return_value = String::new();
-        match &self {
-            RespValueRef::Failure(e) => {
-                return_value.push_str("-");
-                return_value.push_str(&e);
-                return_value.push_str("\r\n");
-            }
-            RespValueRef::String(s) => {
-                return_value.push_str("+");
-                return_value.push_str(&s);
-                return_value.push_str("\r\n");
+/// Decodes the first complete command in `buf`, like `decode` but only
+/// accepting what clients send: a flat array of bulk strings or an inline
+/// command. The array is read without recursing, so no amount of nesting
+/// can exhaust the stack. Arrays with a count below one decode as empty.
+pub fn decode_request(buf: &mut [u8]) -> Result<Option<(usize, RespValueRef)>, RESPError> {
+    if buf.is_empty() {
+        return Ok(None);
+    }
+    if buf[0] != b'*' {
+        return inline(buf);
+    }
+
+    let Some((mut pos, num_args)) = int(buf, 1)? else {
+        return Ok(None);
+    };
+    if num_args > PROTO_MAX_MULTIBULK_LEN {
+        return Err(RESPError::InvalidMultibulkLength);
+    }
+    let mut args = Vec::with_capacity(num_args.clamp(0, MAX_PREALLOCATED_ELEMENTS) as usize);
+    for _ in 0..num_args {
+        match buf.get(pos) {
+            None => return Ok(None),
+            Some(b'$') => {}
+            Some(&byte) => return Err(RESPError::ExpectedBulkString(byte)),
+        }
+        match bulk_string(buf, pos + 1)? {
+            Some((next, RespBufSplit::BulkString(arg))) => {
+                args.push(arg);
+                pos = next;
             }
+            Some(_) => return Err(RESPError::InvalidBulkLength),
+            None => return Ok(None),
+        }
+    }
+    let args = args
+        .into_iter()
+        .map(|arg| RespValueRef::BulkString(arg.as_slice(buf).to_vec()))
+        .collect();
+    Ok(Some((pos, RespValueRef::Array(args))))
+}
+
+impl RespValueRef {
+    /// Encodes the value straight into `out`, so replies can be appended to a
+    /// connection's output buffer without any intermediate allocation.
+    pub fn write_resp_value<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        match self {
+            RespValueRef::Failure(e) => write!(out, "-{}\r\n", e),
+            RespValueRef::String(s) => write!(out, "+{}\r\n", s),
             RespValueRef::BulkString(s) => {
-                return_value.push_str("$");
-                return_value.push_str(s.len().to_string().as_str());
-                return_value.push_str("\r\n");
-                return_value.push_str(&s);
-                return_value.push_str("\r\n");
+                write!(out, "${}\r\n", s.len())?;
+                out.write_all(s)?;
+                out.write_all(b"\r\n")
             }
             RespValueRef::Array(array) => {
-                return_value.push_str("*");
-                return_value.push_str(array.len().to_string().as_str());
-                return_value.push_str("\r\n");
+                write!(out, "*{}\r\n", array.len())?;
                 for redis_value in array {
-                    redis_value.write_resp_value();
+                    redis_value.write_resp_value(out)?;
                 }
+                Ok(())
             }
-            RespValueRef::Int(i) => {
-                return_value.push_str(":");
-                return_value.push_str(i.to_string().as_str());
-                return_value.push_str("\r\n");
+            RespValueRef::Int(i) => write!(out, ":{}\r\n", i),
+            RespValueRef::NullArray => out.write_all(b"*-1\r\n"),
+            RespValueRef::NullBulkString => out.write_all(b"$-1\r\n"),
+            RespValueRef::Null => out.write_all(b"_\r\n"),
+            RespValueRef::Boolean(b) => out.write_all(if *b { b"#t\r\n" } else { b"#f\r\n" }),
+            RespValueRef::Double(d) => write!(out, ",{}\r\n", format_double(*d)),
+            RespValueRef::BigNumber(n) => write!(out, "({}\r\n", n),
+            RespValueRef::Verbatim(format, s) => {
+                write!(out, "={}\r\n{}:", s.len() + format.len() + 1, format)?;
+                out.write_all(s)?;
+                out.write_all(b"\r\n")
             }
-            RespValueRef::NullArray => return_value.push_str("*-1\r\n"),
-            RespValueRef::NullBulkString => return_value.push_str("$-1\r\n"),
+            RespValueRef::Map(pairs) => {
+                write!(out, "%{}\r\n", pairs.len())?;
+                write_pairs(pairs, out)
+            }
+            RespValueRef::Set(values) => {
+                write!(out, "~{}\r\n", values.len())?;
+                values.iter().try_for_each(|value| value.write_resp_value(out))
+            }
+            RespValueRef::Push(values) => {
+                write!(out, ">{}\r\n", values.len())?;
+                values.iter().try_for_each(|value| value.write_resp_value(out))
+            }
+            RespValueRef::Attribute(pairs, value) => {
+                write!(out, "|{}\r\n", pairs.len())?;
+                write_pairs(pairs, out)?;
+                value.write_resp_value(out)
+            }
+        }
+    }
+}
+
+fn write_pairs<W: Write>(pairs: &[(RespValueRef, RespValueRef)], out: &mut W) -> io::Result<()> {
+    for (key, value) in pairs {
+        key.write_resp_value(out)?;
+        value.write_resp_value(out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(value: RespValueRef) -> Vec<u8> {
+        let mut out = Vec::new();
+        value.write_resp_value(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_decode_complete_frame() {
+        let mut buf = b"*2\r\n$3\r\nGET\r\n$1\r\nx\r\n".to_vec();
+        let (consumed, value) = decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(consumed, buf.len());
+        assert_eq!(
+            value,
+            RespValueRef::Array(vec![
+                RespValueRef::BulkString(b"GET".to_vec()),
+                RespValueRef::BulkString(b"x".to_vec()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_decode_incomplete_frame() {
+        let frame = b"*2\r\n$3\r\nGET\r\n$1\r\nx\r\n";
+        for end in 0..frame.len() {
+            let mut buf = frame[..end].to_vec();
+            assert!(decode(&mut buf).unwrap().is_none(), "decoded {} bytes", end);
         }
+    }
+
+    #[test]
+    fn test_binary_bulk_string_round_trip() {
+        let payload = vec![0u8, 255, b'\r', b'\n', 0x80, 0xfe];
+        let encoded = encode(RespValueRef::BulkString(payload.clone()));
+        let mut buf = encoded.clone();
+        let (consumed, value) = decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(consumed, encoded.len());
+        assert_eq!(value, RespValueRef::BulkString(payload));
+    }
+
+    #[test]
+    fn test_write_nested_array() {
+        let reply = OperationResult::Array(vec![
+            OperationResult::StringRes(b"a".to_vec()),
+            OperationResult::Nil,
+            OperationResult::Array(vec![OperationResult::Int(1), OperationResult::NullArray]),
+            OperationResult::Array(vec![]),
+            OperationResult::Status("PONG".to_string()),
+        ]);
+
+        assert_eq!(
+            encode(RespValueRef::from_result(reply, ProtocolVersion::Resp2)),
+            b"*5\r\n$1\r\na\r\n$-1\r\n*2\r\n:1\r\n*-1\r\n*0\r\n+PONG\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_resp3_round_trip() {
+        let value = RespValueRef::Attribute(
+            vec![(RespValueRef::String("ttl".to_string()), RespValueRef::Int(3))],
+            Box::new(RespValueRef::Map(vec![
+                (RespValueRef::BulkString(b"null".to_vec()), RespValueRef::Null),
+                (RespValueRef::BulkString(b"bool".to_vec()), RespValueRef::Boolean(false)),
+                (RespValueRef::BulkString(b"double".to_vec()), RespValueRef::Double(-1.5)),
+                (RespValueRef::BulkString(b"inf".to_vec()), RespValueRef::Double(f64::INFINITY)),
+                (RespValueRef::BulkString(b"big".to_vec()), RespValueRef::BigNumber("3492890328409238509324850943850943825024385".to_string())),
+                (RespValueRef::BulkString(b"txt".to_vec()), RespValueRef::Verbatim("txt".to_string(), b"Some string".to_vec())),
+                (RespValueRef::BulkString(b"set".to_vec()), RespValueRef::Set(vec![RespValueRef::Int(1), RespValueRef::Int(2)])),
+                (RespValueRef::BulkString(b"push".to_vec()), RespValueRef::Push(vec![RespValueRef::String("message".to_string())])),
+            ])),
+        );
+        let mut buf = encode(value.clone());
+        let (consumed, decoded) = decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(consumed, buf.len());
+        assert_eq!(decoded, value);
+    }
+
+    #[test]
+    fn test_results_downgrade_to_resp2() {
+        let reply = OperationResult::Map(vec![(
+            OperationResult::StringRes(b"score".to_vec()),
+            OperationResult::Double(2.5),
+        )]);
+        assert_eq!(
+            encode(RespValueRef::from_result(reply.clone(), ProtocolVersion::Resp2)),
+            b"*2\r\n$5\r\nscore\r\n$3\r\n2.5\r\n".to_vec()
+        );
+        assert_eq!(
+            encode(RespValueRef::from_result(reply, ProtocolVersion::Resp3)),
+            b"%1\r\n$5\r\nscore\r\n,2.5\r\n".to_vec()
+        );
+        assert_eq!(encode(RespValueRef::from_result(OperationResult::Nil, ProtocolVersion::Resp3)), b"_\r\n".to_vec());
+    }
+
+    fn inline_args(line: &[u8]) -> Result<Option<(usize, RespValueRef)>, RESPError> {
+        decode(&mut line.to_vec())
+    }
+
+    fn bulk_array(args: &[&[u8]]) -> RespValueRef {
+        RespValueRef::Array(args.iter().map(|arg| RespValueRef::BulkString(arg.to_vec())).collect())
+    }
+
+    #[test]
+    fn test_decode_inline_command() {
+        assert_eq!(inline_args(b"PING\r\n").unwrap(), Some((6, bulk_array(&[b"PING"]))));
+        assert_eq!(
+            inline_args(b"  set  key\tvalue\nGET key\n").unwrap(),
+            Some((17, bulk_array(&[b"set", b"key", b"value"])))
+        );
+        assert_eq!(inline_args(b"\r\n").unwrap(), Some((2, bulk_array(&[]))));
+        assert_eq!(inline_args(b"SET key val").unwrap(), None);
+    }
+
+    #[test]
+    fn test_decode_inline_quoted_arguments() {
+        assert_eq!(
+            inline_args(b"SET \"hello world\" 'it\\'s'\n").unwrap(),
+            Some((26, bulk_array(&[b"SET", b"hello world", b"it's"])))
+        );
+        assert_eq!(
+            inline_args(b"SET k \"a\\r\\n\\x00\\xff\\\"\"\n").unwrap(),
+            Some((24, bulk_array(&[b"SET", b"k", b"a\r\n\x00\xff\""])))
+        );
+        assert_eq!(inline_args(b"SET k \"\"\n").unwrap(), Some((9, bulk_array(&[b"SET", b"k", b""]))));
+        assert!(matches!(inline_args(b"SET \"open\n"), Err(RESPError::UnbalancedQuotes)));
+        assert!(matches!(inline_args(b"SET \"a\"b\n"), Err(RESPError::UnbalancedQuotes)));
+    }
+
+    #[test]
+    fn test_decode_leaves_trailing_bytes() {
+        let mut buf = b"+OK\r\n:1\r\n".to_vec();
+        let (consumed, value) = decode(&mut buf).unwrap().unwrap();
+
+        assert_eq!(consumed, 5);
+        assert_eq!(value, RespValueRef::String("OK".to_string()));
+    }
+
+    #[test]
+    fn test_decode_rejects_oversized_lengths() {
+        let mut buf = b"*3000000000\r\n".to_vec();
+        assert!(matches!(decode(&mut buf), Err(RESPError::InvalidMultibulkLength)));
+
+        let mut buf = b"*1\r\n$1000000000\r\n".to_vec();
+        assert!(matches!(decode(&mut buf), Err(RESPError::InvalidBulkLength)));
+
+        let mut buf = b"*100000\r\n$3\r\nGET\r\n".to_vec();
+        assert!(matches!(decode(&mut buf), Ok(None)));
+    }
+
+    #[test]
+    fn test_decode_rejects_oversized_maps() {
+        let mut buf = b"%9223372036854775807\r\n".to_vec();
+        assert!(matches!(decode(&mut buf), Err(RESPError::BadArraySize(_))));
+
+        let mut buf = b"|3000000000\r\n".to_vec();
+        assert!(matches!(decode(&mut buf), Err(RESPError::InvalidMultibulkLength)));
+    }
+
+    #[test]
+    fn test_decode_rejects_unknown_nested_type() {
+        let mut buf = b"*2\r\n$3\r\nGET\r\nx1\r\n".to_vec();
+        assert!(matches!(decode(&mut buf), Err(RESPError::UnknownStartingByte(b'x'))));
+    }
+
+    #[test]
+    fn test_decode_caps_nesting() {
+        let mut buf = b"*1\r\n".repeat(200_000);
+        assert!(matches!(decode(&mut buf), Err(RESPError::NestingTooDeep)));
+
+        let mut buf = b"*1\r\n".repeat(MAX_NESTING_DEPTH);
+        buf.extend_from_slice(b":1\r\n");
+        assert!(matches!(decode(&mut buf), Ok(Some(_))));
+    }
+
+    #[test]
+    fn test_decode_request() {
+        let mut buf = b"*2\r\n$3\r\nGET\r\n$1\r\nx\r\n*1\r\n".to_vec();
+        let (consumed, value) = decode_request(&mut buf).unwrap().unwrap();
+        assert_eq!(consumed, 20);
+        assert_eq!(
+            value,
+            RespValueRef::Array(vec![
+                RespValueRef::BulkString(b"GET".to_vec()),
+                RespValueRef::BulkString(b"x".to_vec()),
+            ])
+        );
 
-        return_value
+        let mut buf = b"*2\r\n$3\r\nGET\r\n".to_vec();
+        assert!(matches!(decode_request(&mut buf), Ok(None)));
+        let mut buf = b"*1\r\n*1\r\n$4\r\nPING\r\n".to_vec();
+        assert!(matches!(decode_request(&mut buf), Err(RESPError::ExpectedBulkString(b'*'))));
+        let mut buf = b"*1\r\n$-1\r\n".to_vec();
+        assert!(matches!(decode_request(&mut buf), Err(RESPError::InvalidBulkLength)));
+        let mut buf = b"*0\r\n".to_vec();
+        assert_eq!(decode_request(&mut buf).unwrap().unwrap(), (4, RespValueRef::Array(vec![])));
+        let mut buf = b"PING\r\n".to_vec();
+        assert!(matches!(decode_request(&mut buf), Ok(Some((6, _)))));
     }
 }
@@ -1,7 +1,70 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::{sorted_set::SortedSet, stream::Stream};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Record {
-    String(String),
-    HashMap(HashMap<String, String>),
+    String(Vec<u8>),
+    /// A string that holds the canonical form of an `i64`, kept as a number
+    /// so counters don't parse and reformat it on every increment.
+    Int(i64),
+    HashMap(HashMap<Vec<u8>, Vec<u8>>),
+    List(VecDeque<Vec<u8>>),
+    Set(HashSet<Vec<u8>>),
+    SortedSet(SortedSet),
+    Stream(Stream),
+}
+
+impl Record {
+    /// Builds a string record, picking the integer encoding when the value
+    /// reads back exactly the same after formatting.
+    pub fn from_string(value: Vec<u8>) -> Record {
+        match parse_canonical_int(&value) {
+            Some(n) => Record::Int(n),
+            None => Record::String(value),
+        }
+    }
+
+    /// The bytes of a string record, whatever its encoding, or `None` if the
+    /// record is of another type.
+    pub fn string_value(&self) -> Option<Vec<u8>> {
+        match self {
+            Record::String(s) => Some(s.clone()),
+            Record::Int(n) => Some(n.to_string().into_bytes()),
+            _ => None,
+        }
+    }
+}
+
+/// Parses `value` as an integer only if it is written the way the integer
+/// would be formatted: no leading zeros and no sign other than a leading `-`.
+pub(crate) fn parse_canonical_int(value: &[u8]) -> Option<i64> {
+    if value.is_empty() || value.len() > 20 {
+        return None;
+    }
+    let n: i64 = std::str::from_utf8(value).ok()?.parse().ok()?;
+    (n.to_string().as_bytes() == value).then_some(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_string_encoding() {
+        assert_eq!(Record::from_string(b"42".to_vec()), Record::Int(42));
+        assert_eq!(Record::from_string(b"-9223372036854775808".to_vec()), Record::Int(i64::MIN));
+        assert_eq!(Record::from_string(b"042".to_vec()), Record::String(b"042".to_vec()));
+        assert_eq!(Record::from_string(b"+1".to_vec()), Record::String(b"+1".to_vec()));
+        assert_eq!(Record::from_string(b"-0".to_vec()), Record::String(b"-0".to_vec()));
+        assert_eq!(Record::from_string(b"1.5".to_vec()), Record::String(b"1.5".to_vec()));
+        assert_eq!(Record::from_string(b"".to_vec()), Record::String(b"".to_vec()));
+    }
+
+    #[test]
+    fn test_string_value() {
+        assert_eq!(Record::Int(-7).string_value(), Some(b"-7".to_vec()));
+        assert_eq!(Record::String(b"abc".to_vec()).string_value(), Some(b"abc".to_vec()));
+        assert_eq!(Record::HashMap(HashMap::new()).string_value(), None);
+    }
 }
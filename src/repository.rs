@@ -1,58 +1,330 @@
-use std::{collections::HashMap, time::Instant};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use rand::{seq::index, Rng};
 
 use crate::record::Record;
 
+/// How many keys, and then hashes with field expirations, each round of
+/// active expiry looks at.
+const ACTIVE_EXPIRE_SAMPLE: usize = 20;
+/// How long a single call to `remove_expired` may keep sampling.
+const ACTIVE_EXPIRE_BUDGET: Duration = Duration::from_millis(25);
+
 pub struct Repository {
-    store: HashMap<String, Record>,
-    expires: HashMap<String, Instant>,
+    store: HashMap<Vec<u8>, Record>,
+    expires: SampledMap<Instant>,
+    /// Expirations of individual hash fields, by key and then field.
+    field_expires: SampledMap<HashMap<Vec<u8>, Instant>>,
+    /// Keys that received data clients may be blocked waiting for, in the
+    /// order they got it.
+    ready_keys: Vec<Vec<u8>>,
+}
+
+impl Default for Repository {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Repository {
     pub fn new() -> Self {
         Self {
             store: HashMap::new(),
-            expires: HashMap::new(),
+            expires: SampledMap::default(),
+            field_expires: SampledMap::default(),
+            ready_keys: Vec::new(),
         }
     }
 
-    pub fn set(&mut self, key: String, record: Record) {
+    pub fn set(&mut self, key: Vec<u8>, record: Record) {
+        if !matches!(record, Record::HashMap(_)) {
+            self.field_expires.remove(&key);
+        }
         self.store.insert(key, record.clone());
     }
 
-    pub fn get(&mut self, key: String) -> Option<Record> {
-        if self.is_expired(key.to_string()) {
+    pub fn get(&mut self, key: &[u8]) -> Option<Record> {
+        if self.is_expired(key) {
+            self.delete(key);
+            return None
+        }
+        self.remove_expired_fields(key);
+
+        self.store.get(key).cloned()
+    }
+
+    /// Borrows a live record for in-place updates, so commands on large
+    /// collections don't clone them.
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut Record> {
+        if self.is_expired(key) {
             self.delete(key);
             return None
         }
+        self.remove_expired_fields(key);
+
+        self.store.get_mut(key)
+    }
 
-        self.store.get(&key).map(|r| r.clone())
+    pub fn delete(&mut self, key: &[u8]) -> Option<Record> {
+        self.expires.remove(key);
+        self.field_expires.remove(key);
+        self.store.remove(key)
     }
 
-    pub fn delete(&mut self, key: String) -> Option<Record> {
-        self.expires.remove(&key);
-        self.store.remove(&key)
+    /// Lists every key that hasn't expired yet.
+    pub fn keys(&self) -> Vec<Vec<u8>> {
+        let now = Instant::now();
+        self.store
+            .keys()
+            .filter(|key| self.expires.get(key).is_none_or(|expiration| now <= *expiration))
+            .cloned()
+            .collect()
     }
 
     pub fn clear(&mut self) {
         self.store.clear();
         self.expires.clear();
+        self.field_expires.clear();
 
         self.store.shrink_to_fit();
         self.expires.shrink_to_fit();
+        self.field_expires.shrink_to_fit();
     }
 
-    fn is_expired(&mut self, key: String) -> bool {
-        match self.expires.get(&key.to_string()).copied() {
+    fn is_expired(&mut self, key: &[u8]) -> bool {
+        match self.expires.get(key).copied() {
             Some(expiration) => Instant::now() > expiration,
             _ => false,
         }
     }
 
-    pub fn set_expiration(&mut self, key: String, time: Instant) {
-        if let Some(_) = self.get(key.to_string()) {
+    pub fn set_expiration(&mut self, key: Vec<u8>, time: Instant) {
+        if self.get(&key).is_some() {
             self.expires.insert(key, time);
         };
     }
+
+    /// Returns when a live key expires, or `None` if it has no expiration.
+    pub fn expiration(&mut self, key: &[u8]) -> Option<Instant> {
+        if self.is_expired(key) {
+            self.delete(key);
+            return None;
+        }
+        self.expires.get(key).copied()
+    }
+
+    /// Removes the expiration of a key, returning whether it had one.
+    pub fn persist(&mut self, key: &[u8]) -> bool {
+        if self.is_expired(key) {
+            self.delete(key);
+            return false;
+        }
+        self.expires.remove(key).is_some()
+    }
+
+    /// Drops the fields of the hash at `key` whose expiration has passed, and
+    /// the key itself once no fields are left.
+    fn remove_expired_fields(&mut self, key: &[u8]) {
+        let Some(fields) = self.field_expires.get_mut(key) else {
+            return;
+        };
+        let now = Instant::now();
+        let expired: Vec<Vec<u8>> = fields
+            .iter()
+            .filter(|(_, expiration)| now > **expiration)
+            .map(|(field, _)| field.clone())
+            .collect();
+        if expired.is_empty() {
+            return;
+        }
+
+        fields.retain(|_, expiration| now <= *expiration);
+        if fields.is_empty() {
+            self.field_expires.remove(key);
+        }
+        if let Some(Record::HashMap(hash)) = self.store.get_mut(key) {
+            for field in &expired {
+                hash.remove(field);
+            }
+            if hash.is_empty() {
+                self.delete(key);
+            }
+        }
+    }
+
+    /// Sets when a field of the hash at `key` expires. Does nothing unless
+    /// the field exists.
+    pub fn set_field_expiration(&mut self, key: &[u8], field: &[u8], time: Instant) {
+        match self.get_mut(key) {
+            Some(Record::HashMap(hash)) if hash.contains_key(field) => {
+                self.field_expires
+                    .get_or_default(key)
+                    .insert(field.to_vec(), time);
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns when a field of the hash at `key` expires, or `None` if it has
+    /// no expiration.
+    pub fn field_expiration(&mut self, key: &[u8], field: &[u8]) -> Option<Instant> {
+        self.get_mut(key)?;
+        self.field_expires.get(key)?.get(field).copied()
+    }
+
+    /// Removes the expiration of a hash field, returning whether it had one.
+    pub fn persist_field(&mut self, key: &[u8], field: &[u8]) -> bool {
+        let Some(fields) = self.field_expires.get_mut(key) else {
+            return false;
+        };
+        let removed = fields.remove(field).is_some();
+        if fields.is_empty() {
+            self.field_expires.remove(key);
+        }
+        removed
+    }
+
+    /// Marks a key as having received data, so clients blocked on it get a
+    /// chance to be served.
+    pub fn signal_ready(&mut self, key: &[u8]) {
+        if !self.ready_keys.iter().any(|ready| ready == key) {
+            self.ready_keys.push(key.to_vec());
+        }
+    }
+
+    /// Returns the keys signaled since the last call.
+    pub fn take_ready_keys(&mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.ready_keys)
+    }
+
+    /// Deletes keys and hash fields whose expiration has passed, so the
+    /// memory they hold is reclaimed even if nobody reads them again.
+    ///
+    /// Like Redis's active expiry this only samples: it checks a few random
+    /// keys and goes for another round while more than a quarter of them had
+    /// expired, until it runs out of time. Then it does the same for hashes
+    /// with field expirations.
+    pub fn remove_expired(&mut self) {
+        let started = Instant::now();
+        let mut rng = rand::thread_rng();
+        loop {
+            let now = Instant::now();
+            let sample = self.expires.sample(&mut rng, ACTIVE_EXPIRE_SAMPLE);
+            let mut expired = 0;
+            for key in &sample {
+                if self.expires.get(key).is_some_and(|expiration| now > *expiration) {
+                    self.delete(key);
+                    expired += 1;
+                }
+            }
+            if expired * 4 <= sample.len() || started.elapsed() > ACTIVE_EXPIRE_BUDGET {
+                break;
+            }
+        }
+
+        loop {
+            let sample = self.field_expires.sample(&mut rng, ACTIVE_EXPIRE_SAMPLE);
+            let mut expired = 0;
+            for key in &sample {
+                let fields = self.field_expires.get(key).map_or(0, HashMap::len);
+                self.remove_expired_fields(key);
+                if self.field_expires.get(key).map_or(0, HashMap::len) < fields {
+                    expired += 1;
+                }
+            }
+            if expired * 4 <= sample.len() || started.elapsed() > ACTIVE_EXPIRE_BUDGET {
+                break;
+            }
+        }
+    }
+}
+
+/// A map that can also pick random keys in constant time, so that active
+/// expiry can sample it instead of scanning all of it.
+struct SampledMap<V> {
+    /// Each value along with the position of its key in `keys`.
+    entries: HashMap<Vec<u8>, (V, usize)>,
+    keys: Vec<Vec<u8>>,
+}
+
+impl<V> Default for SampledMap<V> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            keys: Vec::new(),
+        }
+    }
+}
+
+impl<V> SampledMap<V> {
+    fn get(&self, key: &[u8]) -> Option<&V> {
+        self.entries.get(key).map(|(value, _)| value)
+    }
+
+    fn get_mut(&mut self, key: &[u8]) -> Option<&mut V> {
+        self.entries.get_mut(key).map(|(value, _)| value)
+    }
+
+    fn get_or_default(&mut self, key: &[u8]) -> &mut V
+    where
+        V: Default,
+    {
+        if !self.entries.contains_key(key) {
+            self.insert(key.to_vec(), V::default());
+        }
+        self.get_mut(key).expect("the key was just inserted")
+    }
+
+    fn insert(&mut self, key: Vec<u8>, value: V) -> Option<V> {
+        if let Some((old, _)) = self.entries.get_mut(&key) {
+            return Some(std::mem::replace(old, value));
+        }
+        self.entries.insert(key.clone(), (value, self.keys.len()));
+        self.keys.push(key);
+        None
+    }
+
+    fn remove(&mut self, key: &[u8]) -> Option<V> {
+        let (value, position) = self.entries.remove(key)?;
+        self.keys.swap_remove(position);
+        if let Some(moved) = self.keys.get(position) {
+            if let Some((_, moved_position)) = self.entries.get_mut(moved) {
+                *moved_position = position;
+            }
+        }
+        Some(value)
+    }
+
+    /// Up to `amount` distinct keys, picked at random.
+    fn sample(&self, rng: &mut impl Rng, amount: usize) -> Vec<Vec<u8>> {
+        index::sample(rng, self.keys.len(), amount.min(self.keys.len()))
+            .into_iter()
+            .map(|position| self.keys[position].clone())
+            .collect()
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[cfg(test)]
+    fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.keys.clear();
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.entries.shrink_to_fit();
+        self.keys.shrink_to_fit();
+    }
 }
 
 #[cfg(test)]
@@ -65,20 +337,20 @@ mod tests {
     #[test]
     fn test_set() {
         let mut repo = Repository::new();
-        let key = String::from("x");
-        let record = Record::String("abc".to_string());
+        let key = b"x".to_vec();
+        let record = Record::String(b"abc".to_vec());
         repo.set(key.clone(), record);
 
         assert_eq!(repo.store.len(), 1);
-        assert_eq!(repo.store.get(&key).unwrap().to_owned(), Record::String("abc".to_string()));
+        assert_eq!(repo.store.get(&key).unwrap().to_owned(), Record::String(b"abc".to_vec()));
     }
 
     #[test]
     fn test_clear() {
         let mut repo = Repository::new();
         let expires_at = Instant::now() + Duration::from_secs(10);
-        let key = String::from("x");
-        let record = Record::String("abc".to_string());
+        let key = b"x".to_vec();
+        let record = Record::String(b"abc".to_vec());
         repo.set(key.clone(), record);
         repo.set_expiration(key.clone(), expires_at);
 
@@ -94,29 +366,112 @@ mod tests {
     fn test_delete() {
         let mut repo = Repository::new();
         let expires_at = Instant::now() + Duration::from_secs(10);
-        let key = String::from("x");
-        let record = Record::String("abc".to_string());
+        let key = b"x".to_vec();
+        let record = Record::String(b"abc".to_vec());
         repo.set(key.clone(), record);
         repo.set_expiration(key.clone(), expires_at);
 
         assert_eq!(repo.store.len(), 1);
         assert_eq!(repo.expires.len(), 1);
 
-        repo.delete(key);
+        repo.delete(&key);
         assert_eq!(repo.store.len(), 0);
         assert_eq!(repo.expires.len(), 0);
     }
 
+    #[test]
+    fn test_set_keeps_expiration() {
+        let mut repo = Repository::new();
+        let expires_at = Instant::now() + Duration::from_secs(10);
+        let key = b"x".to_vec();
+        repo.set(key.clone(), Record::String(b"abc".to_vec()));
+        repo.set_expiration(key.clone(), expires_at);
+        repo.set(key.clone(), Record::String(b"def".to_vec()));
+
+        assert_eq!(repo.expiration(&key), Some(expires_at));
+        assert!(repo.persist(&key));
+        assert_eq!(repo.expiration(&key), None);
+        assert!(!repo.persist(&key));
+    }
+
+    #[test]
+    fn test_field_expiration() {
+        let mut repo = Repository::new();
+        let key = b"h".to_vec();
+        let hash = [(b"a".to_vec(), b"1".to_vec()), (b"b".to_vec(), b"2".to_vec())];
+        repo.set(key.clone(), Record::HashMap(hash.into_iter().collect()));
+
+        let later = Instant::now() + Duration::from_secs(10);
+        repo.set_field_expiration(&key, b"a", later);
+        repo.set_field_expiration(&key, b"missing", later);
+        assert_eq!(repo.field_expiration(&key, b"a"), Some(later));
+        assert_eq!(repo.field_expiration(&key, b"missing"), None);
+
+        repo.set_field_expiration(&key, b"b", Instant::now() - Duration::from_secs(1));
+        let Some(Record::HashMap(hash)) = repo.get(&key) else {
+            panic!("the hash should still exist");
+        };
+        assert_eq!(hash.len(), 1);
+
+        assert!(repo.persist_field(&key, b"a"));
+        assert!(repo.field_expires.is_empty());
+    }
+
+    #[test]
+    fn test_remove_expired() {
+        let mut repo = Repository::new();
+        let past = Instant::now() - Duration::from_secs(1);
+        repo.set(b"s".to_vec(), Record::String(b"abc".to_vec()));
+        repo.expires.insert(b"s".to_vec(), past);
+        repo.set(b"h".to_vec(), Record::HashMap([(b"a".to_vec(), b"1".to_vec())].into_iter().collect()));
+        repo.field_expires.insert(b"h".to_vec(), [(b"a".to_vec(), past)].into_iter().collect());
+
+        repo.remove_expired();
+        assert!(repo.store.is_empty());
+        assert!(repo.expires.is_empty());
+        assert!(repo.field_expires.is_empty());
+    }
+
+    #[test]
+    fn test_remove_expired_samples_until_mostly_live() {
+        let mut repo = Repository::new();
+        let past = Instant::now() - Duration::from_secs(1);
+        let later = Instant::now() + Duration::from_secs(10);
+        for i in 0..500 {
+            let key = format!("expired:{}", i).into_bytes();
+            repo.set(key.clone(), Record::Int(i));
+            repo.expires.insert(key, past);
+        }
+        repo.set(b"live".to_vec(), Record::Int(0));
+        repo.expires.insert(b"live".to_vec(), later);
+
+        repo.remove_expired();
+        assert_eq!(repo.keys(), vec![b"live".to_vec()]);
+        assert_eq!(repo.expires.len(), 1);
+    }
+
+    #[test]
+    fn test_sampled_map_remove() {
+        let mut map = SampledMap::default();
+        for key in ["a", "b", "c"] {
+            map.insert(key.as_bytes().to_vec(), key);
+        }
+        assert_eq!(map.remove(b"a"), Some("a"));
+        assert_eq!(map.remove(b"a"), None);
+        assert_eq!(map.remove(b"c"), Some("c"));
+        assert_eq!(map.sample(&mut rand::thread_rng(), 5), vec![b"b".to_vec()]);
+    }
+
     #[test]
     fn test_get_expired() {
         let mut repo = Repository::new();
         let expires_at = Instant::now() - Duration::from_secs(10);
-        let key = String::from("x");
-        let record = Record::String("abc".to_string());
+        let key = b"x".to_vec();
+        let record = Record::String(b"abc".to_vec());
         repo.set(key.clone(), record);
         repo.set_expiration(key.clone(), expires_at);
 
-        assert_eq!(repo.get(key), None);
+        assert_eq!(repo.get(&key), None);
 
         assert_eq!(repo.store.len(), 0);
         assert_eq!(repo.expires.len(), 0);
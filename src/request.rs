@@ -1,21 +1,22 @@
+#[derive(Debug, Clone, PartialEq)]
 pub struct Request {
-    query: Vec<String>,
+    query: Vec<Vec<u8>>,
 }
 
 impl Request {
-    pub fn new(query: Vec<String>) -> Self {
+    pub fn new(query: Vec<Vec<u8>>) -> Self {
         Self { query }
     }
 
-    pub fn command(&self) -> &str {
-        self.query[0].as_str()
+    pub fn command(&self) -> &[u8] {
+        &self.query[0]
     }
 
     pub fn arity(&self) -> i64 {
         self.query.len().try_into().unwrap()
     }
 
-    pub fn arguments(&self) -> &[String] {
+    pub fn arguments(&self) -> &[Vec<u8>] {
         &self.query[1..]
     }
-}
\ No newline at end of file
+}
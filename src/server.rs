@@ -1,75 +1,948 @@
 use std::{
-    io::{Read, Write},
-    net::{Shutdown, TcpStream},
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    fmt,
+    io::{self, Read, Write},
+    net::SocketAddr,
+    time::{Duration, Instant},
 };
 
+use mio::{
+    net::{TcpListener, TcpStream},
+    Events, Interest, Poll, Token,
+};
 use thiserror::Error;
 
 use crate::{
-    operations::{lookup, OperationResult},
-    protocol::{decode, RESPError, RespValueRef},
+    config::{Config, LogLevel},
+    operations::{lookup, Context, OperationError, OperationResult},
+    protocol::{decode_request, RESPError, RespValueRef},
     repository::Repository,
     request::Request,
+    session::{Block, Session},
+    stats::Stats,
 };
 
 #[derive(Error, Debug)]
 pub enum ResponseError {
-    #[error("Failed to parse request")]
+    #[error("ERR Protocol error: {0}")]
     ProtocolError(#[from] RESPError),
-    #[error("Empty message")]
-    EmptyMessageError,
-    #[error("Malformed Request")]
+    #[error("ERR Protocol error: expected an array of bulk strings")]
     BadRequestError,
-    #[error("Not implemented")]
-    NotImplementedError,
+    #[error("ERR max number of clients reached")]
+    MaxClientsError,
 }
 
-pub fn handle_connection(mut stream: TcpStream) -> () {
-    let mut buffer = [0; 1024];
-    let mut repo = Repository::new();
+const EVENTS_CAPACITY: usize = 1024;
+/// How often housekeeping such as closing idle clients runs.
+const CRON_INTERVAL: Duration = Duration::from_millis(100);
+/// Clients whose unparsed input grows past this are disconnected, matching
+/// the default `client-query-buffer-limit`.
+const QUERY_BUFFER_LIMIT: usize = 1024 * 1024 * 1024;
 
-    while match stream.read(&mut buffer) {
-        Ok(_) => {
-            let result = handle_request(&mut buffer, &mut repo);
-            let res = match result {
-                Ok(v) => v.into(),
-                Err(e) => RespValueRef::Failure(e.to_string()),
-            };
+/// A connected client and the bytes waiting to be parsed or sent back to it.
+/// The read buffer grows until it holds a whole frame and keeps any bytes
+/// past the end of it for the next one.
+struct Client {
+    stream: TcpStream,
+    addr: SocketAddr,
+    session: Session,
+    read_buf: Vec<u8>,
+    write_buf: Vec<u8>,
+    /// Whether the socket is registered for writable events because the last
+    /// flush couldn't send everything.
+    write_interest: bool,
+    last_interaction: Instant,
+    /// The command this client is parked on, if any. Input that arrives in
+    /// the meantime stays buffered until it is served or times out.
+    blocked: Option<(Request, Block)>,
+    /// Set after a protocol error: nothing more is read from this client and
+    /// it is closed once its pending replies are sent.
+    close_after_reply: bool,
+}
 
-            stream.write(res.write_resp_value().as_bytes()).unwrap();
-            stream.flush().unwrap();
-            true
+impl Client {
+    fn new(stream: TcpStream, addr: SocketAddr, id: u64) -> Self {
+        Self {
+            stream,
+            addr,
+            session: Session::new(id),
+            read_buf: Vec::new(),
+            write_buf: Vec::new(),
+            write_interest: false,
+            last_interaction: Instant::now(),
+            blocked: None,
+            close_after_reply: false,
         }
-        Err(_) => {
-            println!(
-                "An error occurred, terminating connection with {}",
-                stream.peer_addr().unwrap()
-            );
-            stream.shutdown(Shutdown::Both).unwrap();
-            false
+    }
+
+    /// Drains the socket into the read buffer. Returns `false` once the peer
+    /// has closed the connection or the socket failed.
+    fn fill_read_buf(&mut self) -> bool {
+        let mut chunk = [0; 4096];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return false,
+                Ok(n) => self.read_buf.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return true,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => return false,
+            }
         }
-    } {}
+    }
+
+    /// Encodes a reply at the end of the output buffer.
+    fn reply(&mut self, value: RespValueRef) {
+        value
+            .write_resp_value(&mut self.write_buf)
+            .expect("writing to a Vec never fails");
+    }
+
+    /// Writes as much of the pending output as the socket accepts. Returns
+    /// `false` if the socket failed.
+    fn flush_write_buf(&mut self) -> bool {
+        let mut written = 0;
+        let open = loop {
+            if written == self.write_buf.len() {
+                break true;
+            }
+            match self.stream.write(&self.write_buf[written..]) {
+                Ok(0) => break false,
+                Ok(n) => written += n,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break true,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break false,
+            }
+        };
+        self.write_buf.drain(..written);
+        open
+    }
+}
+
+/// Clients parked by blocking commands, queued per key in the order they
+/// blocked so that the longest waiting client is served first.
+#[derive(Default)]
+struct BlockedClients {
+    by_key: HashMap<Vec<u8>, VecDeque<Token>>,
+    deadlines: BTreeSet<(Instant, Token)>,
 }
 
-fn handle_request(buffer: &mut [u8], repo: &mut Repository) -> Result<OperationResult, ResponseError> {
-    let raw_message = String::from_utf8_lossy(&buffer[..]);
-    println!("Message received:\r\n{}", raw_message);
+impl BlockedClients {
+    fn add(&mut self, token: Token, block: &Block) {
+        for key in &block.keys {
+            self.by_key.entry(key.clone()).or_default().push_back(token);
+        }
+        if let Some(deadline) = block.deadline {
+            self.deadlines.insert((deadline, token));
+        }
+    }
+
+    fn remove(&mut self, token: Token, block: &Block) {
+        for key in &block.keys {
+            if let Some(waiting) = self.by_key.get_mut(key) {
+                waiting.retain(|waiter| *waiter != token);
+                if waiting.is_empty() {
+                    self.by_key.remove(key);
+                }
+            }
+        }
+        if let Some(deadline) = block.deadline {
+            self.deadlines.remove(&(deadline, token));
+        }
+    }
 
-    let decoded_message = decode(&mut buffer[..])?;
+    /// The clients waiting on `key`, longest waiting first.
+    fn waiting_on(&self, key: &[u8]) -> Vec<Token> {
+        self.by_key
+            .get(key)
+            .map_or_else(Vec::new, |waiting| waiting.iter().copied().collect())
+    }
 
-    let Some(parsed_message) = decoded_message else {
-        return Err(ResponseError::EmptyMessageError)
-    };
+    fn is_waiting_on(&self, key: &[u8]) -> bool {
+        self.by_key.contains_key(key)
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        self.deadlines.first().map(|(deadline, _)| *deadline)
+    }
+
+    /// The clients whose deadline has passed by `now`.
+    fn timed_out(&self, now: Instant) -> Vec<Token> {
+        self.deadlines
+            .iter()
+            .take_while(|(deadline, _)| *deadline <= now)
+            .map(|(_, token)| *token)
+            .collect()
+    }
+}
+
+/// Single-threaded reactor serving every client from one event loop.
+/// Commands run one at a time against the keyspace, so the repository needs
+/// no locking. Replies are buffered per client and flushed once per loop
+/// iteration, after every ready client has been processed.
+pub struct Server {
+    poll: Poll,
+    /// One listener per bind address. A listener's token is its index here.
+    listeners: Vec<TcpListener>,
+    config: Config,
+    stats: Stats,
+    repo: Repository,
+    clients: HashMap<Token, Client>,
+    pending_writes: HashSet<Token>,
+    blocked: BlockedClients,
+    /// Keys blocked clients wait on that received data, to be served once the
+    /// command that signaled them finishes.
+    ready_keys: Vec<Vec<u8>>,
+    next_token: usize,
+    last_cron: Instant,
+}
+
+impl Server {
+    pub fn new(config: Config) -> io::Result<Self> {
+        let poll = Poll::new()?;
+        let mut listeners = Vec::with_capacity(config.bind.len());
+        for (index, ip) in config.bind.iter().enumerate() {
+            let mut listener = TcpListener::bind(SocketAddr::new(*ip, config.port))?;
+            poll.registry()
+                .register(&mut listener, Token(index), Interest::READABLE)?;
+            listeners.push(listener);
+        }
+
+        Ok(Self {
+            poll,
+            next_token: listeners.len(),
+            listeners,
+            config,
+            stats: Stats::default(),
+            repo: Repository::new(),
+            clients: HashMap::new(),
+            pending_writes: HashSet::new(),
+            blocked: BlockedClients::default(),
+            ready_keys: Vec::new(),
+            last_cron: Instant::now(),
+        })
+    }
+
+    pub fn local_addrs(&self) -> io::Result<Vec<SocketAddr>> {
+        self.listeners.iter().map(|listener| listener.local_addr()).collect()
+    }
+
+    pub fn run(&mut self) -> io::Result<()> {
+        let mut events = Events::with_capacity(EVENTS_CAPACITY);
+        for addr in self.local_addrs()? {
+            log(&self.config, LogLevel::Notice, format_args!("Listening on {}", addr));
+        }
+
+        loop {
+            let mut timeout = CRON_INTERVAL.saturating_sub(self.last_cron.elapsed());
+            if let Some(deadline) = self.blocked.next_deadline() {
+                timeout = timeout.min(deadline.saturating_duration_since(Instant::now()));
+            }
+            if let Err(e) = self.poll.poll(&mut events, Some(timeout)) {
+                if e.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(e);
+            }
+
+            for event in events.iter() {
+                match event.token() {
+                    Token(index) if index < self.listeners.len() => self.accept(index),
+                    token => {
+                        let mut open = true;
+                        if event.is_readable() {
+                            open = self.readable(token);
+                        }
+                        if open && event.is_writable() {
+                            open = self.writable(token);
+                        }
+                        if !open {
+                            self.disconnect(token);
+                        }
+                    }
+                }
+            }
+
+            self.time_out_blocked();
+            if self.last_cron.elapsed() >= CRON_INTERVAL {
+                self.cron();
+                self.last_cron = Instant::now();
+            }
+            self.flush_pending_writes();
+        }
+    }
+
+    /// Periodic housekeeping, run between event loop iterations.
+    fn cron(&mut self) {
+        self.repo.remove_expired();
+
+        if self.config.timeout > 0 {
+            let timeout = Duration::from_secs(self.config.timeout);
+            let idle: Vec<(Token, SocketAddr)> = self
+                .clients
+                .iter()
+                .filter(|(_, client)| client.blocked.is_none())
+                .filter(|(_, client)| client.last_interaction.elapsed() > timeout)
+                .map(|(token, client)| (*token, client.addr))
+                .collect();
+            for (token, addr) in idle {
+                log(&self.config, LogLevel::Verbose, format_args!("Closing idle client: {}", addr));
+                self.disconnect(token);
+            }
+        }
+    }
+
+    /// Accepts every pending connection on a listener. Errors such as running
+    /// out of file descriptors are logged rather than stopping the server.
+    fn accept(&mut self, index: usize) {
+        loop {
+            let (mut stream, addr) = match self.listeners[index].accept() {
+                Ok(conn) => conn,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    log(&self.config, LogLevel::Warning, format_args!("Accepting client connection: {}", e));
+                    return;
+                }
+            };
+
+            self.stats.total_connections_received += 1;
+            if self.clients.len() >= self.config.maxclients {
+                self.stats.rejected_connections += 1;
+                let mut err = Vec::new();
+                let _ = RespValueRef::Failure(ResponseError::MaxClientsError.to_string())
+                    .write_resp_value(&mut err);
+                let _ = stream.write_all(&err);
+                continue;
+            }
+
+            log(&self.config, LogLevel::Verbose, format_args!("New connection: {}", addr));
+            let token = Token(self.next_token);
+            self.next_token += 1;
+            if let Err(e) = self.poll.registry().register(&mut stream, token, Interest::READABLE) {
+                log(&self.config, LogLevel::Warning, format_args!("Registering client {}: {}", addr, e));
+                continue;
+            }
+            self.clients.insert(token, Client::new(stream, addr, token.0 as u64));
+        }
+    }
+
+    fn readable(&mut self, token: Token) -> bool {
+        let Some(client) = self.clients.get_mut(&token) else {
+            return true;
+        };
+
+        if client.close_after_reply {
+            return true;
+        }
+        let open = client.fill_read_buf();
+        client.last_interaction = Instant::now();
+        self.process_input(token);
+
+        let Some(client) = self.clients.get(&token) else {
+            return open;
+        };
+        if client.read_buf.len() > QUERY_BUFFER_LIMIT {
+            log(&self.config, LogLevel::Warning, format_args!("Closing client that reached max query buffer length: {}", client.addr));
+            return false;
+        }
+        open
+    }
+
+    /// Runs every complete frame in a client's read buffer, in order, serving
+    /// blocked clients as soon as a command gives them something to pop.
+    fn process_input(&mut self, token: Token) {
+        loop {
+            let interrupted = self.process_frames(token);
+            self.serve_ready_keys();
+            if !interrupted {
+                return;
+            }
+        }
+    }
+
+    /// Runs complete frames from a client's read buffer, queueing all of the
+    /// replies so that a pipeline is answered with a single write. Stops when
+    /// a command parks the client, or returns `true` to let blocked clients
+    /// run first when a command made one of their keys ready.
+    fn process_frames(&mut self, token: Token) -> bool {
+        let Some(client) = self.clients.get_mut(&token) else {
+            return false;
+        };
+
+        let mut parsed = 0;
+        let mut interrupted = false;
+        while client.blocked.is_none() && !client.close_after_reply && !interrupted {
+            let request = match decode_request(&mut client.read_buf[parsed..]) {
+                Ok(Some((consumed, message))) => {
+                    let raw_message = String::from_utf8_lossy(&client.read_buf[parsed..parsed + consumed]);
+                    log(&self.config, LogLevel::Debug, format_args!("Message received:\r\n{}", raw_message));
+                    parsed += consumed;
+                    // Blank inline lines and empty arrays are skipped without a reply.
+                    if message == RespValueRef::Array(vec![]) {
+                        continue;
+                    }
+                    message.try_into().map_err(|_| ResponseError::BadRequestError)
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    log(&self.config, LogLevel::Verbose, format_args!("Protocol error from client {}: {}", client.addr, e));
+                    parsed = client.read_buf.len();
+                    client.close_after_reply = true;
+                    Err(e.into())
+                }
+            };
+            let request: Request = match request {
+                Ok(request) => request,
+                Err(e) => {
+                    client.reply(RespValueRef::Failure(e.to_string()));
+                    continue;
+                }
+            };
+
+            self.stats.total_commands_processed += 1;
+            let mut ctx = Context {
+                repo: &mut self.repo,
+                session: &mut client.session,
+                config: &mut self.config,
+                stats: &mut self.stats,
+            };
+            let result = execute(&request, &mut ctx);
+            match client.session.block.take() {
+                Some(mut block) => {
+                    self.blocked.add(token, &block);
+                    client.blocked = Some((block.retry.take().unwrap_or(request), block));
+                }
+                None => client.reply(RespValueRef::from_result(result, client.session.protocol)),
+            }
+            for key in self.repo.take_ready_keys() {
+                if self.blocked.is_waiting_on(&key) {
+                    self.ready_keys.push(key);
+                }
+            }
+            interrupted = !self.ready_keys.is_empty();
+        }
+        client.read_buf.drain(..parsed);
+
+        if !client.write_buf.is_empty() {
+            self.pending_writes.insert(token);
+        }
+        interrupted
+    }
 
-    let message_to_request_result: Result<Request, _> = parsed_message.try_into();
-    if let Err(_) = message_to_request_result {
-        return Err(ResponseError::BadRequestError);
+    /// Gives the clients blocked on keys that received data a chance to run
+    /// their command again, longest waiting first. Serving one client can
+    /// make other keys ready, so this repeats until nothing is left.
+    fn serve_ready_keys(&mut self) {
+        loop {
+            let mut ready = std::mem::take(&mut self.ready_keys);
+            ready.extend(self.repo.take_ready_keys());
+            if ready.is_empty() {
+                return;
+            }
+            for key in ready {
+                for token in self.blocked.waiting_on(&key) {
+                    self.retry_blocked(token);
+                }
+            }
+        }
+    }
+
+    /// Runs a parked command again, replying and unparking the client if it
+    /// no longer needs to wait. A client that still has to wait keeps its
+    /// place in line and its original deadline.
+    fn retry_blocked(&mut self, token: Token) {
+        let Some(client) = self.clients.get_mut(&token) else {
+            return;
+        };
+        let Some((request, _)) = &client.blocked else {
+            return;
+        };
+
+        let mut ctx = Context {
+            repo: &mut self.repo,
+            session: &mut client.session,
+            config: &mut self.config,
+            stats: &mut self.stats,
+        };
+        let result = execute(request, &mut ctx);
+        if client.session.block.take().is_some() {
+            return;
+        }
+        client.reply(RespValueRef::from_result(result, client.session.protocol));
+        self.unblock(token);
+    }
+
+    /// Replies with a null array to every parked client whose timeout passed.
+    fn time_out_blocked(&mut self) {
+        for token in self.blocked.timed_out(Instant::now()) {
+            if let Some(client) = self.clients.get_mut(&token) {
+                let reply = OperationResult::NullArray;
+                client.reply(RespValueRef::from_result(reply, client.session.protocol));
+            }
+            self.unblock(token);
+        }
+    }
+
+    /// Takes a client out of the blocked registry and runs whatever it sent
+    /// while it was parked.
+    fn unblock(&mut self, token: Token) {
+        let Some(client) = self.clients.get_mut(&token) else {
+            return;
+        };
+        if let Some((_, block)) = client.blocked.take() {
+            self.blocked.remove(token, &block);
+        }
+        self.pending_writes.insert(token);
+        self.process_input(token);
+    }
+
+    fn writable(&mut self, token: Token) -> bool {
+        self.flush(token)
+    }
+
+    fn flush_pending_writes(&mut self) {
+        let pending: Vec<Token> = self.pending_writes.drain().collect();
+        for token in pending {
+            if !self.flush(token) {
+                self.disconnect(token);
+            }
+        }
+    }
+
+    /// Sends pending output, asking to be woken up when the socket drains if
+    /// it could not all be written right away.
+    fn flush(&mut self, token: Token) -> bool {
+        let Some(client) = self.clients.get_mut(&token) else {
+            return true;
+        };
+
+        if !client.flush_write_buf() {
+            return false;
+        }
+        if client.close_after_reply && client.write_buf.is_empty() {
+            return false;
+        }
+
+        let want_write = !client.write_buf.is_empty();
+        if want_write == client.write_interest {
+            return true;
+        }
+        client.write_interest = want_write;
+        let interest = if want_write {
+            Interest::READABLE | Interest::WRITABLE
+        } else {
+            Interest::READABLE
+        };
+        self.poll
+            .registry()
+            .reregister(&mut client.stream, token, interest)
+            .is_ok()
     }
-    let request = message_to_request_result.unwrap();
 
-    let Some(operation) = lookup(&request.command()) else {
-        return Err(ResponseError::NotImplementedError)
+    fn disconnect(&mut self, token: Token) {
+        self.pending_writes.remove(&token);
+        if let Some(mut client) = self.clients.remove(&token) {
+            if let Some((_, block)) = &client.blocked {
+                self.blocked.remove(token, block);
+            }
+            log(&self.config, LogLevel::Verbose, format_args!("Connection closed: {}", client.addr));
+            let _ = self.poll.registry().deregister(&mut client.stream);
+        }
+    }
+}
+
+fn log(config: &Config, level: LogLevel, message: fmt::Arguments) {
+    if level >= config.loglevel {
+        println!("{}", message);
+    }
+}
+
+fn execute(request: &Request, ctx: &mut Context) -> OperationResult {
+    let Some(operation) = lookup(request.command()) else {
+        let name = String::from_utf8_lossy(request.command()).to_string();
+        return OperationError::UnknownCommand(name).into()
     };
 
-    Ok(operation.execute(repo, &request))
+    operation.execute(ctx, request)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net, sync::mpsc, thread};
+
+    use super::*;
+
+    fn start_server(max_clients: usize) -> SocketAddr {
+        start_server_with(Config {
+            port: 0,
+            maxclients: max_clients,
+            loglevel: LogLevel::Warning,
+            ..Config::default()
+        })
+    }
+
+    fn start_server_with(config: Config) -> SocketAddr {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut server = Server::new(config).unwrap();
+            tx.send(server.local_addrs().unwrap()[0]).unwrap();
+            server.run().unwrap();
+        });
+        rx.recv().unwrap()
+    }
+
+    fn send(stream: &mut net::TcpStream, message: &[u8]) -> String {
+        stream.write_all(message).unwrap();
+        receive(stream)
+    }
+
+    fn receive(stream: &mut net::TcpStream) -> String {
+        let mut buffer = [0; 1024];
+        let n = stream.read(&mut buffer).unwrap();
+        String::from_utf8_lossy(&buffer[..n]).to_string()
+    }
+
+    /// Sends a blocking command and waits long enough for the server to park
+    /// the client before anything else happens.
+    fn send_blocking(stream: &mut net::TcpStream, message: &[u8]) {
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        stream.write_all(message).unwrap();
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_clients_share_keyspace() {
+        let addr = start_server(10);
+        let mut first = net::TcpStream::connect(addr).unwrap();
+        let mut second = net::TcpStream::connect(addr).unwrap();
+
+        assert_eq!(send(&mut first, b"*3\r\n$3\r\nSET\r\n$1\r\nx\r\n$1\r\n1\r\n"), "+OK\r\n");
+        assert_eq!(send(&mut second, b"*2\r\n$3\r\nGET\r\n$1\r\nx\r\n"), "$1\r\n1\r\n");
+    }
+
+    #[test]
+    fn test_idle_client_does_not_block_others() {
+        let addr = start_server(10);
+        let _idle = net::TcpStream::connect(addr).unwrap();
+        let mut active = net::TcpStream::connect(addr).unwrap();
+
+        assert_eq!(send(&mut active, b"*1\r\n$7\r\nCOMMAND\r\n"), "+OK\r\n");
+    }
+
+    #[test]
+    fn test_frame_split_across_reads() {
+        let addr = start_server(10);
+        let mut client = net::TcpStream::connect(addr).unwrap();
+        let value = "v".repeat(4096);
+        let set = format!("*3\r\n$3\r\nSET\r\n$1\r\nx\r\n${}\r\n{}\r\n", value.len(), value);
+        let (head, tail) = set.as_bytes().split_at(10);
+
+        client.write_all(head).unwrap();
+        client.flush().unwrap();
+        thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(send(&mut client, tail), "+OK\r\n");
+
+        client.write_all(b"*2\r\n$3\r\nGET\r\n$1\r\nx\r\n").unwrap();
+        let expected = format!("${}\r\n{}\r\n", value.len(), value);
+        let mut reply = vec![0; expected.len()];
+        client.read_exact(&mut reply).unwrap();
+        assert_eq!(reply, expected.as_bytes());
+    }
+
+    #[test]
+    fn test_pipelined_commands() {
+        let addr = start_server(10);
+        let mut client = net::TcpStream::connect(addr).unwrap();
+
+        let mut pipeline = String::new();
+        let mut expected = String::new();
+        for i in 0..1000 {
+            let key = format!("key:{}", i);
+            let value = i.to_string();
+            pipeline.push_str(&format!(
+                "*3\r\n$3\r\nSET\r\n${}\r\n{}\r\n${}\r\n{}\r\n",
+                key.len(), key, value.len(), value
+            ));
+            pipeline.push_str(&format!("*2\r\n$3\r\nGET\r\n${}\r\n{}\r\n", key.len(), key));
+            expected.push_str(&format!("+OK\r\n${}\r\n{}\r\n", value.len(), value));
+        }
+        client.write_all(pipeline.as_bytes()).unwrap();
+
+        let mut replies = vec![0; expected.len()];
+        client.read_exact(&mut replies).unwrap();
+        assert_eq!(String::from_utf8_lossy(&replies), expected);
+    }
+
+    #[test]
+    fn test_protocol_error_closes_connection() {
+        let addr = start_server(10);
+        let mut client = net::TcpStream::connect(addr).unwrap();
+
+        assert_eq!(send(&mut client, b"*3000000000\r\n"), "-ERR Protocol error: invalid multibulk length\r\n");
+        let mut buffer = [0; 16];
+        assert_eq!(client.read(&mut buffer).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_nested_request_closes_only_that_connection() {
+        let addr = start_server(10);
+        let mut client = net::TcpStream::connect(addr).unwrap();
+
+        let nested = b"*1\r\n".repeat(200_000);
+        assert_eq!(send(&mut client, &nested), "-ERR Protocol error: expected '$', got '*'\r\n");
+        let mut buffer = [0; 16];
+        assert_eq!(client.read(&mut buffer).unwrap(), 0);
+
+        let mut other = net::TcpStream::connect(addr).unwrap();
+        assert_eq!(send(&mut other, b"*1\r\n$4\r\nPING\r\n"), "+PONG\r\n");
+    }
+
+    #[test]
+    fn test_binary_values_round_trip() {
+        let addr = start_server(10);
+        let mut client = net::TcpStream::connect(addr).unwrap();
+        let value: Vec<u8> = (0..=255).collect();
+
+        let mut set = format!("*3\r\n$3\r\nSET\r\n$3\r\nbin\r\n${}\r\n", value.len()).into_bytes();
+        set.extend_from_slice(&value);
+        set.extend_from_slice(b"\r\n");
+        assert_eq!(send(&mut client, &set), "+OK\r\n");
+
+        client.write_all(b"*2\r\n$3\r\nGET\r\n$3\r\nbin\r\n").unwrap();
+        let mut expected = format!("${}\r\n", value.len()).into_bytes();
+        expected.extend_from_slice(&value);
+        expected.extend_from_slice(b"\r\n");
+        let mut reply = vec![0; expected.len()];
+        client.read_exact(&mut reply).unwrap();
+        assert_eq!(reply, expected);
+    }
+
+    #[test]
+    fn test_hello_switches_protocol() {
+        let addr = start_server(10);
+        let mut client = net::TcpStream::connect(addr).unwrap();
+        let get = b"*2\r\n$3\r\nGET\r\n$7\r\nmissing\r\n";
+
+        assert_eq!(send(&mut client, get), "$-1\r\n");
+        let hello = send(&mut client, b"*2\r\n$5\r\nHELLO\r\n$1\r\n3\r\n");
+        assert!(hello.starts_with("%7\r\n$6\r\nserver\r\n$4\r\nmuna\r\n"), "{}", hello);
+        assert!(hello.contains("$5\r\nproto\r\n:3\r\n"), "{}", hello);
+        assert_eq!(send(&mut client, get), "_\r\n");
+
+        assert_eq!(
+            send(&mut client, b"*2\r\n$5\r\nHELLO\r\n$1\r\n4\r\n"),
+            "-NOPROTO unsupported protocol version\r\n"
+        );
+    }
+
+    #[test]
+    fn test_inline_commands() {
+        let addr = start_server(10);
+        let mut client = net::TcpStream::connect(addr).unwrap();
+
+        assert_eq!(send(&mut client, b"PING\r\n"), "+PONG\r\n");
+        assert_eq!(send(&mut client, b"\nset greeting \"hello world\"\n"), "+OK\r\n");
+        assert_eq!(send(&mut client, b"GET greeting\n"), "$11\r\nhello world\r\n");
+    }
+
+    #[test]
+    fn test_error_replies() {
+        let addr = start_server(10);
+        let mut client = net::TcpStream::connect(addr).unwrap();
+
+        assert_eq!(send(&mut client, b"FOO bar\r\n"), "-ERR unknown command 'FOO'\r\n");
+        assert_eq!(
+            send(&mut client, b"GET\r\n"),
+            "-ERR wrong number of arguments for 'get' command\r\n"
+        );
+        send(&mut client, b"HSET h f v\r\n");
+        assert_eq!(
+            send(&mut client, b"GET h\r\n"),
+            "-WRONGTYPE Operation against a key holding the wrong kind of value\r\n"
+        );
+    }
+
+    #[test]
+    fn test_closes_idle_clients() {
+        let addr = start_server_with(Config {
+            port: 0,
+            timeout: 1,
+            loglevel: LogLevel::Warning,
+            ..Config::default()
+        });
+        let mut client = net::TcpStream::connect(addr).unwrap();
+        assert_eq!(send(&mut client, b"PING\r\n"), "+PONG\r\n");
+
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut buffer = [0; 16];
+        assert_eq!(client.read(&mut buffer).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_config_get_and_set() {
+        let addr = start_server(10);
+        let mut client = net::TcpStream::connect(addr).unwrap();
+
+        assert_eq!(
+            send(&mut client, b"CONFIG GET maxclients\r\n"),
+            "*2\r\n$10\r\nmaxclients\r\n$2\r\n10\r\n"
+        );
+        assert_eq!(send(&mut client, b"CONFIG SET maxclients 1 timeout 30\r\n"), "+OK\r\n");
+        assert_eq!(
+            send(&mut client, b"CONFIG GET max* timeout\r\n"),
+            "*4\r\n$10\r\nmaxclients\r\n$1\r\n1\r\n$7\r\ntimeout\r\n$2\r\n30\r\n"
+        );
+        assert_eq!(
+            send(&mut client, b"CONFIG SET timeout 5 port 1234\r\n"),
+            "-ERR CONFIG SET failed (possibly related to argument 'port') - can't set immutable config\r\n"
+        );
+        assert_eq!(send(&mut client, b"CONFIG GET timeout\r\n"), "*2\r\n$7\r\ntimeout\r\n$2\r\n30\r\n");
+
+        // The new limit applies to the next connection.
+        let mut rejected = net::TcpStream::connect(addr).unwrap();
+        let mut buffer = [0; 64];
+        let n = rejected.read(&mut buffer).unwrap();
+        assert_eq!(&buffer[..n], b"-ERR max number of clients reached\r\n");
+    }
+
+    #[test]
+    fn test_config_resetstat() {
+        let addr = start_server(10);
+        let mut client = net::TcpStream::connect(addr).unwrap();
+
+        send(&mut client, b"PING\r\n");
+        assert!(send(&mut client, b"INFO stats\r\n").contains("total_commands_processed:2\r\n"));
+        assert_eq!(send(&mut client, b"CONFIG RESETSTAT\r\n"), "+OK\r\n");
+        assert!(send(&mut client, b"INFO stats\r\n").contains("total_commands_processed:1\r\n"));
+        assert_eq!(
+            send(&mut client, b"CONFIG REWRITE\r\n"),
+            "-ERR The server is running without a config file\r\n"
+        );
+    }
+
+    #[test]
+    fn test_rejects_clients_over_limit() {
+        let addr = start_server(1);
+        let mut first = net::TcpStream::connect(addr).unwrap();
+        assert_eq!(send(&mut first, b"*1\r\n$7\r\nCOMMAND\r\n"), "+OK\r\n");
+
+        let mut second = net::TcpStream::connect(addr).unwrap();
+        let mut buffer = [0; 1024];
+        let n = second.read(&mut buffer).unwrap();
+        assert_eq!(&buffer[..n], b"-ERR max number of clients reached\r\n");
+    }
+
+    #[test]
+    fn test_blocked_client_is_served_by_push() {
+        let addr = start_server(10);
+        let mut waiter = net::TcpStream::connect(addr).unwrap();
+        let mut pusher = net::TcpStream::connect(addr).unwrap();
+
+        send_blocking(&mut waiter, b"BLPOP empty queue 0\r\n");
+        assert_eq!(send(&mut pusher, b"RPUSH queue job\r\n"), ":1\r\n");
+        assert_eq!(receive(&mut waiter), "*2\r\n$5\r\nqueue\r\n$3\r\njob\r\n");
+        assert_eq!(send(&mut pusher, b"LLEN queue\r\n"), ":0\r\n");
+    }
+
+    #[test]
+    fn test_blocked_client_is_served_by_zadd() {
+        let addr = start_server(10);
+        let mut waiter = net::TcpStream::connect(addr).unwrap();
+        let mut producer = net::TcpStream::connect(addr).unwrap();
+
+        send_blocking(&mut waiter, b"BZPOPMIN delayed 0\r\n");
+        assert_eq!(send(&mut producer, b"ZADD delayed 5 job\r\n"), ":1\r\n");
+        assert_eq!(receive(&mut waiter), "*3\r\n$7\r\ndelayed\r\n$3\r\njob\r\n$1\r\n5\r\n");
+
+        send_blocking(&mut waiter, b"BZMPOP 0 2 other delayed MAX\r\n");
+        assert_eq!(send(&mut producer, b"ZADD delayed 1 a 2 b\r\n"), ":2\r\n");
+        assert_eq!(
+            receive(&mut waiter),
+            "*2\r\n$7\r\ndelayed\r\n*1\r\n*2\r\n$1\r\nb\r\n$1\r\n2\r\n"
+        );
+        assert_eq!(send(&mut producer, b"ZCARD delayed\r\n"), ":1\r\n");
+    }
+
+    #[test]
+    fn test_blocked_xread_is_served_by_xadd() {
+        let addr = start_server(10);
+        let mut reader = net::TcpStream::connect(addr).unwrap();
+        let mut producer = net::TcpStream::connect(addr).unwrap();
+
+        send(&mut producer, b"XADD events 1-0 n 1\r\n");
+        send_blocking(&mut reader, b"XREAD BLOCK 0 STREAMS events $\r\n");
+        assert_eq!(send(&mut producer, b"XADD events 2-0 n 2\r\n"), "$3\r\n2-0\r\n");
+        assert_eq!(
+            receive(&mut reader),
+            "*1\r\n*2\r\n$6\r\nevents\r\n*1\r\n*2\r\n$3\r\n2-0\r\n*2\r\n$1\r\nn\r\n$1\r\n2\r\n"
+        );
+
+        send(&mut producer, b"XGROUP CREATE events workers $\r\n");
+        send_blocking(&mut reader, b"XREADGROUP GROUP workers w1 BLOCK 0 STREAMS events >\r\n");
+        send(&mut producer, b"XADD events 3-0 n 3\r\n");
+        assert_eq!(
+            receive(&mut reader),
+            "*1\r\n*2\r\n$6\r\nevents\r\n*1\r\n*2\r\n$3\r\n3-0\r\n*2\r\n$1\r\nn\r\n$1\r\n3\r\n"
+        );
+        assert_eq!(send(&mut producer, b"XACK events workers 3-0\r\n"), ":1\r\n");
+    }
+
+    #[test]
+    fn test_blocked_clients_are_served_in_order() {
+        let addr = start_server(10);
+        let mut first = net::TcpStream::connect(addr).unwrap();
+        let mut second = net::TcpStream::connect(addr).unwrap();
+        let mut pusher = net::TcpStream::connect(addr).unwrap();
+
+        send_blocking(&mut first, b"BRPOP queue 0\r\n");
+        send_blocking(&mut second, b"BLMOVE queue done LEFT RIGHT 0\r\n");
+        send(&mut pusher, b"RPUSH queue a\r\n");
+        assert_eq!(receive(&mut first), "*2\r\n$5\r\nqueue\r\n$1\r\na\r\n");
+        send(&mut pusher, b"RPUSH queue b\r\n");
+        assert_eq!(receive(&mut second), "$1\r\nb\r\n");
+        assert_eq!(send(&mut pusher, b"LRANGE done 0 -1\r\n"), "*1\r\n$1\r\nb\r\n");
+    }
+
+    #[test]
+    fn test_push_in_pipeline_serves_blocked_client_first() {
+        let addr = start_server(10);
+        let mut waiter = net::TcpStream::connect(addr).unwrap();
+        let mut pusher = net::TcpStream::connect(addr).unwrap();
+
+        send_blocking(&mut waiter, b"BLPOP queue 0\r\n");
+        pusher.write_all(b"RPUSH queue a\r\nLPOP queue\r\n").unwrap();
+        let mut replies = [0; 9];
+        pusher.read_exact(&mut replies).unwrap();
+        assert_eq!(&replies, b":1\r\n$-1\r\n");
+        assert_eq!(receive(&mut waiter), "*2\r\n$5\r\nqueue\r\n$1\r\na\r\n");
+    }
+
+    #[test]
+    fn test_blocked_client_times_out() {
+        let addr = start_server(10);
+        let mut waiter = net::TcpStream::connect(addr).unwrap();
+
+        let started = Instant::now();
+        send_blocking(&mut waiter, b"BLMPOP 0.2 1 queue LEFT\r\n");
+        assert_eq!(receive(&mut waiter), "*-1\r\n");
+        assert!(started.elapsed() >= Duration::from_millis(200));
+
+        send(&mut waiter, b"HELLO 3\r\n");
+        send_blocking(&mut waiter, b"BLPOP queue 0.01\r\n");
+        assert_eq!(receive(&mut waiter), "_\r\n");
+    }
+
+    #[test]
+    fn test_input_waits_while_blocked() {
+        let addr = start_server(10);
+        let mut waiter = net::TcpStream::connect(addr).unwrap();
+        let mut pusher = net::TcpStream::connect(addr).unwrap();
+
+        send_blocking(&mut waiter, b"BLPOP queue 0\r\nPING\r\n");
+        send(&mut pusher, b"RPUSH queue a\r\n");
+        let expected = b"*2\r\n$5\r\nqueue\r\n$1\r\na\r\n+PONG\r\n";
+        let mut replies = vec![0; expected.len()];
+        waiter.read_exact(&mut replies).unwrap();
+        assert_eq!(replies, expected);
+    }
 }
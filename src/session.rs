@@ -0,0 +1,36 @@
+use std::time::Instant;
+
+use crate::{protocol::ProtocolVersion, request::Request};
+
+/// Per-connection state that commands can read and change, such as the
+/// protocol version negotiated through `HELLO`.
+#[derive(Debug, Default)]
+pub struct Session {
+    pub id: u64,
+    pub name: Option<Vec<u8>>,
+    pub protocol: ProtocolVersion,
+    /// Set by a blocking command that found nothing to serve, asking the
+    /// server to park the client instead of replying.
+    pub block: Option<Block>,
+}
+
+/// The keys a parked client waits on, and when it gives up if none of them
+/// receives data. A `deadline` of `None` waits forever.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub keys: Vec<Vec<u8>>,
+    pub deadline: Option<Instant>,
+    /// The command to run on wakeup when it isn't the one that blocked, such
+    /// as an `XREAD` whose `$` IDs must keep meaning the IDs that were last
+    /// when it blocked.
+    pub retry: Option<Request>,
+}
+
+impl Session {
+    pub fn new(id: u64) -> Self {
+        Self {
+            id,
+            ..Self::default()
+        }
+    }
+}
@@ -0,0 +1,628 @@
+use std::{cmp::Ordering, collections::HashMap, iter};
+
+use rand::Rng;
+
+const MAX_LEVEL: usize = 32;
+/// Chance of a node reaching each level above the first.
+const LEVEL_PROBABILITY: f64 = 0.25;
+/// Index of the sentinel node every level starts from.
+const HEAD: usize = 0;
+
+#[derive(Debug, Clone, Copy)]
+struct Level {
+    forward: Option<usize>,
+    /// How many nodes the forward link skips over, counting the one it
+    /// lands on. Summing spans along a search path gives a node's rank.
+    span: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    member: Vec<u8>,
+    score: f64,
+    backward: Option<usize>,
+    levels: Vec<Level>,
+}
+
+impl Node {
+    fn cmp(&self, score: f64, member: &[u8]) -> Ordering {
+        self.score
+            .total_cmp(&score)
+            .then_with(|| self.member.as_slice().cmp(member))
+    }
+}
+
+/// Members ordered by score and then by member, with the rank of any member
+/// found in O(log n). Nodes live in a vector and link to each other by
+/// index, and removed slots are reused.
+#[derive(Debug, Clone)]
+struct SkipList {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    tail: Option<usize>,
+    len: usize,
+    level: usize,
+}
+
+impl SkipList {
+    fn new() -> Self {
+        let head = Node {
+            member: Vec::new(),
+            score: 0.0,
+            backward: None,
+            levels: vec![Level { forward: None, span: 0 }; MAX_LEVEL],
+        };
+        Self {
+            nodes: vec![head],
+            free: Vec::new(),
+            tail: None,
+            len: 0,
+            level: 1,
+        }
+    }
+
+    fn forward(&self, node: usize, level: usize) -> Option<usize> {
+        self.nodes[node].levels[level].forward
+    }
+
+    fn random_level() -> usize {
+        let mut rng = rand::thread_rng();
+        let mut level = 1;
+        while level < MAX_LEVEL && rng.gen::<f64>() < LEVEL_PROBABILITY {
+            level += 1;
+        }
+        level
+    }
+
+    /// Finds, on every level, the last node ordered before `(score,
+    /// member)`, along with its rank.
+    fn predecessors(&self, score: f64, member: &[u8]) -> ([usize; MAX_LEVEL], [usize; MAX_LEVEL]) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            rank[i] = if i == self.level - 1 { 0 } else { rank[i + 1] };
+            while let Some(next) = self.forward(x, i) {
+                if self.nodes[next].cmp(score, member) != Ordering::Less {
+                    break;
+                }
+                rank[i] += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            update[i] = x;
+        }
+        (update, rank)
+    }
+
+    /// Adds a member that isn't in the list yet.
+    fn insert(&mut self, score: f64, member: Vec<u8>) {
+        let (mut update, mut rank) = self.predecessors(score, &member);
+        let level = Self::random_level();
+        if level > self.level {
+            for i in self.level..level {
+                rank[i] = 0;
+                update[i] = HEAD;
+                self.nodes[HEAD].levels[i].span = self.len;
+            }
+            self.level = level;
+        }
+
+        let node = Node {
+            member,
+            score,
+            backward: (update[0] != HEAD).then_some(update[0]),
+            levels: vec![Level { forward: None, span: 0 }; level],
+        };
+        let x = match self.free.pop() {
+            Some(slot) => {
+                self.nodes[slot] = node;
+                slot
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+
+        for i in 0..level {
+            let prev = update[i];
+            let skipped = rank[0] - rank[i];
+            self.nodes[x].levels[i].forward = self.nodes[prev].levels[i].forward;
+            self.nodes[x].levels[i].span = self.nodes[prev].levels[i].span - skipped;
+            self.nodes[prev].levels[i].forward = Some(x);
+            self.nodes[prev].levels[i].span = skipped + 1;
+        }
+        for (i, &prev) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[prev].levels[i].span += 1;
+        }
+        match self.forward(x, 0) {
+            Some(next) => self.nodes[next].backward = Some(x),
+            None => self.tail = Some(x),
+        }
+        self.len += 1;
+    }
+
+    /// Removes a member, returning whether it was in the list.
+    fn remove(&mut self, score: f64, member: &[u8]) -> bool {
+        let (update, _) = self.predecessors(score, member);
+        let Some(x) = self.forward(update[0], 0) else {
+            return false;
+        };
+        if self.nodes[x].cmp(score, member) != Ordering::Equal {
+            return false;
+        }
+
+        for (i, prev) in update.iter().enumerate().take(self.level) {
+            if self.forward(*prev, i) == Some(x) {
+                self.nodes[*prev].levels[i].span += self.nodes[x].levels[i].span;
+                self.nodes[*prev].levels[i].span -= 1;
+                self.nodes[*prev].levels[i].forward = self.nodes[x].levels[i].forward;
+            } else {
+                self.nodes[*prev].levels[i].span -= 1;
+            }
+        }
+        let backward = self.nodes[x].backward;
+        match self.forward(x, 0) {
+            Some(next) => self.nodes[next].backward = backward,
+            None => self.tail = backward,
+        }
+        while self.level > 1 && self.forward(HEAD, self.level - 1).is_none() {
+            self.level -= 1;
+        }
+
+        self.nodes[x].member = Vec::new();
+        self.nodes[x].levels = Vec::new();
+        self.free.push(x);
+        self.len -= 1;
+        true
+    }
+
+    /// The 0-based rank of a member, if it is in the list.
+    fn rank(&self, score: f64, member: &[u8]) -> Option<usize> {
+        let mut rank = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i) {
+                if self.nodes[next].cmp(score, member) == Ordering::Greater {
+                    break;
+                }
+                rank += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            if x != HEAD && self.nodes[x].cmp(score, member) == Ordering::Equal {
+                return Some(rank - 1);
+            }
+        }
+        None
+    }
+
+    /// The node at a 0-based rank.
+    fn by_rank(&self, rank: usize) -> Option<usize> {
+        let target = rank + 1;
+        let mut traversed = 0;
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i) {
+                if traversed + self.nodes[x].levels[i].span > target {
+                    break;
+                }
+                traversed += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            if traversed == target {
+                return Some(x);
+            }
+        }
+        None
+    }
+
+    /// The first node within a range, given whether a node is past its lower
+    /// end and before its upper end.
+    fn first_in(&self, range: &impl Range) -> Option<usize> {
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i) {
+                if range.above_min(&self.nodes[next]) {
+                    break;
+                }
+                x = next;
+            }
+        }
+        self.forward(x, 0)
+            .filter(|x| range.below_max(&self.nodes[*x]))
+    }
+
+    /// The last node within a range.
+    fn last_in(&self, range: &impl Range) -> Option<usize> {
+        let mut x = HEAD;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.forward(x, i) {
+                if !range.below_max(&self.nodes[next]) {
+                    break;
+                }
+                x = next;
+            }
+        }
+        (x != HEAD && range.above_min(&self.nodes[x])).then_some(x)
+    }
+
+    /// Walks the list from `start`, towards the tail or towards the head.
+    fn walk(&self, start: Option<usize>, reverse: bool) -> impl Iterator<Item = &Node> {
+        iter::successors(start, move |x| {
+            if reverse {
+                self.nodes[*x].backward
+            } else {
+                self.forward(*x, 0)
+            }
+        })
+        .map(|x| &self.nodes[x])
+    }
+}
+
+/// A range of members in sort order, which must be contiguous in the list.
+trait Range {
+    fn above_min(&self, node: &Node) -> bool;
+    fn below_max(&self, node: &Node) -> bool;
+}
+
+/// One end of a score range, as in `(1.5` or `+inf`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreBound {
+    pub value: f64,
+    pub exclusive: bool,
+}
+
+impl ScoreBound {
+    /// Parses a score bound, prefixed with `(` to exclude the value itself.
+    pub fn parse(arg: &[u8]) -> Option<ScoreBound> {
+        let (exclusive, value) = match arg.strip_prefix(b"(") {
+            Some(value) => (true, value),
+            None => (false, arg),
+        };
+        let value = parse_score(value)?;
+        Some(ScoreBound { value, exclusive })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreRange {
+    pub min: ScoreBound,
+    pub max: ScoreBound,
+}
+
+impl Range for ScoreRange {
+    fn above_min(&self, node: &Node) -> bool {
+        if self.min.exclusive {
+            node.score > self.min.value
+        } else {
+            node.score >= self.min.value
+        }
+    }
+
+    fn below_max(&self, node: &Node) -> bool {
+        if self.max.exclusive {
+            node.score < self.max.value
+        } else {
+            node.score <= self.max.value
+        }
+    }
+}
+
+/// One end of a lexicographical range, as in `[a`, `(a`, `-` or `+`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexBound {
+    Min,
+    Max,
+    Inclusive(Vec<u8>),
+    Exclusive(Vec<u8>),
+}
+
+impl LexBound {
+    pub fn parse(arg: &[u8]) -> Option<LexBound> {
+        match arg {
+            b"-" => Some(LexBound::Min),
+            b"+" => Some(LexBound::Max),
+            [b'[', value @ ..] => Some(LexBound::Inclusive(value.to_vec())),
+            [b'(', value @ ..] => Some(LexBound::Exclusive(value.to_vec())),
+            _ => None,
+        }
+    }
+}
+
+/// A range of members by name, meaningful when every member has the same
+/// score.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexRange {
+    pub min: LexBound,
+    pub max: LexBound,
+}
+
+impl Range for LexRange {
+    fn above_min(&self, node: &Node) -> bool {
+        match &self.min {
+            LexBound::Min => true,
+            LexBound::Max => false,
+            LexBound::Inclusive(value) => node.member >= *value,
+            LexBound::Exclusive(value) => node.member > *value,
+        }
+    }
+
+    fn below_max(&self, node: &Node) -> bool {
+        match &self.max {
+            LexBound::Min => false,
+            LexBound::Max => true,
+            LexBound::Inclusive(value) => node.member <= *value,
+            LexBound::Exclusive(value) => node.member < *value,
+        }
+    }
+}
+
+/// Parses a score, which unlike other floats may be `+inf` or `-inf`.
+pub fn parse_score(arg: &[u8]) -> Option<f64> {
+    let score: f64 = std::str::from_utf8(arg).ok()?.parse().ok()?;
+    (!score.is_nan()).then_some(score)
+}
+
+/// Members with a score each, kept in a skiplist for ordered access and in a
+/// map for finding the score of a member.
+#[derive(Debug, Clone)]
+pub struct SortedSet {
+    scores: HashMap<Vec<u8>, f64>,
+    list: SkipList,
+}
+
+impl Default for SortedSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PartialEq for SortedSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.scores == other.scores
+    }
+}
+
+// Scores are never NaN, so every score is equal to itself.
+impl Eq for SortedSet {}
+
+impl SortedSet {
+    pub fn new() -> Self {
+        Self {
+            scores: HashMap::new(),
+            list: SkipList::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Adds a member or changes its score, returning whether it is new.
+    pub fn insert(&mut self, member: Vec<u8>, score: f64) -> bool {
+        // Treat -0 as 0 so the two can't sort apart.
+        let score = if score == 0.0 { 0.0 } else { score };
+        let new = match self.scores.insert(member.clone(), score) {
+            Some(old) if old.total_cmp(&score) == Ordering::Equal => return false,
+            Some(old) => {
+                self.list.remove(old, &member);
+                false
+            }
+            None => true,
+        };
+        self.list.insert(score, member);
+        new
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self.scores.remove(member) {
+            Some(score) => self.list.remove(score, member),
+            None => false,
+        }
+    }
+
+    /// The 0-based position of a member in ascending order.
+    pub fn rank(&self, member: &[u8]) -> Option<usize> {
+        let score = self.score(member)?;
+        self.list.rank(score, member)
+    }
+
+    /// Members between two 0-based ranks, both included. With `reverse`,
+    /// ranks count from the highest score down.
+    pub fn range_by_rank(&self, start: usize, stop: usize, reverse: bool) -> Vec<(Vec<u8>, f64)> {
+        if start > stop || start >= self.len() {
+            return vec![];
+        }
+        let first = if reverse { self.len() - 1 - start } else { start };
+        self.list
+            .walk(self.list.by_rank(first), reverse)
+            .take(stop.min(self.len() - 1) - start + 1)
+            .map(|node| (node.member.clone(), node.score))
+            .collect()
+    }
+
+    pub fn range_by_score(
+        &self,
+        range: &ScoreRange,
+        reverse: bool,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> Vec<(Vec<u8>, f64)> {
+        self.range(range, reverse, offset, limit)
+    }
+
+    pub fn range_by_lex(
+        &self,
+        range: &LexRange,
+        reverse: bool,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> Vec<(Vec<u8>, f64)> {
+        self.range(range, reverse, offset, limit)
+    }
+
+    fn range(
+        &self,
+        range: &impl Range,
+        reverse: bool,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> Vec<(Vec<u8>, f64)> {
+        let start = if reverse {
+            self.list.last_in(range)
+        } else {
+            self.list.first_in(range)
+        };
+        self.list
+            .walk(start, reverse)
+            .take_while(|node| range.above_min(node) && range.below_max(node))
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|node| (node.member.clone(), node.score))
+            .collect()
+    }
+
+    /// Counts the members within a score range using their ranks, without
+    /// walking the range.
+    pub fn count_by_score(&self, range: &ScoreRange) -> usize {
+        let (Some(first), Some(last)) = (self.list.first_in(range), self.list.last_in(range)) else {
+            return 0;
+        };
+        let rank = |x: usize| {
+            let node = &self.list.nodes[x];
+            self.list.rank(node.score, &node.member).expect("node is in the list")
+        };
+        rank(last) + 1 - rank(first)
+    }
+
+    /// Removes and returns the member with the lowest score, or the highest
+    /// with `max`.
+    pub fn pop(&mut self, max: bool) -> Option<(Vec<u8>, f64)> {
+        let x = if max { self.list.tail? } else { self.list.forward(HEAD, 0)? };
+        let member = self.list.nodes[x].member.clone();
+        let score = self.list.nodes[x].score;
+        self.remove(&member);
+        Some((member, score))
+    }
+
+    /// Every member in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], f64)> {
+        self.list
+            .walk(self.list.forward(HEAD, 0), false)
+            .map(|node| (node.member.as_slice(), node.score))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(i: usize) -> Vec<u8> {
+        format!("m{:04}", i).into_bytes()
+    }
+
+    fn score_range(min: &str, max: &str) -> ScoreRange {
+        ScoreRange {
+            min: ScoreBound::parse(min.as_bytes()).unwrap(),
+            max: ScoreBound::parse(max.as_bytes()).unwrap(),
+        }
+    }
+
+    /// Checks every rank against a plain sorted copy of the members.
+    fn assert_consistent(set: &SortedSet) {
+        let mut expected: Vec<(Vec<u8>, f64)> =
+            set.scores.iter().map(|(member, score)| (member.clone(), *score)).collect();
+        expected.sort_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+
+        let walked: Vec<(Vec<u8>, f64)> = set.iter().map(|(m, s)| (m.to_vec(), s)).collect();
+        assert_eq!(walked, expected);
+        for (rank, (member, _)) in expected.iter().enumerate() {
+            assert_eq!(set.rank(member), Some(rank));
+            assert_eq!(set.range_by_rank(rank, rank, false)[0].0, *member);
+        }
+        let reversed: Vec<_> = set.range_by_rank(0, usize::MAX, true);
+        assert_eq!(reversed, expected.iter().rev().cloned().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_ranks_stay_consistent() {
+        let mut set = SortedSet::new();
+        let mut rng = rand::thread_rng();
+        for i in 0..500 {
+            set.insert(member(i), rng.gen_range(0..50) as f64);
+        }
+        assert_eq!(set.len(), 500);
+        assert_consistent(&set);
+
+        for i in (0..500).step_by(3) {
+            assert!(set.remove(&member(i)));
+        }
+        assert!(!set.remove(&member(0)));
+        for i in (1..500).step_by(7) {
+            set.insert(member(i), -(i as f64));
+        }
+        assert_consistent(&set);
+
+        while let Some((member, _)) = set.pop(false) {
+            assert_eq!(set.rank(&member), None);
+        }
+        assert!(set.is_empty());
+        assert_eq!(set.list.len, 0);
+    }
+
+    #[test]
+    fn test_insert_reports_new_members() {
+        let mut set = SortedSet::new();
+        assert!(set.insert(b"a".to_vec(), 1.0));
+        assert!(!set.insert(b"a".to_vec(), 2.0));
+        assert!(!set.insert(b"a".to_vec(), 2.0));
+        assert_eq!(set.score(b"a"), Some(2.0));
+        assert_eq!(set.len(), 1);
+        assert_eq!(set.list.len, 1);
+    }
+
+    #[test]
+    fn test_score_ranges() {
+        let mut set = SortedSet::new();
+        for i in 0..10 {
+            set.insert(member(i), i as f64);
+        }
+        let members = |items: Vec<(Vec<u8>, f64)>| items.into_iter().map(|(_, s)| s as usize).collect::<Vec<_>>();
+
+        assert_eq!(members(set.range_by_score(&score_range("2", "4"), false, 0, None)), [2, 3, 4]);
+        assert_eq!(members(set.range_by_score(&score_range("(2", "(4"), false, 0, None)), [3]);
+        assert_eq!(members(set.range_by_score(&score_range("-inf", "+inf"), true, 1, Some(2))), [8, 7]);
+        assert_eq!(members(set.range_by_score(&score_range("5", "3"), false, 0, None)), Vec::<usize>::new());
+        assert_eq!(set.count_by_score(&score_range("2", "(7")), 5);
+        assert_eq!(set.count_by_score(&score_range("(9", "+inf")), 0);
+        assert_eq!(set.count_by_score(&score_range("-inf", "+inf")), 10);
+    }
+
+    #[test]
+    fn test_lex_ranges() {
+        let mut set = SortedSet::new();
+        for member in ["a", "b", "c", "d", "e"] {
+            set.insert(member.as_bytes().to_vec(), 0.0);
+        }
+        let range = |min: &str, max: &str| LexRange {
+            min: LexBound::parse(min.as_bytes()).unwrap(),
+            max: LexBound::parse(max.as_bytes()).unwrap(),
+        };
+        let members = |items: Vec<(Vec<u8>, f64)>| {
+            items.into_iter().map(|(m, _)| String::from_utf8(m).unwrap()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(members(set.range_by_lex(&range("[b", "(d"), false, 0, None)), ["b", "c"]);
+        assert_eq!(members(set.range_by_lex(&range("-", "+"), true, 0, Some(2))), ["e", "d"]);
+        assert_eq!(members(set.range_by_lex(&range("(e", "+"), false, 0, None)), Vec::<String>::new());
+        assert_eq!(LexBound::parse(b"b"), None);
+    }
+}
@@ -0,0 +1,523 @@
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    fmt,
+};
+
+/// Identifies a stream entry: the Unix time in milliseconds it was added at,
+/// and a sequence number telling apart entries added in the same
+/// millisecond.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    pub fn new(ms: u64, seq: u64) -> Self {
+        Self { ms, seq }
+    }
+
+    /// Parses `ms-seq`, or a bare `ms` that takes `default_seq` as its
+    /// sequence number.
+    pub fn parse(arg: &[u8], default_seq: u64) -> Option<StreamId> {
+        let arg = std::str::from_utf8(arg).ok()?;
+        let (ms, seq) = match arg.split_once('-') {
+            Some((ms, seq)) => (ms, parse_u64(seq)?),
+            None => (arg, default_seq),
+        };
+        Some(StreamId::new(parse_u64(ms)?, seq))
+    }
+
+    /// The smallest ID greater than this one, if any.
+    pub fn next(self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_add(1)?, 0)),
+        }
+    }
+
+    /// The greatest ID smaller than this one, if any.
+    pub fn prev(self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_sub(1)?, u64::MAX)),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+fn parse_u64(s: &str) -> Option<u64> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// An entry's fields and values, alternating.
+pub type Fields = Vec<Vec<u8>>;
+
+/// An append-only log of entries ordered by ID, along with the consumer
+/// groups reading it. Unlike other collections a stream stays around once
+/// empty, as its last ID and groups still matter.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, Fields>,
+    /// The ID of the newest entry ever added, which new IDs must exceed
+    /// even after that entry is deleted.
+    pub last_id: StreamId,
+    pub max_deleted_id: StreamId,
+    pub entries_added: u64,
+    pub groups: BTreeMap<Vec<u8>, ConsumerGroup>,
+}
+
+impl Stream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The ID an entry added at Unix time `ms` gets when none is given: the
+    /// time itself, or the last ID's time if the clock went backwards, with
+    /// the next free sequence number. `None` once IDs are exhausted.
+    pub fn next_id(&self, ms: u64) -> Option<StreamId> {
+        if ms > self.last_id.ms {
+            Some(StreamId::new(ms, 0))
+        } else {
+            self.last_id.next()
+        }
+    }
+
+    /// Appends an entry. The caller makes sure `id` is greater than the last
+    /// ID.
+    pub fn add(&mut self, id: StreamId, fields: Fields) {
+        debug_assert!(id > self.last_id);
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        self.entries_added += 1;
+    }
+
+    pub fn get(&self, id: &StreamId) -> Option<&Fields> {
+        self.entries.get(id)
+    }
+
+    pub fn first(&self) -> Option<(StreamId, &Fields)> {
+        self.entries.first_key_value().map(|(id, fields)| (*id, fields))
+    }
+
+    pub fn last(&self) -> Option<(StreamId, &Fields)> {
+        self.entries.last_key_value().map(|(id, fields)| (*id, fields))
+    }
+
+    /// Up to `count` entries with IDs between `start` and `end` inclusive,
+    /// newest first if `reverse` is set.
+    pub fn range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+        reverse: bool,
+        count: Option<usize>,
+    ) -> Vec<(StreamId, Fields)> {
+        if start > end {
+            return vec![];
+        }
+        let entries = self.entries.range(start..=end);
+        let entries: Box<dyn Iterator<Item = _>> = if reverse {
+            Box::new(entries.rev())
+        } else {
+            Box::new(entries)
+        };
+        entries
+            .take(count.unwrap_or(usize::MAX))
+            .map(|(id, fields)| (*id, fields.clone()))
+            .collect()
+    }
+
+    /// Deletes an entry, returning whether it existed.
+    pub fn delete(&mut self, id: &StreamId) -> bool {
+        if self.entries.remove(id).is_none() {
+            return false;
+        }
+        self.max_deleted_id = self.max_deleted_id.max(*id);
+        true
+    }
+
+    /// Evicts the oldest entries until at most `max_len` are left, evicting
+    /// no more than `limit`. Returns how many were evicted.
+    pub fn trim_to_len(&mut self, max_len: usize, limit: Option<usize>) -> usize {
+        let excess = self.len().saturating_sub(max_len);
+        let count = limit.map_or(excess, |limit| excess.min(limit));
+        for _ in 0..count {
+            if let Some((id, _)) = self.entries.pop_first() {
+                self.max_deleted_id = self.max_deleted_id.max(id);
+            }
+        }
+        count
+    }
+
+    /// Evicts entries with IDs below `min_id`, evicting no more than
+    /// `limit`. Returns how many were evicted.
+    pub fn trim_to_min_id(&mut self, min_id: StreamId, limit: Option<usize>) -> usize {
+        let mut count = 0;
+        while limit.is_none_or(|limit| count < limit) {
+            match self.entries.first_entry() {
+                Some(entry) if *entry.key() < min_id => {
+                    let (id, _) = entry.remove_entry();
+                    self.max_deleted_id = self.max_deleted_id.max(id);
+                    count += 1;
+                }
+                _ => break,
+            }
+        }
+        count
+    }
+
+    /// Hands `consumer` up to `count` entries that `group` hasn't delivered
+    /// yet, tracking them as pending unless `noack` is set. `None` if the
+    /// group doesn't exist.
+    pub fn deliver(
+        &mut self,
+        group: &[u8],
+        consumer: &[u8],
+        count: Option<usize>,
+        noack: bool,
+        now: u64,
+    ) -> Option<Vec<(StreamId, Fields)>> {
+        let group = self.groups.get_mut(group)?;
+        let delivered: Vec<_> = match group.last_delivered.next() {
+            Some(start) => self
+                .entries
+                .range(start..)
+                .take(count.unwrap_or(usize::MAX))
+                .map(|(id, fields)| (*id, fields.clone()))
+                .collect(),
+            None => vec![],
+        };
+
+        let seen = group.consumer(consumer, now);
+        let Some((last, _)) = delivered.last() else {
+            return Some(delivered);
+        };
+        seen.active_at = Some(now);
+        group.last_delivered = *last;
+        if !noack {
+            for (id, _) in &delivered {
+                let entry = PendingEntry {
+                    consumer: consumer.to_vec(),
+                    delivered_at: now,
+                    delivery_count: 1,
+                };
+                group.pending.insert(*id, entry);
+            }
+        }
+        Some(delivered)
+    }
+
+    /// Transfers the pending entry `id` of `group` to `consumer` if it was
+    /// idle long enough. `None` if the group doesn't exist.
+    pub fn claim(
+        &mut self,
+        group: &[u8],
+        consumer: &[u8],
+        id: StreamId,
+        options: &ClaimOptions,
+        now: u64,
+    ) -> Option<Claim> {
+        let group = self.groups.get_mut(group)?;
+        let Some(fields) = self.entries.get(&id) else {
+            let claim = match group.pending.remove(&id) {
+                Some(_) => Claim::Deleted,
+                None => Claim::Skipped,
+            };
+            return Some(claim);
+        };
+        let entry = match group.pending.entry(id) {
+            Entry::Occupied(entry) => {
+                let entry = entry.into_mut();
+                if now.saturating_sub(entry.delivered_at) < options.min_idle {
+                    return Some(Claim::Skipped);
+                }
+                entry
+            }
+            Entry::Vacant(entry) if options.force => entry.insert(PendingEntry {
+                consumer: consumer.to_vec(),
+                delivered_at: options.delivered_at,
+                delivery_count: 0,
+            }),
+            Entry::Vacant(_) => return Some(Claim::Skipped),
+        };
+
+        entry.consumer = consumer.to_vec();
+        entry.delivered_at = options.delivered_at;
+        match options.retry_count {
+            Some(count) => entry.delivery_count = count,
+            None if !options.just_id => entry.delivery_count += 1,
+            None => {}
+        }
+        group.consumer(consumer, now).active_at = Some(now);
+        Some(Claim::Claimed(fields.clone()))
+    }
+}
+
+/// How `XCLAIM` and `XAUTOCLAIM` treat the entries they claim.
+#[derive(Debug, Clone, Default)]
+pub struct ClaimOptions {
+    /// Entries delivered fewer than this many milliseconds ago are skipped.
+    pub min_idle: u64,
+    /// The delivery time claimed entries get, as a Unix time in
+    /// milliseconds.
+    pub delivered_at: u64,
+    /// Sets the delivery count of claimed entries instead of incrementing
+    /// it.
+    pub retry_count: Option<u64>,
+    /// Claims entries that are in the stream but aren't pending.
+    pub force: bool,
+    /// Leaves delivery counts alone.
+    pub just_id: bool,
+}
+
+/// What claiming a single entry did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Claim {
+    Claimed(Fields),
+    /// The entry was pending but no longer in the stream, so it was dropped
+    /// from the pending entries.
+    Deleted,
+    Skipped,
+}
+
+/// A named cursor into a stream shared by consumers, which tracks what
+/// each of them was delivered until they acknowledge it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConsumerGroup {
+    pub last_delivered: StreamId,
+    /// The pending entries list: entries delivered but not acknowledged.
+    pub pending: BTreeMap<StreamId, PendingEntry>,
+    pub consumers: BTreeMap<Vec<u8>, Consumer>,
+}
+
+/// An entry delivered to a consumer and awaiting acknowledgement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingEntry {
+    pub consumer: Vec<u8>,
+    /// Unix time in milliseconds of the last delivery.
+    pub delivered_at: u64,
+    pub delivery_count: u64,
+}
+
+/// Times are Unix times in milliseconds.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Consumer {
+    /// When the consumer last tried to read or claim.
+    pub seen_at: u64,
+    /// When the consumer last read or claimed something, if ever.
+    pub active_at: Option<u64>,
+}
+
+impl ConsumerGroup {
+    pub fn new(last_delivered: StreamId) -> Self {
+        Self {
+            last_delivered,
+            ..Self::default()
+        }
+    }
+
+    /// Looks up a consumer, creating it if needed, and records that it was
+    /// seen at `now`.
+    pub fn consumer(&mut self, name: &[u8], now: u64) -> &mut Consumer {
+        let consumer = self.consumers.entry(name.to_vec()).or_default();
+        consumer.seen_at = now;
+        consumer
+    }
+
+    /// Creates a consumer, returning `false` if it already exists.
+    pub fn create_consumer(&mut self, name: &[u8], now: u64) -> bool {
+        match self.consumers.entry(name.to_vec()) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(Consumer {
+                    seen_at: now,
+                    active_at: None,
+                });
+                true
+            }
+        }
+    }
+
+    /// Deletes a consumer along with its pending entries, returning how many
+    /// of those it had.
+    pub fn delete_consumer(&mut self, name: &[u8]) -> usize {
+        if self.consumers.remove(name).is_none() {
+            return 0;
+        }
+        let before = self.pending.len();
+        self.pending.retain(|_, entry| entry.consumer != name);
+        before - self.pending.len()
+    }
+
+    /// The pending entries delivered to `consumer`.
+    pub fn pending_of<'a>(
+        &'a self,
+        consumer: &'a [u8],
+    ) -> impl Iterator<Item = (&'a StreamId, &'a PendingEntry)> {
+        self.pending
+            .iter()
+            .filter(move |(_, entry)| entry.consumer == consumer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(value: &str) -> Fields {
+        vec![b"f".to_vec(), value.as_bytes().to_vec()]
+    }
+
+    #[test]
+    fn test_parse_ids() {
+        assert_eq!(StreamId::parse(b"5-3", 0), Some(StreamId::new(5, 3)));
+        assert_eq!(StreamId::parse(b"5", 0), Some(StreamId::new(5, 0)));
+        assert_eq!(StreamId::parse(b"5", u64::MAX), Some(StreamId::new(5, u64::MAX)));
+        assert_eq!(StreamId::parse(b"5-", 0), None);
+        assert_eq!(StreamId::parse(b"-5", 0), None);
+        assert_eq!(StreamId::parse(b"+5-1", 0), None);
+        assert_eq!(StreamId::parse(b"18446744073709551616", 0), None);
+        assert_eq!(StreamId::new(5, 3).to_string(), "5-3");
+
+        assert_eq!(StreamId::new(5, u64::MAX).next(), Some(StreamId::new(6, 0)));
+        assert_eq!(StreamId::MAX.next(), None);
+        assert_eq!(StreamId::new(5, 0).prev(), Some(StreamId::new(4, u64::MAX)));
+        assert_eq!(StreamId::MIN.prev(), None);
+    }
+
+    #[test]
+    fn test_next_id_never_goes_backwards() {
+        let mut stream = Stream::new();
+        assert_eq!(stream.next_id(10), Some(StreamId::new(10, 0)));
+        stream.add(StreamId::new(10, 0), fields("a"));
+        assert_eq!(stream.next_id(10), Some(StreamId::new(10, 1)));
+        assert_eq!(stream.next_id(3), Some(StreamId::new(10, 1)));
+        assert_eq!(stream.next_id(11), Some(StreamId::new(11, 0)));
+
+        stream.add(StreamId::MAX, fields("b"));
+        assert_eq!(stream.next_id(u64::MAX), None);
+    }
+
+    #[test]
+    fn test_range_delete_and_trim() {
+        let mut stream = Stream::new();
+        for ms in 1..=5 {
+            stream.add(StreamId::new(ms, 0), fields(&ms.to_string()));
+        }
+        let ids = |entries: Vec<(StreamId, Fields)>| {
+            entries.into_iter().map(|(id, _)| id.ms).collect::<Vec<_>>()
+        };
+        let (two, four) = (StreamId::new(2, 0), StreamId::new(4, 0));
+        assert_eq!(ids(stream.range(two, four, false, None)), [2, 3, 4]);
+        assert_eq!(ids(stream.range(StreamId::MIN, StreamId::MAX, true, Some(2))), [5, 4]);
+        assert_eq!(ids(stream.range(four, two, false, None)), []);
+
+        assert!(stream.delete(&StreamId::new(3, 0)));
+        assert!(!stream.delete(&StreamId::new(3, 0)));
+        assert_eq!(stream.max_deleted_id, StreamId::new(3, 0));
+
+        assert_eq!(stream.trim_to_len(2, Some(1)), 1);
+        assert_eq!(stream.trim_to_len(2, None), 1);
+        assert_eq!(ids(stream.range(StreamId::MIN, StreamId::MAX, false, None)), [4, 5]);
+        assert_eq!(stream.max_deleted_id, StreamId::new(3, 0));
+        assert_eq!(stream.trim_to_min_id(StreamId::new(5, 0), None), 1);
+        assert_eq!(stream.max_deleted_id, StreamId::new(4, 0));
+        assert_eq!(stream.len(), 1);
+        assert_eq!(stream.last_id, StreamId::new(5, 0));
+        assert_eq!(stream.entries_added, 5);
+    }
+
+    #[test]
+    fn test_deliver_and_claim() {
+        let mut stream = Stream::new();
+        for ms in 1..=3 {
+            stream.add(StreamId::new(ms, 0), fields(&ms.to_string()));
+        }
+        stream.groups.insert(b"g".to_vec(), ConsumerGroup::new(StreamId::MIN));
+        assert_eq!(stream.deliver(b"missing", b"alice", None, false, 100), None);
+
+        let delivered = stream.deliver(b"g", b"alice", Some(2), false, 100).unwrap();
+        assert_eq!(delivered.len(), 2);
+        let delivered = stream.deliver(b"g", b"bob", None, true, 100).unwrap();
+        assert_eq!(delivered, [(StreamId::new(3, 0), fields("3"))]);
+        assert_eq!(stream.deliver(b"g", b"bob", None, false, 100), Some(vec![]));
+        let group = &stream.groups[b"g".as_slice()];
+        assert_eq!(group.last_delivered, StreamId::new(3, 0));
+        assert_eq!(group.pending.len(), 2);
+        assert_eq!(group.consumers[b"bob".as_slice()].active_at, Some(100));
+
+        let options = ClaimOptions {
+            min_idle: 50,
+            delivered_at: 120,
+            ..ClaimOptions::default()
+        };
+        let first = StreamId::new(1, 0);
+        assert_eq!(stream.claim(b"g", b"bob", first, &options, 120), Some(Claim::Skipped));
+        assert_eq!(
+            stream.claim(b"g", b"bob", first, &options, 150),
+            Some(Claim::Claimed(fields("1")))
+        );
+        let entry = &stream.groups[b"g".as_slice()].pending[&first];
+        assert_eq!((entry.consumer.as_slice(), entry.delivery_count), (b"bob".as_slice(), 2));
+
+        let third = StreamId::new(3, 0);
+        assert_eq!(stream.claim(b"g", b"bob", third, &options, 150), Some(Claim::Skipped));
+        let forced = ClaimOptions {
+            force: true,
+            ..options.clone()
+        };
+        assert_eq!(
+            stream.claim(b"g", b"bob", third, &forced, 150),
+            Some(Claim::Claimed(fields("3")))
+        );
+
+        stream.delete(&StreamId::new(2, 0));
+        let second = StreamId::new(2, 0);
+        assert_eq!(stream.claim(b"g", b"bob", second, &options, 500), Some(Claim::Deleted));
+        assert_eq!(stream.groups[b"g".as_slice()].pending.len(), 2);
+    }
+
+    #[test]
+    fn test_consumers() {
+        let mut group = ConsumerGroup::new(StreamId::MIN);
+        assert!(group.create_consumer(b"alice", 1));
+        assert!(!group.create_consumer(b"alice", 2));
+        group.consumer(b"bob", 3);
+        for (ms, consumer) in [(1, "alice"), (2, "bob"), (3, "alice")] {
+            let entry = PendingEntry {
+                consumer: consumer.as_bytes().to_vec(),
+                delivered_at: 0,
+                delivery_count: 1,
+            };
+            group.pending.insert(StreamId::new(ms, 0), entry);
+        }
+        assert_eq!(group.pending_of(b"alice").count(), 2);
+        assert_eq!(group.delete_consumer(b"alice"), 2);
+        assert_eq!(group.delete_consumer(b"alice"), 0);
+        assert_eq!(group.pending.len(), 1);
+        assert_eq!(group.consumers.len(), 1);
+    }
+}